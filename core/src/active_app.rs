@@ -0,0 +1,119 @@
+//! The application in front on X11, as named by the window manager's
+//! `_NET_ACTIVE_WINDOW`.
+
+use crate::error::{Error, Result};
+use crate::exclusions::AppInfo;
+use std::fs;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _, Window};
+use x11rb::rust_connection::RustConnection;
+use x11rb::NONE;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_PID,
+        WM_CLASS,
+    }
+}
+
+fn x11_error(e: impl std::fmt::Display) -> Error {
+    Error::Clipboard(e.to_string())
+}
+
+/// Looks up the application of the active window.
+///
+/// Its name is the class in `WM_CLASS`, such as `firefox`, which is also what
+/// [`XTestInjector`](crate::paste::XTestInjector) activates windows by. The
+/// process name behind `_NET_WM_PID` takes the place of a bundle ID, so either
+/// can be excluded.
+pub struct X11ActiveApp {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+}
+
+impl X11ActiveApp {
+    /// Connects to the display named by `$DISPLAY`.
+    pub fn new() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(x11_error)?;
+        let root = conn.setup().roots[screen_num].root;
+        let atoms = Atoms::new(&conn)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+
+        Ok(Self { conn, root, atoms })
+    }
+
+    /// Returns an empty [`AppInfo`] if no window is active.
+    pub fn current(&self) -> Result<AppInfo> {
+        let Some(window) = self.active_window()? else {
+            return Ok(AppInfo::default());
+        };
+        let process = self.pid(window)?.and_then(process_name);
+
+        Ok(match self.class(window)? {
+            Some(class) => AppInfo::new(class, process),
+            None => AppInfo::new(process.unwrap_or_default(), None),
+        })
+    }
+
+    fn active_window(&self) -> Result<Option<Window>> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                self.root,
+                self.atoms._NET_ACTIVE_WINDOW,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(reply
+            .value32()
+            .and_then(|mut windows| windows.next())
+            .filter(|&window| window != NONE))
+    }
+
+    /// `WM_CLASS` holds the instance and then the class, each ending in a
+    /// null byte.
+    fn class(&self, window: Window) -> Result<Option<String>> {
+        let reply = self
+            .conn
+            .get_property(false, window, self.atoms.WM_CLASS, AtomEnum::STRING, 0, 256)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(reply
+            .value
+            .split(|&b| b == 0)
+            .rfind(|part| !part.is_empty())
+            .map(|class| String::from_utf8_lossy(class).into_owned()))
+    }
+
+    fn pid(&self, window: Window) -> Result<Option<u32>> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms._NET_WM_PID,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(reply.value32().and_then(|mut pids| pids.next()))
+    }
+}
+
+fn process_name(pid: u32) -> Option<String> {
+    let name = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(name.trim().to_string()).filter(|name| !name.is_empty())
+}
@@ -0,0 +1,498 @@
+//! Content-addressed storage for image bytes.
+//!
+//! Blobs are stored once on disk under their SHA-256 hash, so identical
+//! images copied several times share a single file. The store keeps a
+//! reference count per hash and deletes a blob when its last history entry
+//! goes away.
+//!
+//! Downscaled thumbnails for the popup are generated on first request and
+//! cached next to the blobs until the blob itself is deleted.
+//!
+//! When the history is encrypted, blobs and thumbnails are sealed on disk and
+//! start with [`SEALED_MAGIC`]. Hashes are always of the plaintext.
+
+use crate::encryption::Protection;
+use crate::error::{Error, Result};
+use crate::history::{Entry, HistoryDiff};
+use crate::item::{decode_png_data_url, HistoryItem, ImageRef};
+use crate::store::HistoryStore;
+use image::ImageFormat;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const BLOB_DIR: &str = "blobs";
+const THUMBNAIL_DIR: &str = "thumbnails";
+/// Longest edge of a thumbnail, in pixels. The popup is 250pt wide, so this
+/// stays sharp on Retina displays.
+pub const THUMBNAIL_MAX_SIZE: u32 = 500;
+const SEALED_MAGIC: &[u8] = b"MACOPY-SEALED-1\n";
+
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::with_capacity(64), |mut hash, byte| {
+            let _ = write!(hash, "{:02x}", byte);
+            hash
+        })
+}
+
+pub(crate) fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+pub struct BlobStore {
+    /// `None` for a [`disabled`](Self::disabled) store.
+    dir: Option<PathBuf>,
+    refs: Mutex<HashMap<String, usize>>,
+    protection: Mutex<Protection>,
+}
+
+impl BlobStore {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: Some(dir),
+            refs: Mutex::new(HashMap::new()),
+            protection: Mutex::new(Protection::default()),
+        })
+    }
+
+    /// A store that keeps nothing, for when no blob directory can be opened.
+    /// Writing and reading images fail, so the history records no images,
+    /// while releasing and syncing references succeed without doing anything.
+    pub fn disabled() -> Self {
+        Self {
+            dir: None,
+            refs: Mutex::new(HashMap::new()),
+            protection: Mutex::new(Protection::default()),
+        }
+    }
+
+    fn dir(&self) -> Result<&Path> {
+        self.dir
+            .as_deref()
+            .ok_or_else(|| Error::Blob("image history is disabled".to_string()))
+    }
+
+    fn thumbnail_dir(&self) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(THUMBNAIL_DIR))
+    }
+
+    /// Sets how blobs are protected on disk without rewriting existing ones.
+    pub fn set_protection(&self, protection: Protection) {
+        *self.protection.lock().unwrap() = protection;
+    }
+
+    /// Rewrites every blob under `protection` and switches to it. Cached
+    /// thumbnails are dropped and regenerated on demand.
+    ///
+    /// If a blob cannot be rewritten, the ones already done are put back
+    /// under the current key. Blobs are opened with either key, so an
+    /// interrupted rekey can be run again or rolled back.
+    pub fn rekey(&self, protection: Protection) -> Result<()> {
+        let mut current = self.protection.lock().unwrap();
+        let files = self.blob_files()?;
+        if let Err(e) = reseal_all(&files, &[&current, &protection], &protection) {
+            if let Err(e) = reseal_all(&files, &[&protection, &current], &current) {
+                log::error!("Failed to restore blobs after an interrupted rekey: {}", e);
+            }
+            return Err(e);
+        }
+
+        if let Some(thumbnail_dir) = self.thumbnail_dir().filter(|dir| dir.is_dir()) {
+            fs::remove_dir_all(thumbnail_dir)?;
+        }
+
+        *current = protection;
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let bytes = fs::read(path)?;
+        unseal(&self.protection.lock().unwrap(), bytes)
+    }
+
+    fn write(&self, path: &Path, bytes: &[u8]) -> Result<()> {
+        let bytes = seal(&self.protection.lock().unwrap(), bytes)?;
+        write_atomic(path, &bytes)
+    }
+
+    /// Blobs are sharded by the first two hex digits of their hash.
+    pub fn path(&self, hash: &str) -> Result<PathBuf> {
+        if !is_valid_hash(hash) {
+            return Err(Error::Blob(format!("invalid blob hash {}", hash)));
+        }
+        Ok(self.dir()?.join(&hash[..2]).join(&hash[2..]))
+    }
+
+    /// Writes `bytes` unless a blob with the same hash already exists.
+    /// This does not add a reference.
+    pub fn put(&self, bytes: &[u8]) -> Result<String> {
+        let hash = content_hash(bytes);
+        let path = self.path(&hash)?;
+
+        if !path.exists() {
+            self.write(&path, bytes)?;
+        }
+
+        Ok(hash)
+    }
+
+    pub fn get(&self, hash: &str) -> Result<Vec<u8>> {
+        self.read(&self.path(hash)?)
+    }
+
+    fn thumbnail_path(&self, hash: &str) -> Result<PathBuf> {
+        if !is_valid_hash(hash) {
+            return Err(Error::Blob(format!("invalid blob hash {}", hash)));
+        }
+        Ok(self
+            .dir()?
+            .join(THUMBNAIL_DIR)
+            .join(format!("{}.png", hash)))
+    }
+
+    /// Returns a PNG no larger than [`THUMBNAIL_MAX_SIZE`] on either side.
+    /// Images that already fit are returned as is.
+    pub fn thumbnail(&self, hash: &str) -> Result<Vec<u8>> {
+        let path = self.thumbnail_path(hash)?;
+        if let Ok(bytes) = self.read(&path) {
+            return Ok(bytes);
+        }
+
+        let original = self.get(hash)?;
+        let image = image::load_from_memory_with_format(&original, ImageFormat::Png)?;
+        if image.width() <= THUMBNAIL_MAX_SIZE && image.height() <= THUMBNAIL_MAX_SIZE {
+            return Ok(original);
+        }
+
+        let mut bytes = Cursor::new(Vec::new());
+        image
+            .thumbnail(THUMBNAIL_MAX_SIZE, THUMBNAIL_MAX_SIZE)
+            .write_to(&mut bytes, ImageFormat::Png)?;
+        let bytes = bytes.into_inner();
+        self.write(&path, &bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn ref_count(&self, hash: &str) -> usize {
+        self.refs.lock().unwrap().get(hash).copied().unwrap_or(0)
+    }
+
+    /// Updates reference counts for the entries a history change added and
+    /// removed, deleting blobs that are no longer referenced.
+    pub fn apply(&self, diff: &HistoryDiff) -> Result<()> {
+        for entry in &diff.inserted {
+            if let Some(image) = blob_ref(&entry.item) {
+                *self
+                    .refs
+                    .lock()
+                    .unwrap()
+                    .entry(image.hash.clone())
+                    .or_insert(0) += 1;
+            }
+        }
+
+        for entry in &diff.removed {
+            if let Some(image) = blob_ref(&entry.item) {
+                self.release(&image.hash)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes the blob behind `item` if no entry references it, e.g. after
+    /// the history declined to record it.
+    pub fn discard_unreferenced(&self, item: &HistoryItem) -> Result<()> {
+        match blob_ref(item) {
+            Some(image) if self.ref_count(&image.hash) == 0 => self.delete(&image.hash),
+            _ => Ok(()),
+        }
+    }
+
+    fn release(&self, hash: &str) -> Result<()> {
+        let mut refs = self.refs.lock().unwrap();
+        let remaining = match refs.get_mut(hash) {
+            Some(count) => {
+                *count = count.saturating_sub(1);
+                *count
+            }
+            None => 0,
+        };
+
+        if remaining == 0 {
+            refs.remove(hash);
+            drop(refs);
+            self.delete(hash)?;
+        }
+        Ok(())
+    }
+
+    fn delete(&self, hash: &str) -> Result<()> {
+        if self.dir.is_none() {
+            return Ok(());
+        }
+        remove_if_exists(&self.thumbnail_path(hash)?)?;
+        remove_if_exists(&self.path(hash)?)
+    }
+
+    /// Rebuilds the reference counts from the full history and deletes every
+    /// blob on disk that no entry references. Returns the number of deleted
+    /// blobs.
+    pub fn sync_refs(&self, entries: &[Entry]) -> Result<usize> {
+        let mut refs = HashMap::new();
+        for image in entries.iter().filter_map(|entry| blob_ref(&entry.item)) {
+            *refs.entry(image.hash.clone()).or_insert(0) += 1;
+        }
+
+        let mut deleted = 0;
+        for (hash, path) in self.blob_files()? {
+            if !refs.contains_key(&hash) {
+                fs::remove_file(path)?;
+                deleted += 1;
+            }
+        }
+
+        if let Some(thumbnail_dir) = self.thumbnail_dir().filter(|dir| dir.is_dir()) {
+            for thumbnail in fs::read_dir(thumbnail_dir)? {
+                let path = thumbnail?.path();
+                let hash = path.file_stem().unwrap_or_default().to_string_lossy();
+                if !refs.contains_key(hash.as_ref()) {
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+
+        *self.refs.lock().unwrap() = refs;
+        Ok(deleted)
+    }
+
+    /// Every blob on disk with its hash.
+    fn blob_files(&self) -> Result<Vec<(String, PathBuf)>> {
+        let mut files = Vec::new();
+        let Some(dir) = &self.dir else {
+            return Ok(files);
+        };
+        for shard in fs::read_dir(dir)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() || shard.file_name() == THUMBNAIL_DIR {
+                continue;
+            }
+            let prefix = shard.file_name().to_string_lossy().into_owned();
+
+            for blob in fs::read_dir(shard.path())? {
+                let blob = blob?;
+                let hash = format!("{}{}", prefix, blob.file_name().to_string_lossy());
+                // Skips `.tmp` files left behind by an interrupted write
+                if is_valid_hash(&hash) {
+                    files.push((hash, blob.path()));
+                }
+            }
+        }
+        Ok(files)
+    }
+}
+
+fn seal<'a>(protection: &Protection, bytes: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+    match protection.cipher()? {
+        Some(cipher) => {
+            let mut sealed = SEALED_MAGIC.to_vec();
+            sealed.extend(cipher.seal(bytes)?);
+            Ok(Cow::Owned(sealed))
+        }
+        None => Ok(Cow::Borrowed(bytes)),
+    }
+}
+
+/// Plaintext files are returned as they are, so a store can be read while
+/// it is being migrated.
+fn unseal(protection: &Protection, bytes: Vec<u8>) -> Result<Vec<u8>> {
+    match bytes.strip_prefix(SEALED_MAGIC) {
+        Some(sealed) => protection.cipher()?.ok_or(Error::Locked)?.open(sealed),
+        None => Ok(bytes),
+    }
+}
+
+/// Seals every file under `to`, opening each with the first of `keys` that
+/// works.
+fn reseal_all(files: &[(String, PathBuf)], keys: &[&Protection], to: &Protection) -> Result<()> {
+    for (_, path) in files {
+        let raw = fs::read(path)?;
+        let mut bytes = Err(Error::Locked);
+        for key in keys {
+            bytes = unseal(key, raw.clone());
+            if bytes.is_ok() {
+                break;
+            }
+        }
+        write_atomic(path, &seal(to, &bytes?)?)?;
+    }
+    Ok(())
+}
+
+/// Writes to a temporary file first so readers never see a partial file.
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn blob_ref(item: &HistoryItem) -> Option<&ImageRef> {
+    item.image().filter(|image| !image.is_inline())
+}
+
+/// Moves images that older versions stored inline as data URLs into the blob
+/// store and rewrites their entries. Returns the number of moved images.
+pub fn import_inline_images(
+    blobs: &BlobStore,
+    store: &dyn HistoryStore,
+    entries: &mut [Entry],
+) -> Result<usize> {
+    let mut imported = 0;
+
+    for entry in entries.iter_mut() {
+        let HistoryItem::Image(image) = &entry.item else {
+            continue;
+        };
+        if !image.is_inline() {
+            continue;
+        }
+
+        let Some(bytes) = decode_png_data_url(&image.hash) else {
+            log::warn!("Skipping undecodable image in history entry {}", entry.id);
+            continue;
+        };
+        entry.item = HistoryItem::Image(ImageRef {
+            hash: blobs.put(&bytes)?,
+            size: bytes.len() as u64,
+        });
+        store.insert(entry)?;
+        imported += 1;
+    }
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::Cipher;
+
+    fn temp_store(name: &str) -> BlobStore {
+        let dir =
+            std::env::temp_dir().join(format!("macopy-blobs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        BlobStore::open(dir).unwrap()
+    }
+
+    fn encrypted() -> Protection {
+        Protection::Encrypted(Cipher::new(&Cipher::generate_key()))
+    }
+
+    /// A blob sealed under a key neither side of a rekey knows.
+    fn put_foreign(blobs: &BlobStore) {
+        let bytes = b"foreign";
+        let path = blobs.path(&content_hash(bytes)).unwrap();
+        write_atomic(&path, &seal(&encrypted(), bytes).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn rekey_moves_blobs_to_the_new_key() {
+        let blobs = temp_store("rekey");
+        let hash = blobs.put(b"image").unwrap();
+        let key = encrypted();
+
+        blobs.rekey(key.clone()).unwrap();
+        assert!(fs::read(blobs.path(&hash).unwrap())
+            .unwrap()
+            .starts_with(SEALED_MAGIC));
+        assert_eq!(blobs.get(&hash).unwrap(), b"image");
+
+        blobs.rekey(Protection::Plaintext).unwrap();
+        assert_eq!(fs::read(blobs.path(&hash).unwrap()).unwrap(), b"image");
+    }
+
+    #[test]
+    fn failed_rekey_keeps_blobs_under_the_current_key() {
+        let blobs = temp_store("failed-rekey");
+        let old = encrypted();
+        blobs.rekey(old.clone()).unwrap();
+        let hashes: Vec<String> = (0..8)
+            .map(|index| blobs.put(format!("image {}", index).as_bytes()).unwrap())
+            .collect();
+        put_foreign(&blobs);
+
+        assert!(blobs.rekey(encrypted()).is_err());
+
+        blobs.set_protection(old);
+        for (index, hash) in hashes.iter().enumerate() {
+            assert_eq!(
+                blobs.get(hash).unwrap(),
+                format!("image {}", index).as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn rekey_skips_leftovers_of_interrupted_writes() {
+        let blobs = temp_store("rekey-leftovers");
+        let hash = blobs.put(b"image").unwrap();
+        // The write of another blob stopped before the rename
+        let other = blobs.path(&content_hash(b"other")).unwrap();
+        let leftover = other.with_extension("tmp");
+        fs::create_dir_all(leftover.parent().unwrap()).unwrap();
+        fs::write(&leftover, b"partial").unwrap();
+
+        blobs.rekey(encrypted()).unwrap();
+        assert_eq!(blobs.get(&hash).unwrap(), b"image");
+        assert_eq!(fs::read(&leftover).unwrap(), b"partial");
+        assert!(!other.exists());
+    }
+
+    #[test]
+    fn rekey_accepts_blobs_already_under_the_new_key() {
+        let blobs = temp_store("rekey-again");
+        let hash = blobs.put(b"image").unwrap();
+        let key = encrypted();
+
+        // An earlier rekey to the same key was interrupted after this one
+        let other = content_hash(b"other");
+        write_atomic(&blobs.path(&other).unwrap(), &seal(&key, b"other").unwrap()).unwrap();
+
+        blobs.rekey(key).unwrap();
+        assert_eq!(blobs.get(&hash).unwrap(), b"image");
+        assert_eq!(blobs.get(&other).unwrap(), b"other");
+    }
+
+    #[test]
+    fn disabled_store_records_no_images() {
+        let blobs = BlobStore::disabled();
+        assert!(matches!(blobs.put(b"image"), Err(Error::Blob(_))));
+        let hash = content_hash(b"image");
+        assert!(blobs.get(&hash).is_err());
+
+        // Images loaded from an existing history are let go without errors
+        let item = HistoryItem::Image(ImageRef { hash, size: 5 });
+        assert_eq!(blobs.sync_refs(&[]).unwrap(), 0);
+        blobs.discard_unreferenced(&item).unwrap();
+        blobs.rekey(encrypted()).unwrap();
+    }
+}
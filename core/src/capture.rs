@@ -0,0 +1,351 @@
+//! Deciding whether a clipboard change goes into the history.
+//!
+//! A change is skipped while capture is paused, when Macopy made it itself,
+//! while an encrypted history is locked, when the source marked it as
+//! concealed or transient, when it comes from an excluded app and when the
+//! sensitive detector drops it. Everything else is recorded.
+
+use crate::blob::BlobStore;
+use crate::clipboard::{ClipboardBackend, ClipboardContent};
+use crate::exclusions::{AppInfo, ExcludedApps};
+use crate::history::{Capture, History, HistoryDiff};
+use crate::item::HistoryItem;
+use crate::pause::CapturePause;
+use crate::sensitive::SensitiveDetector;
+use crate::watcher::ClipboardWatcher;
+use std::sync::Mutex;
+
+/// The history and the settings a clipboard change is recorded with.
+///
+/// Only the history and the pause state are locked, and only briefly, so a
+/// slow image conversion or source app lookup does not block other readers.
+pub struct CaptureContext<'a> {
+    pub history: &'a Mutex<History>,
+    pub blobs: &'a BlobStore,
+    pub pause: &'a Mutex<CapturePause>,
+    pub excluded_apps: &'a ExcludedApps,
+    pub sensitive: &'a SensitiveDetector,
+    /// Nothing can be saved until an encrypted history is unlocked.
+    pub locked: bool,
+    /// Looks up the frontmost application, which is taken as the source of
+    /// the change.
+    pub source_app: &'a dyn Fn() -> AppInfo,
+}
+
+/// The result of [`CaptureContext::poll`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Polled {
+    /// What the recorded change did to the history, empty if nothing was
+    /// recorded.
+    pub diff: HistoryDiff,
+    /// A pause until the next copy ended with this change.
+    pub resumed: bool,
+}
+
+impl CaptureContext<'_> {
+    /// Records the change `watcher` sees next, if any, unless capture is
+    /// paused.
+    pub fn poll(&self, watcher: &mut ClipboardWatcher) -> Polled {
+        let Some(content) = watcher.poll() else {
+            return Polled::default();
+        };
+
+        let mut pause = self.pause.lock().unwrap();
+        let was_paused = pause.is_paused();
+        let allowed = pause.on_change(watcher.change_count());
+        let resumed = was_paused && !pause.is_paused();
+        drop(pause);
+
+        Polled {
+            diff: if allowed {
+                self.record(&content)
+            } else {
+                HistoryDiff::default()
+            },
+            resumed,
+        }
+    }
+
+    /// Records what is on the clipboard now, e.g. right before the popup
+    /// opens, without waiting for the watcher. Content copied during a pause
+    /// or written by Macopy is left alone.
+    pub fn record_current(&self, backend: &dyn ClipboardBackend) -> HistoryDiff {
+        let change_count = backend.change_count();
+        if !self.pause.lock().unwrap().allows(change_count) {
+            return HistoryDiff::default();
+        }
+        // Written by Macopy, which already updated the history
+        if backend.is_own_write(change_count) {
+            return HistoryDiff::default();
+        }
+
+        match backend.read() {
+            Ok(content) => self.record(&content),
+            Err(e) => {
+                log::error!("Failed to read clipboard: {}", e);
+                HistoryDiff::default()
+            }
+        }
+    }
+
+    /// Records `content` unless a rule skips it. Returns an empty diff if
+    /// nothing was recorded.
+    pub fn record(&self, content: &ClipboardContent) -> HistoryDiff {
+        if self.locked {
+            return HistoryDiff::default();
+        }
+
+        // Password managers mark their copies as concealed or transient
+        if !content.is_recordable() {
+            log::debug!("Ignoring concealed or transient clipboard content");
+            return HistoryDiff::default();
+        }
+
+        let source_app = (self.source_app)();
+        if self.excluded_apps.is_excluded(&source_app) {
+            log::debug!(
+                "Ignoring clipboard change from excluded app {}",
+                source_app.name
+            );
+            return HistoryDiff::default();
+        }
+
+        // Formatted content keeps every representation; otherwise images take
+        // priority over text
+        let item = match HistoryItem::capture(content, self.blobs) {
+            Ok(Some(item)) => item,
+            Ok(None) => return HistoryDiff::default(),
+            Err(e) => {
+                log::error!("Failed to store clipboard content: {}", e);
+                return HistoryDiff::default();
+            }
+        };
+
+        let capture = Capture {
+            source_app: Some(source_app.name).filter(|name| !name.is_empty()),
+            ..Capture::new(item.clone())
+        };
+        // Secrets are masked, set to expire or dropped
+        let diff = match self.sensitive.screen(capture) {
+            Some(capture) => self.history.lock().unwrap().record(capture),
+            None => {
+                log::debug!("Ignoring sensitive clipboard content");
+                HistoryDiff::default()
+            }
+        };
+
+        if diff.is_empty() {
+            // The image may have been written just for this capture
+            if let Err(e) = self.blobs.discard_unreferenced(&item) {
+                log::error!("Failed to discard image: {}", e);
+            }
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::MemoryClipboard;
+    use crate::history::HistoryLimits;
+    use crate::pause::Pause;
+    use crate::sensitive::{SensitiveAction, SensitiveSettings};
+    use std::sync::Arc;
+
+    const CARD: &str = "4111 1111 1111 1111";
+
+    struct Fixture {
+        clipboard: Arc<MemoryClipboard>,
+        history: Mutex<History>,
+        blobs: BlobStore,
+        pause: Mutex<CapturePause>,
+        excluded_apps: ExcludedApps,
+        sensitive: SensitiveDetector,
+        locked: bool,
+        source_app: AppInfo,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "macopy-capture-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            Self {
+                clipboard: Arc::new(MemoryClipboard::new()),
+                history: Mutex::new(History::new(HistoryLimits::default())),
+                blobs: BlobStore::open(dir).unwrap(),
+                pause: Mutex::new(CapturePause::default()),
+                excluded_apps: ExcludedApps::default(),
+                sensitive: SensitiveDetector::default(),
+                locked: false,
+                source_app: AppInfo::new("Terminal", Some("com.apple.Terminal".to_string())),
+            }
+        }
+
+        fn watcher(&self) -> ClipboardWatcher {
+            ClipboardWatcher::new(self.clipboard.clone())
+        }
+
+        /// Lets another app copy `text`, then polls once.
+        fn copy(&self, watcher: &mut ClipboardWatcher, text: &str) -> Polled {
+            self.clipboard.copy(ClipboardContent::text(text));
+            self.poll(watcher)
+        }
+
+        fn context<R>(&self, run: impl FnOnce(&CaptureContext) -> R) -> R {
+            let source_app = || self.source_app.clone();
+            run(&CaptureContext {
+                history: &self.history,
+                blobs: &self.blobs,
+                pause: &self.pause,
+                excluded_apps: &self.excluded_apps,
+                sensitive: &self.sensitive,
+                locked: self.locked,
+                source_app: &source_app,
+            })
+        }
+
+        fn poll(&self, watcher: &mut ClipboardWatcher) -> Polled {
+            self.context(|context| context.poll(watcher))
+        }
+
+        fn texts(&self) -> Vec<String> {
+            self.history
+                .lock()
+                .unwrap()
+                .entries()
+                .iter()
+                .map(|entry| entry.item.text().unwrap_or_default().to_string())
+                .collect()
+        }
+    }
+
+    #[test]
+    fn records_copies_with_their_source_app() {
+        let fixture = Fixture::new("record");
+        let mut watcher = fixture.watcher();
+
+        assert!(fixture.poll(&mut watcher).diff.is_empty());
+        let polled = fixture.copy(&mut watcher, "a");
+        assert_eq!(polled.diff.inserted.len(), 1);
+        fixture.copy(&mut watcher, "b");
+
+        assert_eq!(fixture.texts(), ["b", "a"]);
+        let history = fixture.history.lock().unwrap();
+        assert_eq!(history.entries()[0].source_app.as_deref(), Some("Terminal"));
+    }
+
+    #[test]
+    fn skips_own_writes() {
+        let fixture = Fixture::new("own-writes");
+        let mut watcher = fixture.watcher();
+        fixture.copy(&mut watcher, "a");
+
+        fixture
+            .clipboard
+            .write(&ClipboardContent::text("pasted"))
+            .unwrap();
+        assert!(fixture.poll(&mut watcher).diff.is_empty());
+
+        let diff = fixture.context(|context| context.record_current(fixture.clipboard.as_ref()));
+        assert!(diff.is_empty());
+        assert_eq!(fixture.texts(), ["a"]);
+
+        // A copy by another app after the write is recorded again
+        fixture.copy(&mut watcher, "b");
+        assert_eq!(fixture.texts(), ["b", "a"]);
+    }
+
+    #[test]
+    fn skips_changes_while_paused() {
+        let fixture = Fixture::new("pause");
+        let mut watcher = fixture.watcher();
+        fixture.pause.lock().unwrap().pause(Pause::Indefinite);
+
+        let polled = fixture.copy(&mut watcher, "a");
+        assert!(polled.diff.is_empty());
+        assert!(!polled.resumed);
+
+        // What was copied during the pause stays out after resuming
+        fixture.pause.lock().unwrap().resume();
+        assert!(fixture.poll(&mut watcher).diff.is_empty());
+        assert!(fixture.texts().is_empty());
+    }
+
+    #[test]
+    fn next_copy_pause_skips_one_change() {
+        let fixture = Fixture::new("next-copy");
+        let mut watcher = fixture.watcher();
+        fixture.pause.lock().unwrap().pause(Pause::NextCopy);
+
+        let polled = fixture.copy(&mut watcher, "a");
+        assert!(polled.diff.is_empty());
+        assert!(polled.resumed);
+
+        fixture.copy(&mut watcher, "b");
+        assert_eq!(fixture.texts(), ["b"]);
+    }
+
+    #[test]
+    fn skips_concealed_and_transient_content() {
+        let fixture = Fixture::new("concealed");
+        let mut watcher = fixture.watcher();
+
+        for content in [
+            ClipboardContent {
+                sensitive: true,
+                ..ClipboardContent::text("hunter2")
+            },
+            ClipboardContent {
+                transient: true,
+                ..ClipboardContent::text("generated")
+            },
+        ] {
+            fixture.clipboard.copy(content);
+            assert!(fixture.poll(&mut watcher).diff.is_empty());
+        }
+        assert!(fixture.texts().is_empty());
+    }
+
+    #[test]
+    fn skips_excluded_apps() {
+        let mut fixture = Fixture::new("excluded");
+        fixture.excluded_apps = ExcludedApps::new(vec!["com.apple.terminal".to_string()]);
+        let mut watcher = fixture.watcher();
+
+        assert!(fixture.copy(&mut watcher, "a").diff.is_empty());
+        assert!(fixture.texts().is_empty());
+    }
+
+    #[test]
+    fn skips_everything_while_locked() {
+        let mut fixture = Fixture::new("locked");
+        fixture.locked = true;
+        let mut watcher = fixture.watcher();
+
+        assert!(fixture.copy(&mut watcher, "a").diff.is_empty());
+        assert!(fixture.texts().is_empty());
+    }
+
+    #[test]
+    fn masks_or_drops_sensitive_text() {
+        let fixture = Fixture::new("masked");
+        let mut watcher = fixture.watcher();
+        fixture.copy(&mut watcher, CARD);
+        assert!(fixture.history.lock().unwrap().entries()[0].sensitive);
+
+        let mut fixture = Fixture::new("dropped");
+        fixture.sensitive = SensitiveDetector::new(SensitiveSettings {
+            action: SensitiveAction::Skip,
+            ..SensitiveSettings::default()
+        })
+        .unwrap();
+        let mut watcher = fixture.watcher();
+        assert!(fixture.copy(&mut watcher, CARD).diff.is_empty());
+        assert!(fixture.texts().is_empty());
+    }
+}
@@ -0,0 +1,261 @@
+use super::own_writes::OwnWrites;
+use super::uri_list::{file_url, parse_file_url};
+use super::{ClipboardBackend, ClipboardContent};
+use crate::error::Result;
+use cocoa::base::{id, nil, BOOL, NO};
+use cocoa::foundation::{NSAutoreleasePool, NSData, NSString};
+use objc::{class, msg_send, sel, sel_impl};
+use std::ffi::CStr;
+use std::path::PathBuf;
+
+const PNG_TYPE: &[u8] = b"public.png\0";
+const TIFF_TYPE: &[u8] = b"public.tiff\0";
+const STRING_TYPE: &[u8] = b"public.utf8-plain-text\0";
+const HTML_TYPE: &[u8] = b"public.html\0";
+const RTF_TYPE: &[u8] = b"public.rtf\0";
+const FILE_URL_TYPE: &[u8] = b"public.file-url\0";
+
+// Markers from http://nspasteboard.org
+const CONCEALED_TYPES: &[&[u8]] = &[b"org.nspasteboard.ConcealedType\0"];
+const TRANSIENT_TYPES: &[&[u8]] = &[
+    b"org.nspasteboard.TransientType\0",
+    b"org.nspasteboard.AutoGeneratedType\0",
+    b"de.petermaurer.TransientPasteboardType\0",
+];
+
+/// `NSPasteboard.generalPasteboard` through Cocoa.
+#[derive(Debug, Default)]
+pub struct MacClipboard {
+    own_writes: OwnWrites,
+}
+
+impl MacClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+unsafe fn pasteboard_type(name: &'static [u8]) -> id {
+    msg_send![class!(NSString), stringWithUTF8String: name.as_ptr()]
+}
+
+unsafe fn data_to_vec(data: id) -> Vec<u8> {
+    let length: usize = msg_send![data, length];
+    let bytes: *const u8 = msg_send![data, bytes];
+    std::slice::from_raw_parts(bytes, length).to_vec()
+}
+
+unsafe fn has_any_type(pasteboard: id, names: &[&'static [u8]]) -> bool {
+    let types: id = msg_send![pasteboard, types];
+    if types == nil {
+        return false;
+    }
+
+    names.iter().any(|name| {
+        let contains: BOOL = msg_send![types, containsObject: pasteboard_type(name)];
+        contains != NO
+    })
+}
+
+unsafe fn to_string(string: id) -> Option<String> {
+    if string == nil {
+        return None;
+    }
+
+    let utf8: *const std::os::raw::c_char = msg_send![string, UTF8String];
+    if utf8.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(utf8).to_string_lossy().into_owned())
+}
+
+unsafe fn read_text(pasteboard: id) -> Option<String> {
+    to_string(msg_send![pasteboard, stringForType: pasteboard_type(STRING_TYPE)])
+}
+
+/// Turns a file URL into a path. Finder may put file reference URLs
+/// (`file:///.file/id=…`) on the pasteboard, which only `NSURL` can map to
+/// the file's current path.
+unsafe fn resolve_file_url(url: &str) -> Option<PathBuf> {
+    let string: id = NSString::alloc(nil).init_str(url);
+    let _: id = msg_send![string, autorelease];
+    let url_object: id = msg_send![class!(NSURL), URLWithString: string];
+    if url_object == nil {
+        return parse_file_url(url);
+    }
+
+    let path_url: id = msg_send![url_object, filePathURL];
+    if path_url == nil {
+        // A reference to a file that no longer exists
+        return None;
+    }
+    to_string(msg_send![path_url, path]).map(PathBuf::from)
+}
+
+/// Finder puts each copied file in a pasteboard item of its own.
+unsafe fn read_files(pasteboard: id) -> Vec<PathBuf> {
+    let items: id = msg_send![pasteboard, pasteboardItems];
+    if items == nil {
+        return Vec::new();
+    }
+
+    let count: usize = msg_send![items, count];
+    (0..count)
+        .filter_map(|index| {
+            let item: id = msg_send![items, objectAtIndex: index];
+            to_string(msg_send![item, stringForType: pasteboard_type(FILE_URL_TYPE)])
+        })
+        .filter_map(|url| resolve_file_url(&url))
+        .collect()
+}
+
+unsafe fn write_files(pasteboard: id, files: &[PathBuf]) {
+    let items: id = msg_send![class!(NSMutableArray), array];
+    for url in files.iter().filter_map(|path| file_url(path)) {
+        let item: id = msg_send![class!(NSPasteboardItem), new];
+        let _: id = msg_send![item, autorelease];
+        let string: id = NSString::alloc(nil).init_str(&url);
+        let _: id = msg_send![string, autorelease];
+        let _: bool = msg_send![item, setString: string forType: pasteboard_type(FILE_URL_TYPE)];
+        let _: () = msg_send![items, addObject: item];
+    }
+    let _: bool = msg_send![pasteboard, writeObjects: items];
+}
+
+/// Reads textual data such as HTML or RTF that is not offered as a string.
+unsafe fn read_data_string(pasteboard: id, name: &'static [u8]) -> Option<String> {
+    let data: id = msg_send![pasteboard, dataForType: pasteboard_type(name)];
+    if data == nil {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&data_to_vec(data)).into_owned())
+}
+
+unsafe fn write_data(pasteboard: id, bytes: &[u8], name: &'static [u8]) {
+    let data: id = NSData::dataWithBytes_length_(
+        nil,
+        bytes.as_ptr() as *const std::ffi::c_void,
+        bytes.len() as u64,
+    );
+    let _: bool = msg_send![pasteboard, setData: data forType: pasteboard_type(name)];
+}
+
+unsafe fn read_png(pasteboard: id) -> Option<Vec<u8>> {
+    // Try PNG first
+    let png_data: id = msg_send![pasteboard, dataForType: pasteboard_type(PNG_TYPE)];
+    if png_data != nil {
+        return Some(data_to_vec(png_data));
+    }
+
+    // Try TIFF and convert it to PNG using NSImage
+    let tiff_data: id = msg_send![pasteboard, dataForType: pasteboard_type(TIFF_TYPE)];
+    if tiff_data == nil {
+        return None;
+    }
+
+    let image: id = msg_send![class!(NSImage), alloc];
+    let image: id = msg_send![image, initWithData: tiff_data];
+    if image == nil {
+        return None;
+    }
+    let _: id = msg_send![image, autorelease];
+
+    let tiff_rep: id = msg_send![image, TIFFRepresentation];
+    if tiff_rep == nil {
+        return None;
+    }
+
+    let bitmap_rep: id = msg_send![class!(NSBitmapImageRep), imageRepWithData: tiff_rep];
+    if bitmap_rep == nil {
+        return None;
+    }
+
+    // NSBitmapImageFileTypePNG
+    let props: id = msg_send![class!(NSDictionary), dictionary];
+    let png_data: id = msg_send![bitmap_rep, representationUsingType: 4u64 properties: props];
+    if png_data == nil {
+        return None;
+    }
+    Some(data_to_vec(png_data))
+}
+
+impl ClipboardBackend for MacClipboard {
+    fn change_count(&self) -> i64 {
+        unsafe {
+            let pool = NSAutoreleasePool::new(nil);
+            let pasteboard: id = msg_send![class!(NSPasteboard), generalPasteboard];
+            let count: i64 = msg_send![pasteboard, changeCount];
+            let _: () = msg_send![pool, drain];
+            count
+        }
+    }
+
+    fn read(&self) -> Result<ClipboardContent> {
+        unsafe {
+            let pool = NSAutoreleasePool::new(nil);
+            let pasteboard: id = msg_send![class!(NSPasteboard), generalPasteboard];
+
+            let content = ClipboardContent {
+                text: read_text(pasteboard),
+                html: read_data_string(pasteboard, HTML_TYPE),
+                rtf: read_data_string(pasteboard, RTF_TYPE),
+                png: read_png(pasteboard),
+                files: read_files(pasteboard),
+                sensitive: has_any_type(pasteboard, CONCEALED_TYPES),
+                transient: has_any_type(pasteboard, TRANSIENT_TYPES),
+            };
+
+            let _: () = msg_send![pool, drain];
+            Ok(content)
+        }
+    }
+
+    fn write(&self, content: &ClipboardContent) -> Result<i64> {
+        unsafe {
+            let pool = NSAutoreleasePool::new(nil);
+            let pasteboard: id = msg_send![class!(NSPasteboard), generalPasteboard];
+            // Clearing starts the change; the types below belong to it
+            let change_count: i64 = msg_send![pasteboard, clearContents];
+            self.own_writes.note(change_count);
+
+            // Written first, so the other types join the first file's item
+            if !content.files.is_empty() {
+                write_files(pasteboard, &content.files);
+            }
+
+            if let Some(png) = &content.png {
+                write_data(pasteboard, png, PNG_TYPE);
+            }
+
+            if let Some(html) = &content.html {
+                write_data(pasteboard, html.as_bytes(), HTML_TYPE);
+            }
+
+            if let Some(rtf) = &content.rtf {
+                write_data(pasteboard, rtf.as_bytes(), RTF_TYPE);
+            }
+
+            if let Some(text) = &content.text {
+                let string: id = NSString::alloc(nil).init_str(text);
+                let _: id = msg_send![string, autorelease];
+                let _: bool =
+                    msg_send![pasteboard, setString: string forType: pasteboard_type(STRING_TYPE)];
+            }
+
+            // Keep the markers when putting back a secret or generated content
+            if content.sensitive {
+                write_data(pasteboard, &[], CONCEALED_TYPES[0]);
+            }
+            if content.transient {
+                write_data(pasteboard, &[], TRANSIENT_TYPES[0]);
+            }
+
+            let _: () = msg_send![pool, drain];
+            Ok(change_count)
+        }
+    }
+
+    fn is_own_write(&self, change_count: i64) -> bool {
+        self.own_writes.contains(change_count)
+    }
+}
@@ -0,0 +1,91 @@
+use super::{ClipboardBackend, ClipboardContent};
+use crate::error::Result;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+#[derive(Debug, Default)]
+struct Inner {
+    change_count: i64,
+    content: ClipboardContent,
+    script: VecDeque<ClipboardContent>,
+    writes: Vec<ClipboardContent>,
+    /// Change counts produced by writes.
+    write_counts: Vec<i64>,
+}
+
+/// In-memory clipboard that tests drive step by step.
+///
+/// [`copy`](Self::copy) simulates another application copying something,
+/// while [`script`](Self::script) queues copies that are applied one at a
+/// time by [`advance`](Self::advance). Writes made through the backend are
+/// recorded and can be inspected with [`writes`](Self::writes).
+#[derive(Debug, Default)]
+pub struct MemoryClipboard {
+    inner: Mutex<Inner>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn copy(&self, content: ClipboardContent) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.content = content;
+        inner.change_count += 1;
+    }
+
+    pub fn script(&self, copies: impl IntoIterator<Item = ClipboardContent>) {
+        self.inner.lock().unwrap().script.extend(copies);
+    }
+
+    /// Applies the next scripted copy. Returns `false` once the script is
+    /// exhausted.
+    pub fn advance(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.script.pop_front() {
+            Some(content) => {
+                inner.content = content;
+                inner.change_count += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn content(&self) -> ClipboardContent {
+        self.inner.lock().unwrap().content.clone()
+    }
+
+    pub fn writes(&self) -> Vec<ClipboardContent> {
+        self.inner.lock().unwrap().writes.clone()
+    }
+}
+
+impl ClipboardBackend for MemoryClipboard {
+    fn change_count(&self) -> i64 {
+        self.inner.lock().unwrap().change_count
+    }
+
+    fn read(&self) -> Result<ClipboardContent> {
+        Ok(self.content())
+    }
+
+    fn write(&self, content: &ClipboardContent) -> Result<i64> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.content = content.clone();
+        inner.change_count += 1;
+        inner.writes.push(content.clone());
+        let change_count = inner.change_count;
+        inner.write_counts.push(change_count);
+        Ok(change_count)
+    }
+
+    fn is_own_write(&self, change_count: i64) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .write_counts
+            .contains(&change_count)
+    }
+}
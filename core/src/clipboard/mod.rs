@@ -0,0 +1,112 @@
+//! Access to the system clipboard.
+//!
+//! [`ClipboardBackend`] hides the platform APIs behind change detection plus
+//! multi-format read and write, so the watcher logic can run against the
+//! Cocoa pasteboard, an X11 selection or a scripted in-memory clipboard.
+
+#[cfg(target_os = "macos")]
+mod macos;
+mod memory;
+#[cfg(any(target_os = "macos", feature = "x11"))]
+mod own_writes;
+mod restore;
+#[cfg(any(target_os = "macos", feature = "x11"))]
+mod uri_list;
+#[cfg(feature = "x11")]
+mod x11;
+
+use crate::error::Result;
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+#[cfg(target_os = "macos")]
+pub use macos::MacClipboard;
+pub use memory::MemoryClipboard;
+pub use restore::ClipboardRestore;
+#[cfg(feature = "x11")]
+pub use x11::X11Clipboard;
+
+/// Every representation currently on the clipboard that Macopy understands.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClipboardContent {
+    pub text: Option<String>,
+    pub html: Option<String>,
+    pub rtf: Option<String>,
+    /// PNG encoded image bytes.
+    pub png: Option<Vec<u8>>,
+    /// Files and folders copied in a file manager.
+    pub files: Vec<PathBuf>,
+    /// The source marked the content as a password or other secret, e.g.
+    /// with `org.nspasteboard.ConcealedType`.
+    pub sensitive: bool,
+    /// The source marked the content as temporary or generated rather than
+    /// copied by the user, e.g. with `org.nspasteboard.TransientType`.
+    pub transient: bool,
+}
+
+impl ClipboardContent {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: Some(text.into()),
+            ..Self::default()
+        }
+    }
+
+    pub fn png(bytes: Vec<u8>) -> Self {
+        Self {
+            png: Some(bytes),
+            ..Self::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.as_deref().map_or(true, str::is_empty)
+            && self.html.is_none()
+            && self.rtf.is_none()
+            && self.png.is_none()
+            && self.files.is_empty()
+    }
+
+    /// The text, or the paths of copied files, one per line.
+    pub fn plain_text(&self) -> Option<Cow<'_, str>> {
+        match &self.text {
+            Some(text) if !text.is_empty() => Some(Cow::Borrowed(text)),
+            _ if !self.files.is_empty() => Some(Cow::Owned(paths_text(&self.files))),
+            _ => None,
+        }
+    }
+
+    /// Whether the history may record this content. Concealed and transient
+    /// content is left alone, as <http://nspasteboard.org> asks of clipboard
+    /// managers.
+    pub fn is_recordable(&self) -> bool {
+        !self.sensitive && !self.transient
+    }
+}
+
+/// Lists paths one per line, for apps that take text rather than files.
+pub(crate) fn paths_text(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub trait ClipboardBackend: Send + Sync {
+    /// A counter that changes whenever the clipboard is written, by anyone.
+    fn change_count(&self) -> i64;
+
+    /// Reads every supported representation from the clipboard.
+    fn read(&self) -> Result<ClipboardContent>;
+
+    /// Replaces the clipboard with the given representations and returns the
+    /// change count of this write. Backends that learn of changes from
+    /// events may report it through [`change_count`](Self::change_count)
+    /// only a little later.
+    fn write(&self, content: &ClipboardContent) -> Result<i64>;
+
+    /// Whether the change with this count was made by [`write`](Self::write)
+    /// rather than by another application.
+    fn is_own_write(&self, change_count: i64) -> bool;
+}
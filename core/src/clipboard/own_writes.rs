@@ -0,0 +1,42 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Writes can outpace the watcher, so a few are remembered.
+const CAPACITY: usize = 16;
+
+/// Change counts produced by a backend's own writes.
+#[derive(Debug, Default)]
+pub(crate) struct OwnWrites {
+    counts: Mutex<VecDeque<i64>>,
+}
+
+impl OwnWrites {
+    pub(crate) fn note(&self, change_count: i64) {
+        let mut counts = self.counts.lock().unwrap();
+        if counts.len() == CAPACITY {
+            counts.pop_front();
+        }
+        counts.push_back(change_count);
+    }
+
+    pub(crate) fn contains(&self, change_count: i64) -> bool {
+        self.counts.lock().unwrap().contains(&change_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remembers_the_latest_writes() {
+        let own_writes = OwnWrites::default();
+        for change_count in 1..=CAPACITY as i64 + 1 {
+            own_writes.note(change_count);
+        }
+        assert!(!own_writes.contains(1));
+        assert!(own_writes.contains(2));
+        assert!(own_writes.contains(CAPACITY as i64 + 1));
+        assert!(!own_writes.contains(0));
+    }
+}
@@ -0,0 +1,212 @@
+use super::{ClipboardBackend, ClipboardContent};
+use crate::error::Result;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Puts back what was on the clipboard after Macopy replaced it for a paste.
+///
+/// The restore is a write through the backend, which the watcher skips.
+#[derive(Clone)]
+pub struct ClipboardRestore {
+    backend: Arc<dyn ClipboardBackend>,
+}
+
+impl ClipboardRestore {
+    pub fn new(backend: Arc<dyn ClipboardBackend>) -> Self {
+        Self { backend }
+    }
+
+    /// Reads the clipboard before a history item replaces it.
+    pub fn snapshot(&self) -> Result<ClipboardContent> {
+        self.backend.read()
+    }
+
+    /// Runs `paste`, waits `delay` and writes `snapshot` back with all its
+    /// representations.
+    ///
+    /// `pasted` is the change count of the write that put the pasted item on
+    /// the clipboard. Nothing is restored if the count moved on in the
+    /// meantime, so a copy made during the paste is kept even if it holds the
+    /// same content.
+    pub fn paste_and_restore(
+        &self,
+        snapshot: ClipboardContent,
+        pasted: i64,
+        delay: Duration,
+        paste: impl FnOnce() -> Result<()>,
+    ) -> Result<()> {
+        let result = paste();
+        thread::sleep(delay);
+
+        if self.backend.change_count() != pasted {
+            log::debug!("Clipboard changed during paste, not restoring it");
+            return result;
+        }
+
+        self.backend.write(&snapshot)?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::MemoryClipboard;
+    use crate::paste::{PasteEvent, PasteProfile, PasteProfiles, Paster, RecordingInjector};
+    use std::sync::Mutex;
+
+    /// Counts a write only once [`publish`](Self::publish) is called, the way
+    /// the X11 backend counts its writes when XFixes reports them.
+    struct LateClipboard {
+        inner: MemoryClipboard,
+        reported: Mutex<i64>,
+    }
+
+    impl LateClipboard {
+        fn publish(&self) {
+            *self.reported.lock().unwrap() = self.inner.change_count();
+        }
+    }
+
+    impl ClipboardBackend for LateClipboard {
+        fn change_count(&self) -> i64 {
+            *self.reported.lock().unwrap()
+        }
+
+        fn read(&self) -> Result<ClipboardContent> {
+            self.inner.read()
+        }
+
+        fn write(&self, content: &ClipboardContent) -> Result<i64> {
+            self.inner.write(content)
+        }
+
+        fn is_own_write(&self, change_count: i64) -> bool {
+            self.inner.is_own_write(change_count)
+        }
+    }
+
+    struct Fixture {
+        clipboard: Arc<MemoryClipboard>,
+        injector: Arc<RecordingInjector>,
+        paster: Paster,
+        restore: ClipboardRestore,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let clipboard = Arc::new(MemoryClipboard::new());
+            let injector = Arc::new(RecordingInjector::new());
+            let profile = PasteProfile {
+                delay_ms: 0,
+                restore_delay_ms: 0,
+                ..PasteProfile::default()
+            };
+            let paster = Paster::new(
+                injector.clone(),
+                PasteProfiles {
+                    default: profile,
+                    ..PasteProfiles::default()
+                },
+            );
+            Self {
+                restore: ClipboardRestore::new(clipboard.clone()),
+                clipboard,
+                injector,
+                paster,
+            }
+        }
+
+        /// Copies `previous`, then puts `item` on the clipboard the way a
+        /// paste does. Returns the snapshot and the change count of the
+        /// write.
+        fn prepare(&self, previous: &str, item: &str) -> (ClipboardContent, i64) {
+            self.clipboard.copy(ClipboardContent::text(previous));
+            let snapshot = self.restore.snapshot().unwrap();
+            let pasted = self.clipboard.write(&ClipboardContent::text(item)).unwrap();
+            (snapshot, pasted)
+        }
+
+        fn paste_and_restore(
+            &self,
+            snapshot: ClipboardContent,
+            pasted: i64,
+            during: impl FnOnce(),
+        ) -> Result<()> {
+            let delay = self.paster.restore_delay("");
+            self.restore.paste_and_restore(snapshot, pasted, delay, || {
+                self.paster.paste("")?;
+                during();
+                Ok(())
+            })
+        }
+    }
+
+    #[test]
+    fn restores_the_previous_clipboard_after_pasting() {
+        let fixture = Fixture::new();
+        let (snapshot, pasted) = fixture.prepare("previous", "item");
+
+        fixture.paste_and_restore(snapshot, pasted, || {}).unwrap();
+        assert_eq!(fixture.injector.events(), [PasteEvent::Paste]);
+        assert_eq!(
+            fixture.clipboard.content(),
+            ClipboardContent::text("previous")
+        );
+    }
+
+    #[test]
+    fn keeps_a_copy_made_during_the_paste() {
+        let fixture = Fixture::new();
+        let (snapshot, pasted) = fixture.prepare("previous", "item");
+
+        // Copying the same text still counts as a new copy
+        fixture
+            .paste_and_restore(snapshot, pasted, || {
+                fixture.clipboard.copy(ClipboardContent::text("item"))
+            })
+            .unwrap();
+        assert_eq!(fixture.clipboard.content(), ClipboardContent::text("item"));
+        assert_eq!(fixture.clipboard.writes().len(), 1);
+    }
+
+    #[test]
+    fn restores_even_if_the_paste_fails() {
+        let fixture = Fixture::new();
+        let (snapshot, pasted) = fixture.prepare("previous", "item");
+
+        fixture.injector.fail_next(1);
+        assert!(fixture.paste_and_restore(snapshot, pasted, || {}).is_err());
+        assert_eq!(
+            fixture.clipboard.content(),
+            ClipboardContent::text("previous")
+        );
+    }
+
+    #[test]
+    fn restores_when_the_write_is_counted_late() {
+        let clipboard = Arc::new(LateClipboard {
+            inner: MemoryClipboard::new(),
+            reported: Mutex::new(0),
+        });
+        clipboard.inner.copy(ClipboardContent::text("previous"));
+        clipboard.publish();
+        let restore = ClipboardRestore::new(clipboard.clone());
+
+        let snapshot = restore.snapshot().unwrap();
+        let pasted = clipboard.write(&ClipboardContent::text("item")).unwrap();
+        assert_ne!(clipboard.change_count(), pasted);
+
+        restore
+            .paste_and_restore(snapshot, pasted, Duration::ZERO, || {
+                clipboard.publish();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            clipboard.inner.content(),
+            ClipboardContent::text("previous")
+        );
+    }
+}
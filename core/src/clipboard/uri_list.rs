@@ -0,0 +1,124 @@
+//! `file://` URLs as used by file managers for copied files, alone or as a
+//! `text/uri-list` (RFC 2483).
+
+use std::path::{Path, PathBuf};
+
+const FILE_REFERENCE_PREFIX: &str = "/.file/id=";
+
+/// Percent-encodes an absolute path into a `file://` URL. Paths that are
+/// relative or not valid UTF-8 are skipped.
+pub(crate) fn file_url(path: &Path) -> Option<String> {
+    let path = path.to_str().filter(|_| path.is_absolute())?;
+
+    let mut url = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    Some(url)
+}
+
+/// Parses a local `file://` URL, with an empty or `localhost` host, into a
+/// path.
+///
+/// macOS file reference URLs (`file:///.file/id=…`) name a file by its ID
+/// rather than its path and are rejected; they have to be resolved through
+/// `NSURL` instead.
+pub(crate) fn parse_file_url(url: &str) -> Option<PathBuf> {
+    let rest = url.trim().strip_prefix("file://")?;
+    let path = match rest.find('/') {
+        Some(0) => rest,
+        Some(start) if rest[..start].eq_ignore_ascii_case("localhost") => &rest[start..],
+        _ => return None,
+    };
+    if path.starts_with(FILE_REFERENCE_PREFIX) {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = match tail {
+            [high, low, ..] if byte == b'%' => std::str::from_utf8(&[*high, *low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Returns the files in a `text/uri-list`, or nothing if it lists anything
+/// other than local files.
+#[cfg(feature = "x11")]
+pub(crate) fn parse_uri_list(list: &str) -> Vec<PathBuf> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_file_url)
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default()
+}
+
+/// Formats `paths` as a `text/uri-list`.
+#[cfg(feature = "x11")]
+pub(crate) fn to_uri_list(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .filter_map(|path| file_url(path))
+        .map(|url| url + "\r\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_paths_with_special_characters() {
+        let path = Path::new("/Users/me/Résumé 100%.pdf");
+        let url = file_url(path).unwrap();
+        assert_eq!(url, "file:///Users/me/R%C3%A9sum%C3%A9%20100%25.pdf");
+        assert_eq!(parse_file_url(&url).as_deref(), Some(path));
+    }
+
+    #[test]
+    fn accepts_only_local_urls() {
+        assert_eq!(
+            parse_file_url("file://localhost/tmp/a").as_deref(),
+            Some(Path::new("/tmp/a"))
+        );
+        assert_eq!(parse_file_url("file://server/tmp/a"), None);
+        assert_eq!(parse_file_url("https://example.com/a"), None);
+        assert_eq!(file_url(Path::new("relative")), None);
+    }
+
+    #[test]
+    fn rejects_file_reference_urls() {
+        assert_eq!(parse_file_url("file:///.file/id=6571367.8612331"), None);
+    }
+
+    #[cfg(feature = "x11")]
+    #[test]
+    fn parses_uri_lists_of_local_files_only() {
+        let paths = vec![PathBuf::from("/tmp/a b"), PathBuf::from("/tmp/c")];
+        let list = to_uri_list(&paths);
+        assert_eq!(list, "file:///tmp/a%20b\r\nfile:///tmp/c\r\n");
+        assert_eq!(parse_uri_list(&format!("# comment\r\n{}", list)), paths);
+        assert!(parse_uri_list("file:///tmp/a\nhttps://example.com/").is_empty());
+    }
+}
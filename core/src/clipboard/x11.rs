@@ -0,0 +1,538 @@
+use super::own_writes::OwnWrites;
+use super::uri_list::{parse_uri_list, to_uri_list};
+use super::{ClipboardBackend, ClipboardContent};
+use crate::error::{Error, Result};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, EventMask, PropMode, Property,
+    SelectionNotifyEvent, SelectionRequestEvent, Window, WindowClass, SELECTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, CURRENT_TIME, NONE};
+
+const READ_TIMEOUT: Duration = Duration::from_millis(500);
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// How long a write waits for XFixes to report it.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        CLIPBOARD,
+        TARGETS,
+        UTF8_STRING,
+        INCR,
+        MACOPY_SELECTION,
+        IMAGE_PNG: b"image/png",
+        TEXT_PLAIN_UTF8: b"text/plain;charset=utf-8",
+        TEXT_HTML: b"text/html",
+        TEXT_RTF: b"text/rtf",
+        APPLICATION_RTF: b"application/rtf",
+        TEXT_URI_LIST: b"text/uri-list",
+        // Nautilus and other GNOME file managers
+        GNOME_COPIED_FILES: b"x-special/gnome-copied-files",
+        // Set by KDE password managers such as KeePassXC
+        PASSWORD_MANAGER_HINT: b"x-kde-passwordManagerHint",
+    }
+}
+
+fn x11_error(e: impl std::fmt::Display) -> Error {
+    Error::Clipboard(e.to_string())
+}
+
+/// Decodes `text/html`, which some browsers send as UTF-16 with a byte order
+/// mark.
+fn decode_html(bytes: &[u8]) -> String {
+    match bytes {
+        [0xff, 0xfe, rest @ ..] => {
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Reads `x-special/gnome-copied-files`: `copy` or `cut`, then one URL per
+/// line.
+fn parse_gnome_copied_files(text: &str) -> Vec<PathBuf> {
+    match text.split_once('\n') {
+        Some((_, urls)) => parse_uri_list(urls),
+        None => Vec::new(),
+    }
+}
+
+/// A connection with a hidden window used as selection requestor or owner.
+struct Endpoint {
+    conn: RustConnection,
+    window: Window,
+    atoms: Atoms,
+}
+
+impl Endpoint {
+    fn connect() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(x11_error)?;
+        let root = conn.setup().roots[screen_num].root;
+        let window = conn.generate_id().map_err(x11_error)?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            COPY_FROM_PARENT,
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )
+        .map_err(x11_error)?;
+        let atoms = Atoms::new(&conn)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        conn.flush().map_err(x11_error)?;
+
+        Ok(Self {
+            conn,
+            window,
+            atoms,
+        })
+    }
+
+    fn text_targets(&self) -> [Atom; 3] {
+        [
+            self.atoms.UTF8_STRING,
+            self.atoms.TEXT_PLAIN_UTF8,
+            AtomEnum::STRING.into(),
+        ]
+    }
+
+    fn rtf_targets(&self) -> [Atom; 2] {
+        [self.atoms.TEXT_RTF, self.atoms.APPLICATION_RTF]
+    }
+
+    fn next_event(&self, deadline: Instant) -> Result<Option<Event>> {
+        loop {
+            if let Some(event) = self.conn.poll_for_event().map_err(x11_error)? {
+                return Ok(Some(event));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(EVENT_POLL_INTERVAL);
+        }
+    }
+
+    /// Asks the selection owner to convert the clipboard to `target` and
+    /// returns the transferred bytes, or `None` if the owner refused.
+    fn convert(&self, target: Atom) -> Result<Option<Vec<u8>>> {
+        let property = self.atoms.MACOPY_SELECTION;
+        self.conn
+            .delete_property(self.window, property)
+            .map_err(x11_error)?;
+        self.conn
+            .convert_selection(
+                self.window,
+                self.atoms.CLIPBOARD,
+                target,
+                property,
+                CURRENT_TIME,
+            )
+            .map_err(x11_error)?;
+        self.conn.flush().map_err(x11_error)?;
+
+        let deadline = Instant::now() + READ_TIMEOUT;
+        loop {
+            match self.next_event(deadline)? {
+                Some(Event::SelectionNotify(e)) if e.requestor == self.window => {
+                    if e.property == NONE {
+                        return Ok(None);
+                    }
+                    break;
+                }
+                Some(_) => continue,
+                None => return Err(Error::Clipboard("selection request timed out".into())),
+            }
+        }
+
+        let reply = self
+            .conn
+            .get_property(true, self.window, property, AtomEnum::ANY, 0, u32::MAX)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+
+        if reply.type_ == self.atoms.INCR {
+            return self.read_incr().map(Some);
+        }
+        Ok(Some(reply.value))
+    }
+
+    /// Receives a transfer that the owner split into chunks.
+    fn read_incr(&self) -> Result<Vec<u8>> {
+        let property = self.atoms.MACOPY_SELECTION;
+        let mut data = Vec::new();
+        let mut deadline = Instant::now() + READ_TIMEOUT;
+
+        loop {
+            let event = self
+                .next_event(deadline)?
+                .ok_or_else(|| Error::Clipboard("incremental transfer timed out".into()))?;
+
+            if let Event::PropertyNotify(e) = event {
+                if e.window != self.window || e.atom != property || e.state != Property::NEW_VALUE {
+                    continue;
+                }
+
+                let reply = self
+                    .conn
+                    .get_property(true, self.window, property, AtomEnum::ANY, 0, u32::MAX)
+                    .map_err(x11_error)?
+                    .reply()
+                    .map_err(x11_error)?;
+                if reply.value.is_empty() {
+                    return Ok(data);
+                }
+                data.extend_from_slice(&reply.value);
+                deadline = Instant::now() + READ_TIMEOUT;
+            }
+        }
+    }
+
+    fn targets(&self) -> Result<Vec<Atom>> {
+        Ok(self
+            .convert(self.atoms.TARGETS)?
+            .unwrap_or_default()
+            .chunks_exact(4)
+            .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect())
+    }
+}
+
+/// Owns the clipboard selection on behalf of [`X11Clipboard::write`] and
+/// counts selection changes reported by XFixes.
+struct Owner {
+    endpoint: Endpoint,
+    content: Mutex<ClipboardContent>,
+    change_count: AtomicI64,
+    own_writes: OwnWrites,
+    /// The change count of the latest own write, which
+    /// [`X11Clipboard::write`] waits for.
+    last_own_write: Mutex<i64>,
+    own_write_counted: Condvar,
+}
+
+impl Owner {
+    fn serve(&self) {
+        loop {
+            let event = match self.endpoint.conn.wait_for_event() {
+                Ok(event) => event,
+                Err(e) => {
+                    log::error!("X11 clipboard connection closed: {}", e);
+                    return;
+                }
+            };
+
+            match event {
+                Event::XfixesSelectionNotify(e) => {
+                    // Noted before the count moves, so the watcher never sees
+                    // the new count without knowing it is ours
+                    let change_count = self.change_count.load(Ordering::SeqCst) + 1;
+                    let own = e.owner == self.endpoint.window;
+                    if own {
+                        self.own_writes.note(change_count);
+                    }
+                    self.change_count.store(change_count, Ordering::SeqCst);
+                    if own {
+                        *self.last_own_write.lock().unwrap() = change_count;
+                        self.own_write_counted.notify_all();
+                    }
+                }
+                Event::SelectionRequest(request) => {
+                    if let Err(e) = self.answer(&request) {
+                        log::error!("Failed to answer selection request: {}", e);
+                    }
+                }
+                Event::SelectionClear(_) => {
+                    *self.content.lock().unwrap() = ClipboardContent::default();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn answer(&self, request: &SelectionRequestEvent) -> Result<()> {
+        let conn = &self.endpoint.conn;
+        let atoms = &self.endpoint.atoms;
+        let content = self.content.lock().unwrap().clone();
+
+        // Obsolete clients pass no property and expect the target to be used
+        let property = if request.property == NONE {
+            request.target
+        } else {
+            request.property
+        };
+        let max_bytes = conn.maximum_request_bytes().saturating_sub(64);
+
+        let uri_list = to_uri_list(&content.files);
+        let gnome_copied_files = format!("copy\n{}", uri_list.replace("\r\n", "\n"));
+
+        let stored = if request.target == atoms.TARGETS {
+            let mut targets = vec![atoms.TARGETS];
+            if content.text.is_some() {
+                targets.extend(self.endpoint.text_targets());
+            }
+            if content.html.is_some() {
+                targets.push(atoms.TEXT_HTML);
+            }
+            if content.rtf.is_some() {
+                targets.extend(self.endpoint.rtf_targets());
+            }
+            if content.png.is_some() {
+                targets.push(atoms.IMAGE_PNG);
+            }
+            if !content.files.is_empty() {
+                targets.extend([atoms.TEXT_URI_LIST, atoms.GNOME_COPIED_FILES]);
+            }
+            if content.sensitive {
+                targets.push(atoms.PASSWORD_MANAGER_HINT);
+            }
+            conn.change_property32(
+                PropMode::REPLACE,
+                request.requestor,
+                property,
+                AtomEnum::ATOM,
+                &targets,
+            )
+            .map_err(x11_error)?;
+            true
+        } else {
+            let data = if self.endpoint.text_targets().contains(&request.target) {
+                content.text.as_deref().map(str::as_bytes)
+            } else if request.target == atoms.TEXT_HTML {
+                content.html.as_deref().map(str::as_bytes)
+            } else if self.endpoint.rtf_targets().contains(&request.target) {
+                content.rtf.as_deref().map(str::as_bytes)
+            } else if request.target == atoms.IMAGE_PNG {
+                content.png.as_deref()
+            } else if request.target == atoms.TEXT_URI_LIST && !content.files.is_empty() {
+                Some(uri_list.as_bytes())
+            } else if request.target == atoms.GNOME_COPIED_FILES && !content.files.is_empty() {
+                Some(gnome_copied_files.trim_end().as_bytes())
+            } else if request.target == atoms.PASSWORD_MANAGER_HINT && content.sensitive {
+                Some(b"secret".as_slice())
+            } else {
+                None
+            };
+
+            match data {
+                // Larger transfers would need the INCR protocol, which is not
+                // served; BIG-REQUESTS already covers typical screenshots.
+                Some(bytes) if bytes.len() <= max_bytes => {
+                    conn.change_property8(
+                        PropMode::REPLACE,
+                        request.requestor,
+                        property,
+                        request.target,
+                        bytes,
+                    )
+                    .map_err(x11_error)?;
+                    true
+                }
+                _ => false,
+            }
+        };
+
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: request.time,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property: if stored { property } else { NONE },
+        };
+        conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify)
+            .map_err(x11_error)?;
+        conn.flush().map_err(x11_error)?;
+        Ok(())
+    }
+}
+
+/// The X11 `CLIPBOARD` selection.
+///
+/// Uses one connection to read and a second one, served by a background
+/// thread, to own the selection after a write. Change detection relies on the
+/// XFixes extension, which Xvfb provides.
+pub struct X11Clipboard {
+    reader: Mutex<Endpoint>,
+    owner: Arc<Owner>,
+}
+
+impl X11Clipboard {
+    /// Connects to the display named by `$DISPLAY`.
+    pub fn new() -> Result<Self> {
+        let reader = Endpoint::connect()?;
+        let endpoint = Endpoint::connect()?;
+
+        endpoint
+            .conn
+            .xfixes_query_version(5, 0)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        endpoint
+            .conn
+            .xfixes_select_selection_input(
+                endpoint.window,
+                endpoint.atoms.CLIPBOARD,
+                SelectionEventMask::SET_SELECTION_OWNER
+                    | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                    | SelectionEventMask::SELECTION_CLIENT_CLOSE,
+            )
+            .map_err(x11_error)?;
+        endpoint.conn.flush().map_err(x11_error)?;
+
+        let owner = Arc::new(Owner {
+            endpoint,
+            content: Mutex::new(ClipboardContent::default()),
+            change_count: AtomicI64::new(0),
+            own_writes: OwnWrites::default(),
+            last_own_write: Mutex::new(0),
+            own_write_counted: Condvar::new(),
+        });
+        let serving = owner.clone();
+        thread::spawn(move || serving.serve());
+
+        Ok(Self {
+            reader: Mutex::new(reader),
+            owner,
+        })
+    }
+}
+
+impl ClipboardBackend for X11Clipboard {
+    fn change_count(&self) -> i64 {
+        self.owner.change_count.load(Ordering::SeqCst)
+    }
+
+    fn read(&self) -> Result<ClipboardContent> {
+        let reader = self.reader.lock().unwrap();
+
+        let owner = reader
+            .conn
+            .get_selection_owner(reader.atoms.CLIPBOARD)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?
+            .owner;
+        if owner == NONE {
+            return Ok(ClipboardContent::default());
+        }
+
+        let targets = reader.targets()?;
+        let mut content = ClipboardContent {
+            sensitive: targets.contains(&reader.atoms.PASSWORD_MANAGER_HINT),
+            ..ClipboardContent::default()
+        };
+
+        if targets.contains(&reader.atoms.IMAGE_PNG) {
+            content.png = reader.convert(reader.atoms.IMAGE_PNG)?;
+        }
+
+        if let Some(target) = reader
+            .text_targets()
+            .into_iter()
+            .find(|target| targets.contains(target))
+        {
+            content.text = reader
+                .convert(target)?
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+        }
+
+        if targets.contains(&reader.atoms.TEXT_HTML) {
+            content.html = reader
+                .convert(reader.atoms.TEXT_HTML)?
+                .map(|bytes| decode_html(&bytes));
+        }
+
+        if let Some(target) = reader
+            .rtf_targets()
+            .into_iter()
+            .find(|target| targets.contains(target))
+        {
+            content.rtf = reader
+                .convert(target)?
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+        }
+
+        if targets.contains(&reader.atoms.TEXT_URI_LIST) {
+            if let Some(bytes) = reader.convert(reader.atoms.TEXT_URI_LIST)? {
+                content.files = parse_uri_list(&String::from_utf8_lossy(&bytes));
+            }
+        } else if targets.contains(&reader.atoms.GNOME_COPIED_FILES) {
+            if let Some(bytes) = reader.convert(reader.atoms.GNOME_COPIED_FILES)? {
+                content.files = parse_gnome_copied_files(&String::from_utf8_lossy(&bytes));
+            }
+        }
+
+        Ok(content)
+    }
+
+    /// The selection change is counted by the owner thread once XFixes
+    /// reports it, so this waits for that before returning the count.
+    fn write(&self, content: &ClipboardContent) -> Result<i64> {
+        let endpoint = &self.owner.endpoint;
+        *self.owner.content.lock().unwrap() = content.clone();
+        let previous = *self.owner.last_own_write.lock().unwrap();
+
+        endpoint
+            .conn
+            .set_selection_owner(endpoint.window, endpoint.atoms.CLIPBOARD, CURRENT_TIME)
+            .map_err(x11_error)?;
+        let owner = endpoint
+            .conn
+            .get_selection_owner(endpoint.atoms.CLIPBOARD)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?
+            .owner;
+
+        if owner != endpoint.window {
+            return Err(Error::Clipboard(
+                "failed to take clipboard ownership".into(),
+            ));
+        }
+
+        let (last_own_write, timeout) = self
+            .owner
+            .own_write_counted
+            .wait_timeout_while(
+                self.owner.last_own_write.lock().unwrap(),
+                WRITE_TIMEOUT,
+                |last_own_write| *last_own_write == previous,
+            )
+            .unwrap();
+        if timeout.timed_out() {
+            // The owner thread will count it as the next change
+            log::warn!("Clipboard write was not reported by XFixes in time");
+            return Ok(self.change_count() + 1);
+        }
+        Ok(*last_own_write)
+    }
+
+    fn is_own_write(&self, change_count: i64) -> bool {
+        self.owner.own_writes.contains(change_count)
+    }
+}
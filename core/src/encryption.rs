@@ -0,0 +1,351 @@
+//! Encryption at rest for the history database and image blobs.
+//!
+//! Data is sealed with XChaCha20-Poly1305 under a 256-bit key. The key is
+//! derived from a passphrase with Argon2id, or generated once and kept in the
+//! macOS Keychain or in a key file. An [`EncryptionConfig`] in the settings
+//! records where the key comes from and holds a verifier sealed with it, so
+//! a wrong key is reported as such rather than as corrupt data.
+
+use crate::error::{Error, Result};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+pub const KEY_FILE: &str = "history.key";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const SALT_LEN: usize = 16;
+const VERIFIER: &[u8] = b"macopy-history";
+
+#[cfg(target_os = "macos")]
+const KEYCHAIN_SERVICE: &str = "Macopy";
+#[cfg(target_os = "macos")]
+const KEYCHAIN_ACCOUNT: &str = "history-encryption-key";
+/// `errSecItemNotFound`
+#[cfg(target_os = "macos")]
+const KEYCHAIN_ITEM_NOT_FOUND: i32 = -25300;
+
+/// Authenticated cipher for data at rest. Sealed data is the random nonce
+/// followed by the ciphertext.
+#[derive(Clone)]
+pub struct Cipher(XChaCha20Poly1305);
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cipher(..)")
+    }
+}
+
+impl Cipher {
+    pub fn new(key: &[u8; KEY_LEN]) -> Self {
+        Self(XChaCha20Poly1305::new(key.into()))
+    }
+
+    pub fn generate_key() -> [u8; KEY_LEN] {
+        XChaCha20Poly1305::generate_key(&mut OsRng).into()
+    }
+
+    /// Derives the key from a passphrase with Argon2id.
+    pub fn from_passphrase(passphrase: &str, salt: &[u8]) -> Result<Self> {
+        let mut key = [0; KEY_LEN];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| Error::Encryption(e.to_string()))?;
+        Ok(Self::new(&key))
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext)
+            .map_err(|e| Error::Encryption(e.to_string()))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    /// Fails with [`Error::WrongKey`] if the data was sealed with another
+    /// key or has been tampered with.
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(Error::Encryption("sealed data is truncated".to_string()));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.0
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::WrongKey)
+    }
+}
+
+/// How data is protected at rest.
+#[derive(Debug, Clone, Default)]
+pub enum Protection {
+    #[default]
+    Plaintext,
+    /// Encryption is on but the key has not been provided yet. Reads and
+    /// writes fail with [`Error::Locked`].
+    Locked,
+    Encrypted(Cipher),
+}
+
+impl Protection {
+    pub fn is_locked(&self) -> bool {
+        matches!(self, Protection::Locked)
+    }
+
+    pub(crate) fn cipher(&self) -> Result<Option<&Cipher>> {
+        match self {
+            Protection::Plaintext => Ok(None),
+            Protection::Locked => Err(Error::Locked),
+            Protection::Encrypted(cipher) => Ok(Some(cipher)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeySource {
+    /// Derived from a passphrase the user enters at startup.
+    Passphrase,
+    /// A random key in the macOS Keychain.
+    Keychain,
+    /// A random key in [`KEY_FILE`] in the app data directory, for platforms
+    /// without a keychain.
+    KeyFile,
+}
+
+/// Saved in the settings while encryption is on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionConfig {
+    pub key_source: KeySource,
+    /// Argon2 salt for passphrase keys, base64-encoded.
+    pub salt: Option<String>,
+    /// A known value sealed with the key, base64-encoded.
+    pub verifier: String,
+}
+
+impl EncryptionConfig {
+    pub fn new(key_source: KeySource, cipher: &Cipher, salt: Option<&[u8]>) -> Result<Self> {
+        Ok(Self {
+            key_source,
+            salt: salt.map(|salt| BASE64.encode(salt)),
+            verifier: BASE64.encode(cipher.seal(VERIFIER)?),
+        })
+    }
+
+    /// Fails with [`Error::WrongKey`] unless `cipher` uses the configured key.
+    pub fn verify(&self, cipher: &Cipher) -> Result<()> {
+        let sealed = BASE64
+            .decode(&self.verifier)
+            .map_err(|e| Error::Encryption(format!("invalid verifier: {}", e)))?;
+        match cipher.open(&sealed)? {
+            verifier if verifier == VERIFIER => Ok(()),
+            _ => Err(Error::WrongKey),
+        }
+    }
+
+    /// Derives the cipher for a passphrase key and checks it.
+    pub fn unlock_with_passphrase(&self, passphrase: &str) -> Result<Cipher> {
+        let salt = self
+            .salt
+            .as_deref()
+            .ok_or_else(|| Error::Encryption("missing passphrase salt".to_string()))?;
+        let salt = BASE64
+            .decode(salt)
+            .map_err(|e| Error::Encryption(format!("invalid salt: {}", e)))?;
+
+        let cipher = Cipher::from_passphrase(passphrase, &salt)?;
+        self.verify(&cipher)?;
+        Ok(cipher)
+    }
+}
+
+/// Sets up a new passphrase key.
+pub fn create_passphrase_key(passphrase: &str) -> Result<(Cipher, EncryptionConfig)> {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+
+    let cipher = Cipher::from_passphrase(passphrase, &salt)?;
+    let config = EncryptionConfig::new(KeySource::Passphrase, &cipher, Some(&salt))?;
+    Ok((cipher, config))
+}
+
+/// Reads the key from the Keychain, creating it when `create` is set and
+/// none exists yet.
+#[cfg(target_os = "macos")]
+pub fn keychain_key(create: bool) -> Result<Cipher> {
+    use security_framework::passwords::{get_generic_password, set_generic_password};
+
+    match get_generic_password(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT) {
+        Ok(key) => decode_key(&key),
+        Err(e) if create && e.code() == KEYCHAIN_ITEM_NOT_FOUND => {
+            let key = Cipher::generate_key();
+            set_generic_password(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT, &key)
+                .map_err(|e| Error::Encryption(format!("cannot save key to Keychain: {}", e)))?;
+            Ok(Cipher::new(&key))
+        }
+        Err(e) => Err(Error::Encryption(format!(
+            "cannot read key from Keychain: {}",
+            e
+        ))),
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn keychain_key(_create: bool) -> Result<Cipher> {
+    Err(Error::Encryption(
+        "the Keychain is not available on this platform".to_string(),
+    ))
+}
+
+/// Reads the key from `path`, creating the file when `create` is set and it
+/// does not exist yet. The file is only readable by the current user.
+pub fn key_file_key(path: &Path, create: bool) -> Result<Cipher> {
+    match fs::read(path) {
+        Ok(key) => decode_key(&key),
+        Err(e) if create && e.kind() == std::io::ErrorKind::NotFound => {
+            let key = Cipher::generate_key();
+            write_private(path, &key)?;
+            Ok(Cipher::new(&key))
+        }
+        Err(e) => Err(Error::Encryption(format!(
+            "cannot read key file {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+fn decode_key(key: &[u8]) -> Result<Cipher> {
+    let key: &[u8; KEY_LEN] = key
+        .try_into()
+        .map_err(|_| Error::Encryption("stored key has the wrong length".to_string()))?;
+    Ok(Cipher::new(key))
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(bytes)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    Ok(fs::write(path, bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_cipher() -> Cipher {
+        Cipher::new(&Cipher::generate_key())
+    }
+
+    #[test]
+    fn sealed_data_opens_with_the_same_key() {
+        let cipher = random_cipher();
+        let sealed = cipher.seal(b"secret").unwrap();
+
+        assert!(!sealed.windows(6).any(|window| window == b"secret"));
+        assert_eq!(cipher.open(&sealed).unwrap(), b"secret");
+        // A fresh nonce every time
+        assert_ne!(cipher.seal(b"secret").unwrap(), sealed);
+    }
+
+    #[test]
+    fn other_keys_and_tampering_are_wrong_keys() {
+        let cipher = random_cipher();
+        let mut sealed = cipher.seal(b"secret").unwrap();
+
+        assert!(matches!(
+            random_cipher().open(&sealed),
+            Err(Error::WrongKey)
+        ));
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(matches!(cipher.open(&sealed), Err(Error::WrongKey)));
+        assert!(matches!(
+            cipher.open(&sealed[..NONCE_LEN - 1]),
+            Err(Error::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn config_verifies_its_key() {
+        let cipher = random_cipher();
+        let config = EncryptionConfig::new(KeySource::KeyFile, &cipher, None).unwrap();
+
+        config.verify(&cipher).unwrap();
+        assert!(matches!(
+            config.verify(&random_cipher()),
+            Err(Error::WrongKey)
+        ));
+
+        let corrupt = EncryptionConfig {
+            verifier: "not base64!".to_string(),
+            ..config
+        };
+        assert!(matches!(corrupt.verify(&cipher), Err(Error::Encryption(_))));
+    }
+
+    #[test]
+    fn unlocks_with_the_right_passphrase_only() {
+        let (cipher, config) = create_passphrase_key("correct horse").unwrap();
+        assert_eq!(config.key_source, KeySource::Passphrase);
+
+        let unlocked = config.unlock_with_passphrase("correct horse").unwrap();
+        let sealed = cipher.seal(b"history").unwrap();
+        assert_eq!(unlocked.open(&sealed).unwrap(), b"history");
+
+        assert!(matches!(
+            config.unlock_with_passphrase("battery staple"),
+            Err(Error::WrongKey)
+        ));
+        let without_salt = EncryptionConfig {
+            salt: None,
+            ..config
+        };
+        assert!(matches!(
+            without_salt.unlock_with_passphrase("correct horse"),
+            Err(Error::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn key_file_is_created_once() {
+        let dir = std::env::temp_dir().join(format!("macopy-key-file-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(KEY_FILE);
+
+        assert!(key_file_key(&path, false).is_err());
+        let created = key_file_key(&path, true).unwrap();
+        let read = key_file_key(&path, false).unwrap();
+        assert_eq!(read.open(&created.seal(b"x").unwrap()).unwrap(), b"x");
+
+        fs::write(&path, b"short").unwrap();
+        assert!(matches!(
+            key_file_key(&path, true),
+            Err(Error::Encryption(_))
+        ));
+    }
+}
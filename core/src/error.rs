@@ -0,0 +1,35 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("clipboard error: {0}")]
+    Clipboard(String),
+    #[error("paste failed: {0}")]
+    Paste(String),
+    #[error("store error: {0}")]
+    Store(String),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("blob store error: {0}")]
+    Blob(String),
+    #[error("invalid search query: {0}")]
+    InvalidQuery(String),
+    #[error("invalid pattern: {0}")]
+    InvalidPattern(String),
+    #[error("invalid shortcut {0}")]
+    InvalidShortcut(String),
+    #[error("shortcut conflict: {0}")]
+    ShortcutConflict(String),
+    #[error("encryption error: {0}")]
+    Encryption(String),
+    #[error("wrong encryption key: the history cannot be decrypted with it")]
+    WrongKey,
+    #[error("the history is encrypted and has not been unlocked")]
+    Locked,
+    #[error("image error: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
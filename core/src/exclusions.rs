@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+/// The application a clipboard change came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppInfo {
+    pub name: String,
+    pub bundle_id: Option<String>,
+}
+
+impl AppInfo {
+    pub fn new(name: impl Into<String>, bundle_id: Option<String>) -> Self {
+        Self {
+            name: name.into(),
+            bundle_id,
+        }
+    }
+}
+
+/// Applications whose clipboard changes are never recorded, identified by
+/// name or bundle ID. Matching ignores ASCII case.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ExcludedApps(Vec<String>);
+
+impl ExcludedApps {
+    pub fn new(apps: Vec<String>) -> Self {
+        let mut excluded = Self::default();
+        for app in apps {
+            excluded.add(&app);
+        }
+        excluded
+    }
+
+    pub fn apps(&self) -> &[String] {
+        &self.0
+    }
+
+    /// Returns `false` if the app was already excluded or the name is blank.
+    pub fn add(&mut self, app: &str) -> bool {
+        let app = app.trim();
+        if app.is_empty() || self.contains(app) {
+            return false;
+        }
+        self.0.push(app.to_string());
+        true
+    }
+
+    pub fn remove(&mut self, app: &str) -> bool {
+        let len = self.0.len();
+        self.0
+            .retain(|excluded| !excluded.eq_ignore_ascii_case(app.trim()));
+        self.0.len() != len
+    }
+
+    pub fn contains(&self, app: &str) -> bool {
+        self.0
+            .iter()
+            .any(|excluded| excluded.eq_ignore_ascii_case(app))
+    }
+
+    pub fn is_excluded(&self, app: &AppInfo) -> bool {
+        self.contains(&app.name) || app.bundle_id.as_deref().is_some_and(|id| self.contains(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_each_app_once_ignoring_case_and_blanks() {
+        let mut excluded = ExcludedApps::new(vec![
+            " 1Password ".to_string(),
+            "1password".to_string(),
+            "  ".to_string(),
+        ]);
+        assert_eq!(excluded.apps(), ["1Password"]);
+        assert!(!excluded.add("1PASSWORD"));
+        assert!(excluded.add("com.apple.keychainaccess"));
+        assert_eq!(excluded.apps().len(), 2);
+    }
+
+    #[test]
+    fn removes_apps_ignoring_case() {
+        let mut excluded = ExcludedApps::new(vec!["Terminal".to_string()]);
+        assert!(!excluded.remove("Finder"));
+        assert!(excluded.remove(" terminal "));
+        assert!(excluded.apps().is_empty());
+    }
+
+    #[test]
+    fn matches_apps_by_name_or_bundle_id() {
+        let excluded = ExcludedApps::new(vec![
+            "KeePassXC".to_string(),
+            "com.agilebits.onepassword7".to_string(),
+        ]);
+        assert!(excluded.is_excluded(&AppInfo::new("keepassxc", None)));
+        assert!(excluded.is_excluded(&AppInfo::new(
+            "1Password 7",
+            Some("com.agilebits.onePassword7".to_string())
+        )));
+        assert!(!excluded.is_excluded(&AppInfo::new(
+            "Safari",
+            Some("com.apple.Safari".to_string())
+        )));
+    }
+}
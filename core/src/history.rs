@@ -0,0 +1,849 @@
+use crate::item::HistoryItem;
+use crate::sensitive::MASK;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_MAX_HISTORY_ITEMS: usize = 10;
+
+/// Capacity rules applied whenever the history grows. Pinned entries are
+/// not counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryLimits {
+    pub max_items: usize,
+    /// Maximum number of image entries, unlimited when `None`.
+    pub max_images: Option<usize>,
+    /// Budget for the combined size of all entries, unlimited when `None`.
+    pub max_total_bytes: Option<u64>,
+    /// Items larger than this are not recorded at all.
+    pub max_item_bytes: Option<u64>,
+}
+
+impl Default for HistoryLimits {
+    fn default() -> Self {
+        Self {
+            max_items: DEFAULT_MAX_HISTORY_ITEMS,
+            max_images: None,
+            max_total_bytes: None,
+            max_item_bytes: None,
+        }
+    }
+}
+
+pub type EntryId = u64;
+
+/// A recorded clipboard item and its metadata. Times are in milliseconds
+/// since the Unix epoch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub id: EntryId,
+    pub created_at: i64,
+    /// When the item was last copied, which orders the history. Copying an
+    /// item again moves its entry to the top instead of adding a new one.
+    pub last_copied_at: i64,
+    pub item: HistoryItem,
+    /// The application that was frontmost when the item was copied.
+    pub source_app: Option<String>,
+    /// When the item was last pasted from the history.
+    pub last_used_at: Option<i64>,
+    pub paste_count: u32,
+    /// Pinned entries are never evicted and do not count towards the limits.
+    pub pinned: bool,
+    /// Flagged by [`crate::sensitive`]; the content is masked for display.
+    pub sensitive: bool,
+    /// When the entry is deleted by [`History::expire`], if ever.
+    pub expires_at: Option<i64>,
+}
+
+impl Entry {
+    pub fn new(id: EntryId, created_at: i64, item: HistoryItem) -> Self {
+        Self {
+            id,
+            created_at,
+            last_copied_at: created_at,
+            item,
+            source_app: None,
+            last_used_at: None,
+            paste_count: 0,
+            pinned: false,
+            sensitive: false,
+            expires_at: None,
+        }
+    }
+
+    pub fn size_bytes(&self) -> u64 {
+        self.item.size_bytes()
+    }
+
+    /// The label shown in the tray menu.
+    pub fn tray_label(&self) -> String {
+        if self.sensitive {
+            MASK.to_string()
+        } else {
+            self.item.tray_label()
+        }
+    }
+
+    /// The shape sent to the frontend, matching `HistoryItem` in
+    /// `shared/types.ts`. Images carry their content hash; the bytes are
+    /// served through [`crate::protocol`]. Sensitive text never leaves the
+    /// backend.
+    pub fn to_frontend_format(&self) -> serde_json::Value {
+        let mut value = self.item.to_frontend_format();
+        if self.sensitive {
+            value["content"] = MASK.into();
+        }
+        value["id"] = self.id.into();
+        value["createdAt"] = self.created_at.into();
+        value["lastCopiedAt"] = self.last_copied_at.into();
+        value["sourceApp"] = self.source_app.clone().into();
+        value["size"] = self.size_bytes().into();
+        value["lastUsedAt"] = self.last_used_at.into();
+        value["pasteCount"] = self.paste_count.into();
+        value["pinned"] = self.pinned.into();
+        value["sensitive"] = self.sensitive.into();
+        value["expiresAt"] = self.expires_at.into();
+        value
+    }
+}
+
+/// A newly copied item and what is known about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub item: HistoryItem,
+    pub source_app: Option<String>,
+    pub sensitive: bool,
+    pub expires_at: Option<i64>,
+}
+
+impl Capture {
+    pub fn new(item: HistoryItem) -> Self {
+        Self {
+            item,
+            source_app: None,
+            sensitive: false,
+            expires_at: None,
+        }
+    }
+}
+
+/// What changed in the history, so stores can persist it incrementally.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryDiff {
+    pub inserted: Vec<Entry>,
+    /// Existing entries whose metadata changed.
+    pub updated: Vec<Entry>,
+    pub removed: Vec<Entry>,
+}
+
+impl HistoryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+pub(crate) fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// Clipboard history, most recently copied entry first.
+///
+/// Every item appears at most once; entries are keyed by
+/// [`HistoryItem::dedupe_key`].
+#[derive(Debug, Clone)]
+pub struct History {
+    entries: Vec<Entry>,
+    limits: HistoryLimits,
+    ignore_whitespace: bool,
+    keys: HashMap<String, EntryId>,
+    next_id: EntryId,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(HistoryLimits::default())
+    }
+}
+
+impl History {
+    pub fn new(limits: HistoryLimits) -> Self {
+        Self {
+            entries: Vec::new(),
+            limits,
+            ignore_whitespace: false,
+            keys: HashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Entry> {
+        self.entries.get(index)
+    }
+
+    pub fn pinned(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|entry| entry.pinned)
+    }
+
+    /// Entries that are not pinned, newest first.
+    pub fn recent(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|entry| !entry.pinned)
+    }
+
+    pub fn find(&self, id: EntryId) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn limits(&self) -> HistoryLimits {
+        self.limits
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.entries
+            .iter()
+            .map(|entry| entry.item.size_bytes())
+            .sum()
+    }
+
+    /// Changes the limits, evicting whatever no longer fits.
+    pub fn set_limits(&mut self, limits: HistoryLimits) -> HistoryDiff {
+        self.limits = limits;
+        HistoryDiff {
+            removed: self.enforce_limits(),
+            ..HistoryDiff::default()
+        }
+    }
+
+    pub fn ignores_whitespace(&self) -> bool {
+        self.ignore_whitespace
+    }
+
+    /// Whether texts that differ only in whitespace count as duplicates.
+    /// Turning this on merges entries that are now considered the same.
+    pub fn set_ignore_whitespace(&mut self, ignore_whitespace: bool) -> HistoryDiff {
+        self.ignore_whitespace = ignore_whitespace;
+        let mut diff = self.rebuild_keys();
+        diff.removed.extend(self.enforce_limits());
+        diff
+    }
+
+    /// Replaces the whole history, e.g. after loading it from a store.
+    /// Duplicates and entries that exceed the limits are removed and
+    /// reported.
+    pub fn replace(&mut self, mut entries: Vec<Entry>) -> HistoryDiff {
+        self.next_id = entries.iter().map(|entry| entry.id + 1).max().unwrap_or(1);
+        entries.sort_by_key(|entry| Reverse((entry.last_copied_at, entry.id)));
+        self.entries = entries;
+
+        let mut diff = self.rebuild_keys();
+        diff.removed.extend(self.enforce_limits());
+        diff
+    }
+
+    /// Re-indexes every entry, merging duplicates into the most recently
+    /// copied one. A merged entry stays pinned if any of its duplicates was
+    /// and keeps the formatting of a formatted duplicate.
+    fn rebuild_keys(&mut self) -> HistoryDiff {
+        let mut diff = HistoryDiff::default();
+        self.keys.clear();
+
+        let mut index = 0;
+        while index < self.entries.len() {
+            let key = self.entries[index].item.dedupe_key(self.ignore_whitespace);
+            match self.keys.get(&key).copied() {
+                Some(kept_id) => {
+                    let duplicate = self.entries.remove(index);
+                    if duplicate.pinned {
+                        self.update_entry(&mut diff, kept_id, |entry| entry.pinned = true);
+                    }
+                    let kept_is_plain = self.entries.iter().any(|entry| {
+                        entry.id == kept_id && matches!(entry.item, HistoryItem::Text(_))
+                    });
+                    if kept_is_plain && matches!(duplicate.item, HistoryItem::Rich(_)) {
+                        self.update_entry(&mut diff, kept_id, |entry| {
+                            entry.item = duplicate.item.clone()
+                        });
+                    }
+                    diff.removed.push(duplicate);
+                }
+                None => {
+                    self.keys.insert(key, self.entries[index].id);
+                    index += 1;
+                }
+            }
+        }
+
+        diff
+    }
+
+    fn update_entry(&mut self, diff: &mut HistoryDiff, id: EntryId, update: impl Fn(&mut Entry)) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.id == id) {
+            update(entry);
+            diff.updated.retain(|updated| updated.id != id);
+            diff.updated.push(entry.clone());
+        }
+    }
+
+    /// Records a newly copied item at the top of the history. If the item is
+    /// already in the history, its entry moves to the top and keeps its ID
+    /// and metadata.
+    ///
+    /// Returns an empty diff when nothing changed, either because the item is
+    /// already the latest entry or because it exceeds the size limits.
+    pub fn record(&mut self, capture: Capture) -> HistoryDiff {
+        self.record_at(capture, now_millis())
+    }
+
+    pub fn record_at(&mut self, capture: Capture, copied_at: i64) -> HistoryDiff {
+        let key = capture.item.dedupe_key(self.ignore_whitespace);
+        if let Some(&id) = self.keys.get(&key) {
+            return self.move_to_top(id, capture, copied_at);
+        }
+
+        let Capture {
+            item,
+            source_app,
+            sensitive,
+            expires_at,
+        } = capture;
+
+        if let Some(max_item_bytes) = self.limits.max_item_bytes {
+            if item.size_bytes() > max_item_bytes {
+                return HistoryDiff::default();
+            }
+        }
+
+        if item.is_image() && self.limits.max_images == Some(0) {
+            return HistoryDiff::default();
+        }
+
+        let entry = Entry {
+            source_app,
+            sensitive,
+            expires_at,
+            ..Entry::new(self.next_id, copied_at, item)
+        };
+        self.next_id += 1;
+        self.keys.insert(key, entry.id);
+        self.entries.insert(0, entry.clone());
+
+        HistoryDiff {
+            inserted: vec![entry],
+            removed: self.enforce_limits(),
+            ..HistoryDiff::default()
+        }
+    }
+
+    /// The sensitivity of the latest capture wins, so copying an expiring
+    /// item again restarts its countdown.
+    fn move_to_top(&mut self, id: EntryId, capture: Capture, copied_at: i64) -> HistoryDiff {
+        let Some(index) = self.entries.iter().position(|entry| entry.id == id) else {
+            return HistoryDiff::default();
+        };
+        // Keep the latest copy when only whitespace or formatting differs,
+        // but not at the cost of the formatting
+        let item = capture
+            .item
+            .prefer_formatted(self.entries[index].item.clone());
+        let unchanged = {
+            let latest = &self.entries[0];
+            index == 0
+                && latest.item == item
+                && latest.sensitive == capture.sensitive
+                && latest.expires_at == capture.expires_at
+        };
+        if unchanged {
+            return HistoryDiff::default();
+        }
+
+        let mut entry = self.entries.remove(index);
+        entry.item = item;
+        entry.sensitive = capture.sensitive;
+        entry.expires_at = capture.expires_at;
+        entry.last_copied_at = copied_at.max(entry.last_copied_at);
+        self.entries.insert(0, entry.clone());
+
+        let removed = self.enforce_limits();
+        HistoryDiff {
+            updated: vec![entry],
+            removed,
+            ..HistoryDiff::default()
+        }
+    }
+
+    /// Notes that the entry was pasted from the history. Its content is on
+    /// the clipboard again, so it moves to the top as if copied.
+    pub fn mark_used(&mut self, id: EntryId) -> HistoryDiff {
+        self.mark_used_at(id, now_millis())
+    }
+
+    pub fn mark_used_at(&mut self, id: EntryId, used_at: i64) -> HistoryDiff {
+        let Some(index) = self.entries.iter().position(|entry| entry.id == id) else {
+            return HistoryDiff::default();
+        };
+        let mut entry = self.entries.remove(index);
+        entry.last_used_at = Some(used_at);
+        entry.paste_count += 1;
+        entry.last_copied_at = used_at.max(entry.last_copied_at);
+        self.entries.insert(0, entry.clone());
+
+        HistoryDiff {
+            updated: vec![entry],
+            ..HistoryDiff::default()
+        }
+    }
+
+    /// Keeps the entry regardless of the limits.
+    pub fn pin(&mut self, id: EntryId) -> HistoryDiff {
+        self.set_pinned(id, true)
+    }
+
+    /// Makes the entry subject to the limits again, which may evict it or
+    /// older entries right away.
+    pub fn unpin(&mut self, id: EntryId) -> HistoryDiff {
+        self.set_pinned(id, false)
+    }
+
+    fn set_pinned(&mut self, id: EntryId, pinned: bool) -> HistoryDiff {
+        let Some(entry) = self.entries.iter_mut().find(|entry| entry.id == id) else {
+            return HistoryDiff::default();
+        };
+        if entry.pinned == pinned {
+            return HistoryDiff::default();
+        }
+        entry.pinned = pinned;
+        let entry = entry.clone();
+
+        let removed = self.enforce_limits();
+        HistoryDiff {
+            updated: if removed.iter().any(|removed| removed.id == id) {
+                Vec::new()
+            } else {
+                vec![entry]
+            },
+            removed,
+            ..HistoryDiff::default()
+        }
+    }
+
+    /// Deletes the entry, whether pinned or not.
+    pub fn remove(&mut self, id: EntryId) -> HistoryDiff {
+        let Some(index) = self.entries.iter().position(|entry| entry.id == id) else {
+            return HistoryDiff::default();
+        };
+        let entry = self.entries.remove(index);
+        self.keys.retain(|_, kept| *kept != id);
+
+        HistoryDiff {
+            removed: vec![entry],
+            ..HistoryDiff::default()
+        }
+    }
+
+    /// Deletes every entry, or every unpinned one if `keep_pinned` is set.
+    pub fn clear(&mut self, keep_pinned: bool) -> HistoryDiff {
+        let (kept, removed): (Vec<Entry>, Vec<Entry>) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|entry| keep_pinned && entry.pinned);
+        self.entries = kept;
+        self.keys
+            .retain(|_, id| !removed.iter().any(|entry| entry.id == *id));
+
+        HistoryDiff {
+            removed,
+            ..HistoryDiff::default()
+        }
+    }
+
+    /// Moves the entry to `index`, clamped to the end of the history. The
+    /// order follows `last_copied_at`, so the moved entry gets a time between
+    /// its new neighbours and entries above it are nudged up where needed.
+    pub fn move_to(&mut self, id: EntryId, index: usize) -> HistoryDiff {
+        let Some(from) = self.entries.iter().position(|entry| entry.id == id) else {
+            return HistoryDiff::default();
+        };
+        let index = index.min(self.entries.len() - 1);
+        if from == index {
+            return HistoryDiff::default();
+        }
+
+        let entry = self.entries.remove(from);
+        self.entries.insert(index, entry);
+
+        self.entries[index].last_copied_at = match self.entries.get(index + 1) {
+            Some(below) => below.last_copied_at + 1,
+            None => self.entries[index - 1].last_copied_at - 1,
+        };
+        let mut diff = HistoryDiff {
+            updated: vec![self.entries[index].clone()],
+            ..HistoryDiff::default()
+        };
+
+        for i in (0..index).rev() {
+            let below = self.entries[i + 1].last_copied_at;
+            if self.entries[i].last_copied_at > below {
+                break;
+            }
+            self.entries[i].last_copied_at = below + 1;
+            diff.updated.push(self.entries[i].clone());
+        }
+
+        diff
+    }
+
+    /// Deletes unpinned entries whose expiry time has passed.
+    pub fn expire(&mut self) -> HistoryDiff {
+        self.expire_at(now_millis())
+    }
+
+    pub fn expire_at(&mut self, now: i64) -> HistoryDiff {
+        let is_expired = |entry: &Entry| {
+            !entry.pinned && entry.expires_at.is_some_and(|expires_at| expires_at <= now)
+        };
+        if !self.entries.iter().any(is_expired) {
+            return HistoryDiff::default();
+        }
+
+        let (removed, kept) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(is_expired);
+        self.entries = kept;
+        self.keys
+            .retain(|_, id| !removed.iter().any(|entry: &Entry| entry.id == *id));
+
+        HistoryDiff {
+            removed,
+            ..HistoryDiff::default()
+        }
+    }
+
+    /// Evicts the oldest unpinned entries until every limit is met and
+    /// returns them. The newest entry is always kept.
+    fn enforce_limits(&mut self) -> Vec<Entry> {
+        let limits = self.limits;
+        let mut removed = Vec::new();
+
+        while self.recent().count() > limits.max_items.max(1) {
+            match self.evict_oldest(|_| true) {
+                Some(entry) => removed.push(entry),
+                None => break,
+            }
+        }
+
+        if let Some(max_images) = limits.max_images {
+            while self.recent().filter(|entry| entry.item.is_image()).count() > max_images {
+                match self.evict_oldest(HistoryItem::is_image) {
+                    Some(entry) => removed.push(entry),
+                    None => break,
+                }
+            }
+        }
+
+        if let Some(max_total_bytes) = limits.max_total_bytes {
+            while self.recent().map(Entry::size_bytes).sum::<u64>() > max_total_bytes {
+                match self.evict_oldest(|_| true) {
+                    Some(entry) => removed.push(entry),
+                    None => break,
+                }
+            }
+        }
+
+        removed
+    }
+
+    fn evict_oldest(&mut self, matches: impl Fn(&HistoryItem) -> bool) -> Option<Entry> {
+        let index = self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(1)
+            .rev()
+            .find(|(_, entry)| !entry.pinned && matches(&entry.item))
+            .map(|(index, _)| index)?;
+
+        let entry = self.entries.remove(index);
+        self.keys.retain(|_, id| *id != entry.id);
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::{ImageRef, RichContent};
+
+    fn text(text: &str) -> Capture {
+        Capture::new(HistoryItem::Text(text.to_string()))
+    }
+
+    fn formatted(text: &str) -> HistoryItem {
+        HistoryItem::Rich(RichContent {
+            text: text.to_string(),
+            html: Some(format!("<b>{}</b>", text)),
+            rtf: None,
+            image: None,
+        })
+    }
+
+    fn image(hash: &str, size: u64) -> Capture {
+        Capture::new(HistoryItem::Image(ImageRef {
+            hash: hash.to_string(),
+            size,
+        }))
+    }
+
+    /// Records `texts` oldest first, one millisecond apart.
+    fn history_with(limits: HistoryLimits, texts: &[&str]) -> History {
+        let mut history = History::new(limits);
+        for (time, item) in texts.iter().enumerate() {
+            history.record_at(text(item), time as i64);
+        }
+        history
+    }
+
+    fn texts(history: &History) -> Vec<&str> {
+        history
+            .entries()
+            .iter()
+            .map(|entry| entry.item.text().unwrap_or("<image>"))
+            .collect()
+    }
+
+    fn unlimited() -> HistoryLimits {
+        HistoryLimits {
+            max_items: 100,
+            ..HistoryLimits::default()
+        }
+    }
+
+    #[test]
+    fn recopying_moves_the_entry_to_the_top() {
+        let mut history = history_with(unlimited(), &["a", "b"]);
+        let id = history.entries()[1].id;
+
+        let diff = history.record_at(text("a"), 10);
+        assert!(diff.inserted.is_empty());
+        assert_eq!(diff.updated.len(), 1);
+        assert_eq!(texts(&history), ["a", "b"]);
+        assert_eq!(history.entries()[0].id, id);
+        assert_eq!(history.entries()[0].last_copied_at, 10);
+
+        // Copying the latest item again changes nothing
+        assert!(history.record_at(text("a"), 11).is_empty());
+    }
+
+    #[test]
+    fn whitespace_differences_are_kept_apart_by_default() {
+        let history = history_with(unlimited(), &["a b", "a  b\n"]);
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn ignoring_whitespace_merges_duplicates() {
+        let mut history = History::new(unlimited());
+        history.set_ignore_whitespace(true);
+        history.record_at(text("a b"), 0);
+        history.record_at(text("c"), 1);
+        history.record_at(text(" a  b\n"), 2);
+
+        // The latest copy's whitespace wins
+        assert_eq!(texts(&history), [" a  b\n", "c"]);
+    }
+
+    #[test]
+    fn formatted_copies_merge_with_plain_ones() {
+        let mut history = history_with(unlimited(), &["hello", "c"]);
+        let formatted = formatted("hello");
+        let id = history.entries()[1].id;
+
+        let diff = history.record_at(Capture::new(formatted.clone()), 2);
+        assert_eq!(diff.updated.len(), 1);
+        assert_eq!(history.len(), 2);
+        // The entry keeps its ID and takes the latest, formatted copy
+        assert_eq!(history.entries()[0].id, id);
+        assert_eq!(history.entries()[0].item, formatted);
+    }
+
+    #[test]
+    fn plain_copies_keep_the_formatting_of_an_entry() {
+        let mut history = History::new(unlimited());
+        let formatted = formatted("hello");
+        history.record_at(Capture::new(formatted.clone()), 0);
+        history.record_at(text("c"), 1);
+
+        let diff = history.record_at(text("hello"), 2);
+        assert_eq!(diff.updated.len(), 1);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.entries()[0].item, formatted);
+        assert_eq!(history.entries()[0].last_copied_at, 2);
+
+        // Copying it again while on top changes nothing
+        assert!(history.record_at(text("hello"), 3).is_empty());
+    }
+
+    #[test]
+    fn merging_duplicates_keeps_the_formatting() {
+        let mut history = History::new(unlimited());
+        history.record_at(Capture::new(formatted("a  b")), 0);
+        history.record_at(text("a b"), 1);
+        assert_eq!(history.len(), 2);
+
+        let diff = history.set_ignore_whitespace(true);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(history.entries()[0].item, formatted("a  b"));
+    }
+
+    #[test]
+    fn turning_on_ignore_whitespace_merges_existing_entries() {
+        let mut history = history_with(unlimited(), &["a b", "c", "a  b"]);
+        history.pin(history.entries()[2].id);
+
+        let diff = history.set_ignore_whitespace(true);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(texts(&history), ["a  b", "c"]);
+        // The merged entry stays pinned because a duplicate was
+        assert!(history.entries()[0].pinned);
+    }
+
+    #[test]
+    fn evicts_the_oldest_entries_beyond_max_items() {
+        let limits = HistoryLimits {
+            max_items: 2,
+            ..HistoryLimits::default()
+        };
+        let mut history = history_with(limits, &["a", "b"]);
+
+        let diff = history.record_at(text("c"), 10);
+        assert_eq!(texts(&history), ["c", "b"]);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].item.text(), Some("a"));
+    }
+
+    #[test]
+    fn pinned_entries_are_exempt_from_max_items() {
+        let limits = HistoryLimits {
+            max_items: 2,
+            ..HistoryLimits::default()
+        };
+        let mut history = history_with(limits, &["a", "b"]);
+        history.pin(history.entries()[1].id);
+
+        history.record_at(text("c"), 10);
+        history.record_at(text("d"), 11);
+        assert_eq!(texts(&history), ["d", "c", "a"]);
+
+        // Unpinning makes the entry count again
+        let diff = history.unpin(history.entries()[2].id);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(texts(&history), ["d", "c"]);
+    }
+
+    #[test]
+    fn evicts_the_oldest_entries_beyond_max_total_bytes() {
+        let limits = HistoryLimits {
+            max_total_bytes: Some(8),
+            ..unlimited()
+        };
+        let mut history = history_with(limits, &["aaaa", "bbbb"]);
+        history.pin(history.entries()[1].id);
+
+        history.record_at(text("cccc"), 10);
+        history.record_at(text("dddd"), 11);
+        // The pinned entry's bytes do not count
+        assert_eq!(texts(&history), ["dddd", "cccc", "aaaa"]);
+    }
+
+    #[test]
+    fn evicts_images_beyond_max_images() {
+        let limits = HistoryLimits {
+            max_images: Some(1),
+            ..unlimited()
+        };
+        let mut history = History::new(limits);
+        history.record_at(image("one", 10), 0);
+        history.record_at(text("a"), 1);
+        history.record_at(image("two", 10), 2);
+
+        assert_eq!(texts(&history), ["<image>", "a"]);
+    }
+
+    #[test]
+    fn skips_items_beyond_max_item_bytes() {
+        let limits = HistoryLimits {
+            max_item_bytes: Some(3),
+            ..unlimited()
+        };
+        let mut history = History::new(limits);
+
+        assert!(history.record_at(text("abcd"), 0).is_empty());
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn move_to_keeps_the_order_by_copy_time() {
+        let mut history = history_with(unlimited(), &["d", "c", "b", "a"]);
+        let id = history.entries()[3].id;
+
+        history.move_to(id, 1);
+        assert_eq!(texts(&history), ["a", "d", "b", "c"]);
+
+        // The new times put the entries in the same order after a reload
+        let mut reloaded = History::new(unlimited());
+        reloaded.replace(history.entries().to_vec());
+        assert_eq!(texts(&reloaded), ["a", "d", "b", "c"]);
+
+        history.move_to(id, 0);
+        assert_eq!(texts(&history), ["d", "a", "b", "c"]);
+        reloaded.replace(history.entries().to_vec());
+        assert_eq!(texts(&reloaded), ["d", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn mark_used_moves_the_entry_to_the_top() {
+        let mut history = history_with(unlimited(), &["a", "b", "c"]);
+        let id = history.entries()[2].id;
+
+        let diff = history.mark_used_at(id, 10);
+        assert_eq!(texts(&history), ["a", "c", "b"]);
+        assert_eq!(diff.updated.len(), 1);
+
+        let entry = &history.entries()[0];
+        assert_eq!(entry.last_used_at, Some(10));
+        assert_eq!(entry.last_copied_at, 10);
+        assert_eq!(entry.paste_count, 1);
+    }
+
+    #[test]
+    fn replace_continues_ids_after_the_highest_one() {
+        let mut history = History::new(unlimited());
+        let diff = history.replace(vec![
+            Entry::new(7, 2, HistoryItem::Text("a".to_string())),
+            Entry::new(3, 1, HistoryItem::Text("b".to_string())),
+            Entry::new(5, 0, HistoryItem::Text("a".to_string())),
+        ]);
+
+        // The older duplicate goes
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].id, 5);
+
+        let diff = history.record_at(text("c"), 10);
+        assert_eq!(diff.inserted[0].id, 8);
+        assert_eq!(texts(&history), ["c", "a", "b"]);
+    }
+}
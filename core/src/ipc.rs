@@ -0,0 +1,145 @@
+//! Errors and events exchanged with the frontend.
+
+use crate::error::Error;
+use crate::history::{Entry, EntryId, HistoryDiff};
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+/// Serialized as `{ "kind": "notFound", "message": "..." }`, so the UI can
+/// branch on `kind` and show `message`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CommandError {
+    #[error("no history item with ID {0}")]
+    NotFound(EntryId),
+    #[error("index {0} is outside the history")]
+    InvalidIndex(usize),
+    #[error("history item {0} has no text to paste")]
+    NoText(EntryId),
+    #[error("invalid search query: {0}")]
+    InvalidQuery(String),
+    #[error("the history is encrypted and has not been unlocked")]
+    Locked,
+    #[error("clipboard error: {0}")]
+    Clipboard(String),
+    #[error("paste failed: {0}")]
+    Paste(String),
+    #[error("storage error: {0}")]
+    Storage(String),
+}
+
+impl CommandError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CommandError::NotFound(_) => "notFound",
+            CommandError::InvalidIndex(_) => "invalidIndex",
+            CommandError::NoText(_) => "noText",
+            CommandError::InvalidQuery(_) => "invalidQuery",
+            CommandError::Locked => "locked",
+            CommandError::Clipboard(_) => "clipboard",
+            CommandError::Paste(_) => "paste",
+            CommandError::Storage(_) => "storage",
+        }
+    }
+}
+
+impl From<Error> for CommandError {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Clipboard(message) => CommandError::Clipboard(message),
+            Error::Paste(message) => CommandError::Paste(message),
+            Error::Locked => CommandError::Locked,
+            Error::InvalidQuery(message) | Error::InvalidPattern(message) => {
+                CommandError::InvalidQuery(message)
+            }
+            error => CommandError::Storage(error.to_string()),
+        }
+    }
+}
+
+impl Serialize for CommandError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("CommandError", 2)?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.end()
+    }
+}
+
+pub const ITEM_ADDED_EVENT: &str = "item-added";
+pub const ITEM_UPDATED_EVENT: &str = "item-updated";
+pub const ITEM_REMOVED_EVENT: &str = "item-removed";
+pub const HISTORY_RESET_EVENT: &str = "history-reset";
+
+/// A change to the history, sent to the popup so it can stay up to date
+/// without receiving the whole list. Items carry `lastCopiedAt`, which the
+/// popup sorts by.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum HistoryEvent {
+    ItemAdded(serde_json::Value),
+    ItemUpdated(serde_json::Value),
+    ItemRemoved(EntryId),
+    /// The whole history, after it was loaded or unlocked.
+    HistoryReset(Vec<serde_json::Value>),
+}
+
+impl HistoryEvent {
+    /// Removals come last, so an entry added and evicted in the same change
+    /// does not linger.
+    pub fn from_diff(diff: &HistoryDiff) -> Vec<Self> {
+        let added = diff
+            .inserted
+            .iter()
+            .map(|entry| HistoryEvent::ItemAdded(entry.to_frontend_format()));
+        let updated = diff
+            .updated
+            .iter()
+            .map(|entry| HistoryEvent::ItemUpdated(entry.to_frontend_format()));
+        let removed = diff
+            .removed
+            .iter()
+            .map(|entry| HistoryEvent::ItemRemoved(entry.id));
+
+        added.chain(updated).chain(removed).collect()
+    }
+
+    pub fn reset(entries: &[Entry]) -> Self {
+        HistoryEvent::HistoryReset(entries.iter().map(Entry::to_frontend_format).collect())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HistoryEvent::ItemAdded(_) => ITEM_ADDED_EVENT,
+            HistoryEvent::ItemUpdated(_) => ITEM_UPDATED_EVENT,
+            HistoryEvent::ItemRemoved(_) => ITEM_REMOVED_EVENT,
+            HistoryEvent::HistoryReset(_) => HISTORY_RESET_EVENT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_kind_and_message() {
+        let error = CommandError::NotFound(7);
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({ "kind": "notFound", "message": "no history item with ID 7" })
+        );
+    }
+
+    #[test]
+    fn maps_core_errors() {
+        assert_eq!(CommandError::from(Error::Locked), CommandError::Locked);
+        assert_eq!(
+            CommandError::from(Error::InvalidPattern("(".to_string())).kind(),
+            "invalidQuery"
+        );
+        assert_eq!(
+            CommandError::from(Error::Store("disk full".to_string())).kind(),
+            "storage"
+        );
+    }
+}
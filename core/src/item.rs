@@ -0,0 +1,475 @@
+use crate::blob::{content_hash, BlobStore};
+use crate::clipboard::{paths_text, ClipboardContent};
+use crate::error::Result;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+const TRAY_LABEL_MAX_CHARS: usize = 30;
+const PNG_DATA_URL_PREFIX: &str = "data:image/png;base64,";
+
+pub fn png_data_url(bytes: &[u8]) -> String {
+    format!(
+        "{}{}",
+        PNG_DATA_URL_PREFIX,
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+pub fn decode_png_data_url(data_url: &str) -> Option<Vec<u8>> {
+    let base64_data = data_url.strip_prefix(PNG_DATA_URL_PREFIX)?;
+    base64::engine::general_purpose::STANDARD
+        .decode(base64_data)
+        .ok()
+}
+
+/// A PNG image kept in the [`BlobStore`], identified by its content hash.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+pub struct ImageRef {
+    pub hash: String,
+    pub size: u64,
+}
+
+impl ImageRef {
+    /// Wraps a data URL written by versions that stored images inline.
+    pub(crate) fn inline(data_url: &str) -> Self {
+        let encoded = data_url.len().saturating_sub(PNG_DATA_URL_PREFIX.len());
+        Self {
+            hash: data_url.to_string(),
+            size: (encoded as u64) * 3 / 4,
+        }
+    }
+
+    /// Whether this still holds an inline data URL rather than a blob hash.
+    pub fn is_inline(&self) -> bool {
+        self.hash.starts_with(PNG_DATA_URL_PREFIX)
+    }
+
+    /// The hash the image is served under by [`crate::protocol`]. Inline
+    /// images are moved to the blob store on load and have none until then.
+    pub fn blob_hash(&self) -> Option<&str> {
+        (!self.is_inline()).then_some(self.hash.as_str())
+    }
+
+    pub fn load(&self, blobs: &BlobStore) -> Result<Vec<u8>> {
+        if self.is_inline() {
+            return decode_png_data_url(&self.hash)
+                .ok_or_else(|| crate::Error::Blob("invalid inline image".to_string()));
+        }
+        blobs.get(&self.hash)
+    }
+}
+
+/// Every representation of a copy from a browser, word processor or other
+/// app that offers formatted content.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct RichContent {
+    /// Plain text, empty if the source offered none.
+    pub text: String,
+    pub html: Option<String>,
+    pub rtf: Option<String>,
+    pub image: Option<ImageRef>,
+}
+
+impl RichContent {
+    /// Names of the representations besides plain text.
+    pub fn formats(&self) -> Vec<&'static str> {
+        [
+            ("html", self.html.is_some()),
+            ("rtf", self.rtf.is_some()),
+            ("image", self.image.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(format, present)| present.then_some(format))
+        .collect()
+    }
+}
+
+/// The last path component, or the whole path for roots such as `/`.
+pub fn file_name(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.display().to_string(),
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", content = "content")]
+pub enum HistoryItem {
+    #[serde(rename = "text")]
+    Text(String),
+    #[serde(rename = "image")]
+    Image(ImageRef),
+    #[serde(rename = "rich")]
+    Rich(RichContent),
+    /// Files and folders copied in a file manager, by path.
+    #[serde(rename = "files")]
+    Files(Vec<PathBuf>),
+}
+
+impl HistoryItem {
+    /// Builds an item from the legacy `{ "type", "content" }` shape, where
+    /// images are inline data URLs. Unknown types are ignored.
+    pub fn from_parts(type_str: &str, content: &str) -> Option<Self> {
+        match type_str {
+            "text" => Some(HistoryItem::Text(content.to_string())),
+            "image" => Some(HistoryItem::Image(ImageRef::inline(content))),
+            _ => None,
+        }
+    }
+
+    /// Builds an item from a database row. Image rows hold a blob hash (or a
+    /// data URL from older versions) plus the decoded size; rich and file
+    /// rows hold their content as JSON.
+    pub fn from_stored(kind: &str, content: String, size: u64) -> Option<Self> {
+        match kind {
+            "text" => Some(HistoryItem::Text(content)),
+            "rich" => serde_json::from_str(&content).ok().map(HistoryItem::Rich),
+            "files" => serde_json::from_str(&content).ok().map(HistoryItem::Files),
+            "image" if content.starts_with(PNG_DATA_URL_PREFIX) => {
+                Some(HistoryItem::Image(ImageRef::inline(&content)))
+            }
+            "image" => Some(HistoryItem::Image(ImageRef {
+                hash: content,
+                size,
+            })),
+            _ => None,
+        }
+    }
+
+    /// Splits the item into the `type` and `content` stored on disk.
+    pub fn to_parts(&self) -> (&'static str, Cow<'_, str>) {
+        match self {
+            HistoryItem::Text(content) => ("text", Cow::Borrowed(content)),
+            HistoryItem::Image(image) => ("image", Cow::Borrowed(&image.hash)),
+            HistoryItem::Rich(rich) => (
+                "rich",
+                Cow::Owned(serde_json::to_string(rich).unwrap_or_default()),
+            ),
+            HistoryItem::Files(paths) => (
+                "files",
+                Cow::Owned(serde_json::to_string(paths).unwrap_or_default()),
+            ),
+        }
+    }
+
+    /// Picks the representations worth recording. Copied files win over the
+    /// names and icons that come with them; formatted content keeps
+    /// everything the clipboard offered; otherwise images are preferred over
+    /// text. Image bytes are written to `blobs`. Returns `None` for an empty
+    /// clipboard.
+    pub fn capture(content: &ClipboardContent, blobs: &BlobStore) -> Result<Option<Self>> {
+        if !content.files.is_empty() {
+            return Ok(Some(HistoryItem::Files(content.files.clone())));
+        }
+
+        let image = match &content.png {
+            Some(png) => Some(ImageRef {
+                hash: blobs.put(png)?,
+                size: png.len() as u64,
+            }),
+            None => None,
+        };
+
+        if content.html.is_some() || content.rtf.is_some() {
+            return Ok(Some(HistoryItem::Rich(RichContent {
+                text: content.text.clone().unwrap_or_default(),
+                html: content.html.clone(),
+                rtf: content.rtf.clone(),
+                image,
+            })));
+        }
+        if let Some(image) = image {
+            return Ok(Some(HistoryItem::Image(image)));
+        }
+
+        Ok(match &content.text {
+            Some(text) if !text.is_empty() => Some(HistoryItem::Text(text.clone())),
+            _ => None,
+        })
+    }
+
+    pub fn to_clipboard(&self, blobs: &BlobStore) -> Result<ClipboardContent> {
+        match self {
+            HistoryItem::Text(content) => Ok(ClipboardContent::text(content.clone())),
+            HistoryItem::Image(image) => Ok(ClipboardContent {
+                png: Some(image.load(blobs)?),
+                ..ClipboardContent::default()
+            }),
+            // Every representation goes back, so the target can pick the richest
+            HistoryItem::Rich(rich) => Ok(ClipboardContent {
+                text: Some(rich.text.clone()).filter(|text| !text.is_empty()),
+                html: rich.html.clone(),
+                rtf: rich.rtf.clone(),
+                png: rich
+                    .image
+                    .as_ref()
+                    .map(|image| image.load(blobs))
+                    .transpose()?,
+                ..ClipboardContent::default()
+            }),
+            // Paths as text too, for apps that do not take files
+            HistoryItem::Files(paths) => Ok(ClipboardContent {
+                text: Some(paths_text(paths)),
+                files: paths.clone(),
+                ..ClipboardContent::default()
+            }),
+        }
+    }
+
+    pub fn to_frontend_format(&self) -> serde_json::Value {
+        match self {
+            HistoryItem::Text(content) => serde_json::json!({
+                "type": "text",
+                "content": content
+            }),
+            HistoryItem::Image(image) => serde_json::json!({
+                "type": "image",
+                "content": image.hash,
+                "imageHash": image.blob_hash()
+            }),
+            HistoryItem::Rich(rich) => serde_json::json!({
+                "type": "rich",
+                "content": rich.text,
+                "formats": rich.formats(),
+                "imageHash": rich.image.as_ref().and_then(ImageRef::blob_hash)
+            }),
+            HistoryItem::Files(paths) => {
+                let files: Vec<serde_json::Value> = paths
+                    .iter()
+                    .map(|path| {
+                        serde_json::json!({
+                            "path": path,
+                            "name": file_name(path),
+                            "exists": path.exists(),
+                        })
+                    })
+                    .collect();
+                serde_json::json!({
+                    "type": "files",
+                    "content": paths.first().map(|path| file_name(path)).unwrap_or_default(),
+                    "files": files
+                })
+            }
+        }
+    }
+
+    /// Identifies items with the same content. With `ignore_whitespace`,
+    /// texts that differ only in surrounding whitespace or in the length of
+    /// whitespace runs share a key.
+    ///
+    /// Formatted items are keyed by their plain text and image, so copying the
+    /// same text with and without formatting yields one entry. Only formatted
+    /// items without any text are keyed by their markup.
+    pub fn dedupe_key(&self, ignore_whitespace: bool) -> String {
+        match self {
+            HistoryItem::Text(content) => text_key(content, ignore_whitespace),
+            HistoryItem::Image(image) => image_key(image),
+            HistoryItem::Rich(rich) if rich.text.trim().is_empty() => {
+                let json = serde_json::to_string(rich).unwrap_or_default();
+                format!("rich:{}", content_hash(json.as_bytes()))
+            }
+            HistoryItem::Rich(rich) => {
+                let text = text_key(&rich.text, ignore_whitespace);
+                match &rich.image {
+                    Some(image) => format!("{}+{}", text, image_key(image)),
+                    None => text,
+                }
+            }
+            HistoryItem::Files(paths) => {
+                let json = serde_json::to_string(paths).unwrap_or_default();
+                format!("files:{}", content_hash(json.as_bytes()))
+            }
+        }
+    }
+
+    /// Size of the payload, in decoded bytes for images. Files count the
+    /// length of their paths, not their contents.
+    pub fn size_bytes(&self) -> u64 {
+        match self {
+            HistoryItem::Text(content) => content.len() as u64,
+            HistoryItem::Image(image) => image.size,
+            HistoryItem::Rich(rich) => {
+                let markup = rich.html.as_ref().map_or(0, String::len)
+                    + rich.rtf.as_ref().map_or(0, String::len);
+                (rich.text.len() + markup) as u64
+                    + rich.image.as_ref().map_or(0, |image| image.size)
+            }
+            HistoryItem::Files(paths) => {
+                paths.iter().map(|path| path.as_os_str().len() as u64).sum()
+            }
+        }
+    }
+
+    /// Of two items with the same [`dedupe_key`](Self::dedupe_key), the one
+    /// to keep: `self`, unless it is plain text and `other` has formatting,
+    /// which a plain copy of the same text must not drop.
+    pub fn prefer_formatted(self, other: HistoryItem) -> HistoryItem {
+        match (self, other) {
+            (HistoryItem::Text(_), rich @ HistoryItem::Rich(_)) => rich,
+            (item, _) => item,
+        }
+    }
+
+    /// Whether this is an image alone. Rich items count as text, even if
+    /// they include an image.
+    pub fn is_image(&self) -> bool {
+        matches!(self, HistoryItem::Image(_))
+    }
+
+    /// The plain text of text and rich items.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            HistoryItem::Text(text) => Some(text),
+            HistoryItem::Rich(rich) => Some(&rich.text),
+            HistoryItem::Image(_) | HistoryItem::Files(_) => None,
+        }
+    }
+
+    /// What pasting as plain text puts on the clipboard: the text of text
+    /// and rich items, or the paths of files. `None` for images and rich
+    /// items without text.
+    pub fn plain_text(&self) -> Option<Cow<'_, str>> {
+        match self {
+            HistoryItem::Files(paths) => Some(Cow::Owned(paths_text(paths))),
+            _ => self
+                .text()
+                .filter(|text| !text.is_empty())
+                .map(Cow::Borrowed),
+        }
+    }
+
+    /// The image of image items and of rich items that include one.
+    pub fn image(&self) -> Option<&ImageRef> {
+        match self {
+            HistoryItem::Image(image) => Some(image),
+            HistoryItem::Rich(rich) => rich.image.as_ref(),
+            HistoryItem::Text(_) | HistoryItem::Files(_) => None,
+        }
+    }
+
+    pub fn tray_label(&self) -> String {
+        match self {
+            HistoryItem::Image(_) => "[Image]".to_string(),
+            HistoryItem::Rich(rich) if rich.text.is_empty() => "[Formatted Content]".to_string(),
+            HistoryItem::Files(paths) => {
+                let Some(first) = paths.first() else {
+                    return "[Files]".to_string();
+                };
+                let mut label: String = file_name(first)
+                    .chars()
+                    .take(TRAY_LABEL_MAX_CHARS)
+                    .collect();
+                if paths.len() > 1 {
+                    label.push_str(&format!(" and {} more", paths.len() - 1));
+                }
+                // Moved or deleted since it was copied
+                if !paths.iter().all(|path| path.exists()) {
+                    label.push_str(" (missing)");
+                }
+                label
+            }
+            _ => {
+                let content = self.text().unwrap_or_default();
+                let display: String = content.chars().take(TRAY_LABEL_MAX_CHARS).collect();
+                if content.len() > TRAY_LABEL_MAX_CHARS {
+                    format!("{}...", display)
+                } else {
+                    display
+                }
+            }
+        }
+    }
+}
+
+fn text_key(text: &str, ignore_whitespace: bool) -> String {
+    if ignore_whitespace {
+        let normalized = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        format!("text:{}", content_hash(normalized.as_bytes()))
+    } else {
+        format!("text:{}", content_hash(text.as_bytes()))
+    }
+}
+
+fn image_key(image: &ImageRef) -> String {
+    if image.is_inline() {
+        format!("image:{}", content_hash(image.hash.as_bytes()))
+    } else {
+        format!("image:{}", image.hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rich(text: &str, html: &str) -> HistoryItem {
+        HistoryItem::Rich(RichContent {
+            text: text.to_string(),
+            html: Some(html.to_string()),
+            rtf: None,
+            image: None,
+        })
+    }
+
+    fn key(item: &HistoryItem, ignore_whitespace: bool) -> String {
+        item.dedupe_key(ignore_whitespace)
+    }
+
+    #[test]
+    fn formatted_and_plain_copies_share_a_key() {
+        let plain = HistoryItem::Text("hello world".to_string());
+        let bold = rich("hello world", "<b>hello world</b>");
+        let italic = rich("hello world", "<i>hello world</i>");
+
+        assert_eq!(key(&bold, false), key(&plain, false));
+        assert_eq!(key(&italic, false), key(&bold, false));
+        assert_ne!(
+            key(&rich("hello", "<b>hello</b>"), false),
+            key(&plain, false)
+        );
+    }
+
+    #[test]
+    fn formatted_copies_respect_ignore_whitespace() {
+        let plain = HistoryItem::Text("hello world".to_string());
+        let spaced = rich("  hello   world\n", "<p>hello world</p>");
+
+        assert_ne!(key(&spaced, false), key(&plain, false));
+        assert_eq!(key(&spaced, true), key(&plain, true));
+    }
+
+    #[test]
+    fn formatted_copies_include_their_image() {
+        let with_image = |hash: &str| {
+            HistoryItem::Rich(RichContent {
+                text: "caption".to_string(),
+                html: None,
+                rtf: None,
+                image: Some(ImageRef {
+                    hash: hash.to_string(),
+                    size: 1,
+                }),
+            })
+        };
+        let plain = HistoryItem::Text("caption".to_string());
+
+        assert_ne!(key(&with_image("a"), false), key(&plain, false));
+        assert_ne!(key(&with_image("a"), false), key(&with_image("b"), false));
+        assert_eq!(key(&with_image("a"), true), key(&with_image("a"), true));
+    }
+
+    #[test]
+    fn formatted_copies_without_text_are_keyed_by_markup() {
+        let table = rich("", "<table></table>");
+        let list = rich("", "<ul></ul>");
+
+        assert_ne!(key(&table, false), key(&list, false));
+        assert_ne!(
+            key(&table, true),
+            key(&HistoryItem::Text(" ".to_string()), true)
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! Global shortcuts for actions other than opening the popup.
+
+use crate::error::{Error, Result};
+use crate::history::{Entry, EntryId};
+use crate::shortcut::Accelerator;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Items reachable with [`Action::PasteItem`], matching the number keys in
+/// the popup.
+pub const MAX_ITEM_INDEX: usize = 9;
+
+/// Presses of [`Action::CycleHistory`] further apart than this start over
+/// from the newest item.
+pub const CYCLE_RESET: Duration = Duration::from_millis(1500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum Action {
+    /// Pastes the item copied before the latest one.
+    PastePrevious,
+    /// Puts older items on the clipboard one by one with repeated presses.
+    CycleHistory,
+    /// Pastes the clipboard without formatting.
+    PastePlainText,
+    /// Pastes the item with this 1-based number in the popup.
+    PasteItem { index: usize },
+    /// Pauses capture, or resumes it if paused.
+    PauseCapture,
+    /// Deletes every unpinned item.
+    ClearHistory,
+}
+
+impl Action {
+    /// What the action does, for conflict messages.
+    pub fn description(&self) -> String {
+        match self {
+            Action::PastePrevious => "paste the previous item".to_string(),
+            Action::CycleHistory => "cycle through the history".to_string(),
+            Action::PastePlainText => "paste as plain text".to_string(),
+            Action::PasteItem { index } => format!("paste item {}", index),
+            Action::PauseCapture => "pause capture".to_string(),
+            Action::ClearHistory => "clear the history".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Binding {
+    #[serde(flatten)]
+    pub action: Action,
+    pub shortcut: String,
+}
+
+impl Binding {
+    pub fn new(action: Action, shortcut: impl Into<String>) -> Self {
+        Self {
+            action,
+            shortcut: shortcut.into(),
+        }
+    }
+}
+
+/// Shortcut bindings persisted in the store. An action may have several
+/// shortcuts, but a shortcut triggers only one action.
+///
+/// Nothing is bound by default: global shortcuts take their keys away from
+/// every other app, and combinations such as Option+digit type characters on
+/// many keyboard layouts. Users bind the actions they want in the settings,
+/// or turn on the Alt+1…9 [`item_bindings`] from the tray menu.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Keymap {
+    /// Validates every binding and stores its shortcut in canonical form.
+    /// Fails on invalid shortcuts and on shortcuts that are reserved, bound
+    /// twice or equal to `popup_shortcut`.
+    pub fn new(bindings: Vec<Binding>, popup_shortcut: &Accelerator) -> Result<Self> {
+        let mut keymap = Self {
+            bindings: Vec::with_capacity(bindings.len()),
+        };
+        for binding in bindings {
+            keymap.bind(binding, popup_shortcut)?;
+        }
+        Ok(keymap)
+    }
+
+    /// Like [`new`](Self::new), but drops invalid bindings with a warning.
+    /// Used for keymaps loaded from disk.
+    pub fn new_lossy(bindings: Vec<Binding>, popup_shortcut: &Accelerator) -> Self {
+        let mut keymap = Self {
+            bindings: Vec::with_capacity(bindings.len()),
+        };
+        for binding in bindings {
+            if let Err(e) = keymap.bind(binding, popup_shortcut) {
+                log::warn!("Ignoring shortcut binding: {}", e);
+            }
+        }
+        keymap
+    }
+
+    fn bind(&mut self, binding: Binding, popup_shortcut: &Accelerator) -> Result<()> {
+        if let Action::PasteItem { index } = binding.action {
+            if !(1..=MAX_ITEM_INDEX).contains(&index) {
+                return Err(Error::InvalidShortcut(format!(
+                    "item number {} is not between 1 and {}",
+                    index, MAX_ITEM_INDEX
+                )));
+            }
+        }
+
+        let accelerator = Accelerator::parse(&binding.shortcut)?;
+        let bound = self.accelerators()?;
+        let mut in_use: Vec<(&Accelerator, &str)> = bound
+            .iter()
+            .map(|(accelerator, action)| (accelerator, action.as_str()))
+            .collect();
+        in_use.push((popup_shortcut, "open the popup"));
+        accelerator.check_conflicts(&in_use)?;
+
+        self.bindings.push(Binding {
+            action: binding.action,
+            shortcut: accelerator.to_string(),
+        });
+        Ok(())
+    }
+
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    /// The first shortcut bound to `action`.
+    pub fn shortcut_for(&self, action: Action) -> Option<&str> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.action == action)
+            .map(|binding| binding.shortcut.as_str())
+    }
+
+    /// Whether every one of the [`item_bindings`] is bound.
+    pub fn has_item_bindings(&self) -> bool {
+        item_bindings()
+            .iter()
+            .all(|item| self.bindings.contains(item))
+    }
+
+    /// Adds the [`item_bindings`] that are missing, or removes all of them.
+    /// Adding fails if one of their shortcuts is already taken.
+    pub fn with_item_bindings(&self, enabled: bool, popup_shortcut: &Accelerator) -> Result<Self> {
+        let items = item_bindings();
+        let mut bindings: Vec<Binding> = self
+            .bindings
+            .iter()
+            .filter(|binding| !items.contains(binding))
+            .cloned()
+            .collect();
+        if enabled {
+            bindings.extend(items);
+        }
+        Self::new(bindings, popup_shortcut)
+    }
+
+    /// Bound shortcuts with what they do, for conflict checks.
+    pub fn accelerators(&self) -> Result<Vec<(Accelerator, String)>> {
+        self.bindings
+            .iter()
+            .map(|binding| {
+                Ok((
+                    Accelerator::parse(&binding.shortcut)?,
+                    binding.action.description(),
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Alt+1 to Alt+9 for pasting the items numbered 1 to 9 in the popup.
+/// Opt-in, see [`Keymap`].
+pub fn item_bindings() -> Vec<Binding> {
+    (1..=MAX_ITEM_INDEX)
+        .map(|index| Binding::new(Action::PasteItem { index }, format!("Alt+{}", index)))
+        .collect()
+}
+
+/// Steps backwards through the history for [`Action::CycleHistory`],
+/// starting after the newest item and wrapping around at the end.
+///
+/// Visited items are remembered by ID, since putting an item on the
+/// clipboard moves it to the top of the history.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryCycle {
+    visited: Vec<EntryId>,
+    last_step: Option<Instant>,
+}
+
+impl HistoryCycle {
+    /// Returns the item to put on the clipboard next.
+    pub fn step(&mut self, entries: &[Entry]) -> Option<EntryId> {
+        self.step_at(entries, Instant::now())
+    }
+
+    pub fn step_at(&mut self, entries: &[Entry], now: Instant) -> Option<EntryId> {
+        let restart = self
+            .last_step
+            .map_or(true, |last| now.duration_since(last) > CYCLE_RESET);
+        if restart {
+            self.visited = entries.first().map(|entry| entry.id).into_iter().collect();
+        }
+        self.last_step = Some(now);
+
+        let next = match entries
+            .iter()
+            .find(|entry| !self.visited.contains(&entry.id))
+        {
+            Some(entry) => entry.id,
+            None => {
+                // Every item was visited; go around again
+                self.visited.clear();
+                entries.first()?.id
+            }
+        };
+        self.visited.push(next);
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::HistoryItem;
+
+    fn popup() -> Accelerator {
+        Accelerator::parse("Alt+V").unwrap()
+    }
+
+    #[test]
+    fn binds_nothing_by_default() {
+        assert!(Keymap::default().bindings().is_empty());
+    }
+
+    #[test]
+    fn rejects_shortcuts_bound_twice_or_to_the_popup() {
+        let twice = vec![
+            Binding::new(Action::PauseCapture, "Control+Alt+P"),
+            Binding::new(Action::ClearHistory, "Control+Alt+P"),
+        ];
+        assert!(Keymap::new(twice, &popup()).is_err());
+
+        let popup_shortcut = vec![Binding::new(Action::PastePrevious, "Alt+V")];
+        assert!(Keymap::new(popup_shortcut, &popup()).is_err());
+    }
+
+    #[test]
+    fn drops_invalid_bindings_from_disk() {
+        let keymap = Keymap::new_lossy(
+            vec![
+                Binding::new(Action::PasteItem { index: 10 }, "Alt+0"),
+                Binding::new(Action::PasteItem { index: 1 }, "Control+Alt+1"),
+            ],
+            &popup(),
+        );
+        assert_eq!(keymap.bindings().len(), 1);
+        assert!(keymap
+            .shortcut_for(Action::PasteItem { index: 1 })
+            .is_some());
+    }
+
+    #[test]
+    fn item_bindings_are_valid_and_opt_in() {
+        let keymap = Keymap::new(
+            vec![Binding::new(Action::PauseCapture, "Control+Alt+P")],
+            &popup(),
+        )
+        .unwrap();
+        assert!(!keymap.has_item_bindings());
+
+        let with_items = keymap.with_item_bindings(true, &popup()).unwrap();
+        assert!(with_items.has_item_bindings());
+        assert_eq!(with_items.bindings().len(), 1 + MAX_ITEM_INDEX);
+        assert_eq!(
+            with_items.shortcut_for(Action::PasteItem { index: 3 }),
+            Some(item_bindings()[2].shortcut.as_str())
+        );
+        // Turning them on twice binds nothing twice
+        assert_eq!(
+            with_items.with_item_bindings(true, &popup()).unwrap(),
+            with_items
+        );
+
+        let without = with_items.with_item_bindings(false, &popup()).unwrap();
+        assert_eq!(without, keymap);
+    }
+
+    #[test]
+    fn item_bindings_fail_on_taken_shortcuts() {
+        let keymap =
+            Keymap::new(vec![Binding::new(Action::PastePrevious, "Alt+1")], &popup()).unwrap();
+        assert!(keymap.with_item_bindings(true, &popup()).is_err());
+        assert!(keymap.with_item_bindings(false, &popup()).is_ok());
+    }
+
+    fn entries(ids: &[EntryId]) -> Vec<Entry> {
+        ids.iter()
+            .map(|&id| Entry::new(id, 0, HistoryItem::Text(id.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn cycles_past_the_newest_item_and_wraps_around() {
+        let mut cycle = HistoryCycle::default();
+        let start = Instant::now();
+        let step = |cycle: &mut HistoryCycle, entries: &[Entry], n: u32| {
+            cycle.step_at(entries, start + Duration::from_millis(100) * n)
+        };
+
+        assert_eq!(step(&mut cycle, &entries(&[3, 2, 1]), 0), Some(2));
+        // Putting the item on the clipboard moved it to the top
+        assert_eq!(step(&mut cycle, &entries(&[2, 3, 1]), 1), Some(1));
+        // Every item was visited, so the cycle starts again from the top
+        assert_eq!(step(&mut cycle, &entries(&[1, 2, 3]), 2), Some(1));
+        assert_eq!(step(&mut cycle, &entries(&[1, 2, 3]), 3), Some(2));
+    }
+
+    #[test]
+    fn starts_over_after_a_pause() {
+        let mut cycle = HistoryCycle::default();
+        let start = Instant::now();
+        let history = entries(&[3, 2, 1]);
+
+        assert_eq!(cycle.step_at(&history, start), Some(2));
+        assert_eq!(cycle.step_at(&history, start + CYCLE_RESET), Some(1));
+        let later = start + CYCLE_RESET * 3;
+        assert_eq!(cycle.step_at(&history, later), Some(2));
+
+        assert_eq!(cycle.step_at(&[], later), None);
+    }
+}
@@ -0,0 +1,47 @@
+//! Platform-independent core of Macopy.
+//!
+//! Everything in here is free of Tauri and Cocoa so it can be reused by other
+//! front-ends and tested on any platform.
+
+#[cfg(feature = "x11")]
+pub mod active_app;
+pub mod blob;
+pub mod capture;
+pub mod clipboard;
+pub mod encryption;
+mod error;
+pub mod exclusions;
+pub mod history;
+pub mod ipc;
+pub mod item;
+pub mod keymap;
+pub mod paste;
+pub mod pause;
+pub mod plain_text;
+pub mod protocol;
+pub mod search;
+pub mod sensitive;
+pub mod settings;
+pub mod shortcut;
+pub mod store;
+pub mod watcher;
+
+pub use blob::BlobStore;
+pub use capture::CaptureContext;
+pub use clipboard::{ClipboardBackend, ClipboardContent};
+pub use error::{Error, Result};
+pub use exclusions::{AppInfo, ExcludedApps};
+pub use history::{
+    Capture, Entry, EntryId, History, HistoryDiff, HistoryLimits, DEFAULT_MAX_HISTORY_ITEMS,
+};
+pub use ipc::{CommandError, HistoryEvent};
+pub use item::{HistoryItem, ImageRef};
+pub use keymap::{Action, Binding, HistoryCycle, Keymap};
+pub use paste::{PasteInjector, PasteProfile, PasteProfiles, PasteStrategy, Paster};
+pub use pause::{CapturePause, Pause};
+pub use plain_text::PlainTextOptions;
+pub use sensitive::{SensitiveAction, SensitiveDetector, SensitiveSettings};
+pub use settings::Settings;
+pub use shortcut::Accelerator;
+pub use store::{HistoryStore, KeyValueStore, MemoryStore};
+pub use watcher::ClipboardWatcher;
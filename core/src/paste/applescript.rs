@@ -0,0 +1,52 @@
+use super::PasteInjector;
+use crate::error::{Error, Result};
+use std::process::Command;
+
+/// Activates apps and sends ⌘V through `osascript` and System Events.
+#[derive(Debug, Default)]
+pub struct AppleScriptInjector;
+
+impl AppleScriptInjector {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+fn run_script(script: &str) -> Result<()> {
+    let output = Command::new("osascript")
+        .arg("-e")
+        .arg(script)
+        .output()
+        .map_err(|e| Error::Paste(format!("failed to run osascript: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(Error::Paste(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl PasteInjector for AppleScriptInjector {
+    fn activate(&self, app_name: &str) -> Result<()> {
+        let escaped_name = app_name.replace('\\', "\\\\").replace('"', "\\\"");
+        run_script(&format!(
+            r#"
+tell application "{}"
+  activate
+end tell
+"#,
+            escaped_name
+        ))
+    }
+
+    fn send_paste(&self) -> Result<()> {
+        run_script(
+            r#"
+tell application "System Events"
+  keystroke "v" using {command down}
+end tell
+"#,
+        )
+    }
+}
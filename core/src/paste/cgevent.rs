@@ -0,0 +1,122 @@
+use super::PasteInjector;
+use crate::error::{Error, Result};
+use cocoa::base::{id, nil};
+use cocoa::foundation::NSAutoreleasePool;
+use objc::runtime::{BOOL, NO};
+use objc::{class, msg_send, sel, sel_impl};
+use std::ffi::{c_void, CStr};
+
+type CGEventRef = *mut c_void;
+type CGEventSourceRef = *mut c_void;
+
+const KVK_ANSI_V: u16 = 9;
+const CG_EVENT_FLAG_MASK_COMMAND: u64 = 1 << 20;
+const CG_HID_EVENT_TAP: u32 = 0;
+const CG_EVENT_SOURCE_STATE_HID_SYSTEM: i32 = 1;
+const NS_APPLICATION_ACTIVATE_IGNORING_OTHER_APPS: u64 = 1 << 1;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGEventSourceCreate(state_id: i32) -> CGEventSourceRef;
+    fn CGEventCreateKeyboardEvent(
+        source: CGEventSourceRef,
+        keycode: u16,
+        key_down: bool,
+    ) -> CGEventRef;
+    fn CGEventSetFlags(event: CGEventRef, flags: u64);
+    fn CGEventPost(tap: u32, event: CGEventRef);
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRelease(cf: *const c_void);
+}
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXIsProcessTrusted() -> bool;
+}
+
+/// Posts ⌘V as Quartz keyboard events and activates apps through
+/// `NSRunningApplication`, without spawning `osascript`.
+#[derive(Debug, Default)]
+pub struct CgEventInjector;
+
+impl CgEventInjector {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+unsafe fn ns_string_to_string(string: id) -> Option<String> {
+    if string == nil {
+        return None;
+    }
+    let utf8: *const std::os::raw::c_char = msg_send![string, UTF8String];
+    if utf8.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(utf8).to_string_lossy().into_owned())
+}
+
+impl PasteInjector for CgEventInjector {
+    fn activate(&self, app_name: &str) -> Result<()> {
+        unsafe {
+            let pool = NSAutoreleasePool::new(nil);
+            let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+            let apps: id = msg_send![workspace, runningApplications];
+            let count: usize = msg_send![apps, count];
+
+            let mut activated = false;
+            for i in 0..count {
+                let app: id = msg_send![apps, objectAtIndex: i];
+                let name: id = msg_send![app, localizedName];
+                if ns_string_to_string(name).as_deref() == Some(app_name) {
+                    let result: BOOL = msg_send![
+                        app,
+                        activateWithOptions: NS_APPLICATION_ACTIVATE_IGNORING_OTHER_APPS
+                    ];
+                    activated = result != NO;
+                    break;
+                }
+            }
+
+            let _: () = msg_send![pool, drain];
+
+            if activated {
+                Ok(())
+            } else {
+                Err(Error::Paste(format!("could not activate {}", app_name)))
+            }
+        }
+    }
+
+    fn send_paste(&self) -> Result<()> {
+        unsafe {
+            // Without Accessibility access the events are silently dropped
+            if !AXIsProcessTrusted() {
+                return Err(Error::Paste(
+                    "Accessibility permission is required to paste".into(),
+                ));
+            }
+
+            let source = CGEventSourceCreate(CG_EVENT_SOURCE_STATE_HID_SYSTEM);
+            for key_down in [true, false] {
+                let event = CGEventCreateKeyboardEvent(source, KVK_ANSI_V, key_down);
+                if event.is_null() {
+                    if !source.is_null() {
+                        CFRelease(source);
+                    }
+                    return Err(Error::Paste("failed to create keyboard event".into()));
+                }
+                CGEventSetFlags(event, CG_EVENT_FLAG_MASK_COMMAND);
+                CGEventPost(CG_HID_EVENT_TAP, event);
+                CFRelease(event);
+            }
+            if !source.is_null() {
+                CFRelease(source);
+            }
+        }
+        Ok(())
+    }
+}
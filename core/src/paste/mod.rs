@@ -0,0 +1,249 @@
+//! Simulating a paste into another application.
+//!
+//! A [`PasteInjector`] knows how to bring an application to the front and send
+//! it the paste keystroke. [`Paster`] drives an injector with per-app delay
+//! and retry profiles and reports whether the paste went through.
+
+mod applescript;
+#[cfg(target_os = "macos")]
+mod cgevent;
+mod recording;
+#[cfg(feature = "x11")]
+mod xtest;
+
+#[cfg(all(not(target_os = "macos"), not(feature = "x11")))]
+use crate::error::Error;
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub use applescript::AppleScriptInjector;
+#[cfg(target_os = "macos")]
+pub use cgevent::CgEventInjector;
+pub use recording::{PasteEvent, RecordingInjector};
+#[cfg(feature = "x11")]
+pub use xtest::XTestInjector;
+
+pub trait PasteInjector: Send + Sync {
+    /// Brings the named application to the front.
+    fn activate(&self, app_name: &str) -> Result<()>;
+
+    /// Sends the paste keystroke to the frontmost application.
+    fn send_paste(&self) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasteStrategy {
+    #[cfg_attr(not(target_os = "linux"), default)]
+    AppleScript,
+    CgEvent,
+    #[cfg_attr(target_os = "linux", default)]
+    XTest,
+}
+
+/// Creates the injector for `strategy`. A strategy that is not available on
+/// this platform falls back to the platform's own: AppleScript on macOS and
+/// XTest on Linux. Fails if that is not available either.
+pub fn create_injector(strategy: PasteStrategy) -> Result<Arc<dyn PasteInjector>> {
+    match strategy {
+        #[cfg(target_os = "macos")]
+        PasteStrategy::AppleScript => return Ok(Arc::new(AppleScriptInjector::new())),
+        #[cfg(target_os = "macos")]
+        PasteStrategy::CgEvent => return Ok(Arc::new(CgEventInjector::new())),
+        #[cfg(feature = "x11")]
+        PasteStrategy::XTest => match XTestInjector::new() {
+            Ok(injector) => return Ok(Arc::new(injector)),
+            // XTest is the fallback here, so there is nothing else to try
+            #[cfg(not(target_os = "macos"))]
+            Err(e) => return Err(e),
+            #[cfg(target_os = "macos")]
+            Err(e) => log::warn!("XTest unavailable: {}", e),
+        },
+        #[allow(unreachable_patterns)]
+        _ => log::warn!("{:?} paste is not supported here", strategy),
+    }
+
+    platform_injector()
+}
+
+#[cfg(target_os = "macos")]
+fn platform_injector() -> Result<Arc<dyn PasteInjector>> {
+    Ok(Arc::new(AppleScriptInjector::new()))
+}
+
+#[cfg(all(not(target_os = "macos"), feature = "x11"))]
+fn platform_injector() -> Result<Arc<dyn PasteInjector>> {
+    Ok(Arc::new(XTestInjector::new()?))
+}
+
+#[cfg(all(not(target_os = "macos"), not(feature = "x11")))]
+fn platform_injector() -> Result<Arc<dyn PasteInjector>> {
+    Err(Error::Paste(
+        "no way to paste is available on this platform".to_string(),
+    ))
+}
+
+/// How long to wait before pasting into an application, how often to retry
+/// a failed keystroke and how long the application gets to read the pasted
+/// item before the previous clipboard goes back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PasteProfile {
+    pub delay_ms: u64,
+    pub retries: u32,
+    pub retry_delay_ms: u64,
+    pub restore_delay_ms: u64,
+}
+
+impl Default for PasteProfile {
+    fn default() -> Self {
+        Self {
+            delay_ms: 100,
+            retries: 0,
+            retry_delay_ms: 100,
+            restore_delay_ms: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PasteProfiles {
+    pub default: PasteProfile,
+    /// Overrides keyed by application name.
+    pub apps: HashMap<String, PasteProfile>,
+}
+
+impl PasteProfiles {
+    pub fn for_app(&self, app_name: &str) -> PasteProfile {
+        self.apps.get(app_name).copied().unwrap_or(self.default)
+    }
+}
+
+#[derive(Clone)]
+pub struct Paster {
+    injector: Arc<dyn PasteInjector>,
+    profiles: PasteProfiles,
+}
+
+impl Paster {
+    pub fn new(injector: Arc<dyn PasteInjector>, profiles: PasteProfiles) -> Self {
+        Self { injector, profiles }
+    }
+
+    /// How long `app_name` gets to read a pasted item before the clipboard is
+    /// restored.
+    pub fn restore_delay(&self, app_name: &str) -> Duration {
+        Duration::from_millis(self.profiles.for_app(app_name).restore_delay_ms)
+    }
+
+    /// Pastes into `app_name`, or into the frontmost application when the
+    /// name is empty.
+    pub fn paste(&self, app_name: &str) -> Result<()> {
+        let profile = self.profiles.for_app(app_name);
+
+        if !app_name.is_empty() {
+            self.injector.activate(app_name)?;
+        }
+        thread::sleep(Duration::from_millis(profile.delay_ms));
+
+        let mut attempt = 0;
+        loop {
+            match self.injector.send_paste() {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < profile.retries => {
+                    attempt += 1;
+                    log::warn!("Paste attempt {} failed, retrying: {}", attempt, e);
+                    thread::sleep(Duration::from_millis(profile.retry_delay_ms));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn immediate(retries: u32) -> PasteProfile {
+        PasteProfile {
+            delay_ms: 0,
+            retries,
+            retry_delay_ms: 0,
+            restore_delay_ms: 0,
+        }
+    }
+
+    fn paster(profiles: PasteProfiles) -> (Arc<RecordingInjector>, Paster) {
+        let injector = Arc::new(RecordingInjector::new());
+        (injector.clone(), Paster::new(injector, profiles))
+    }
+
+    #[test]
+    fn activates_the_app_before_pasting() {
+        let (injector, paster) = paster(PasteProfiles {
+            default: immediate(0),
+            ..PasteProfiles::default()
+        });
+
+        paster.paste("Notes").unwrap();
+        paster.paste("").unwrap();
+        assert_eq!(
+            injector.events(),
+            [
+                PasteEvent::Activate("Notes".to_string()),
+                PasteEvent::Paste,
+                PasteEvent::Paste,
+            ]
+        );
+    }
+
+    #[test]
+    fn retries_failed_keystrokes() {
+        let (injector, paster) = paster(PasteProfiles {
+            default: immediate(2),
+            ..PasteProfiles::default()
+        });
+
+        injector.fail_next(2);
+        paster.paste("").unwrap();
+        assert_eq!(injector.events(), [PasteEvent::Paste]);
+
+        injector.fail_next(3);
+        assert!(paster.paste("").is_err());
+        assert_eq!(injector.events(), [PasteEvent::Paste]);
+    }
+
+    #[test]
+    fn uses_the_profile_of_the_target_app() {
+        let (injector, paster) = paster(PasteProfiles {
+            default: immediate(0),
+            apps: HashMap::from([("Slack".to_string(), immediate(1))]),
+        });
+
+        injector.fail_next(1);
+        paster.paste("Slack").unwrap();
+
+        injector.fail_next(1);
+        assert!(paster.paste("Notes").is_err());
+        assert_eq!(
+            injector.events(),
+            [
+                PasteEvent::Activate("Slack".to_string()),
+                PasteEvent::Paste,
+                PasteEvent::Activate("Notes".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(feature = "x11")))]
+    #[test]
+    fn fails_without_a_way_to_paste() {
+        assert!(create_injector(PasteStrategy::AppleScript).is_err());
+    }
+}
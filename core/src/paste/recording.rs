@@ -0,0 +1,58 @@
+use super::PasteInjector;
+use crate::error::{Error, Result};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasteEvent {
+    Activate(String),
+    Paste,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    events: Vec<PasteEvent>,
+    failures: u32,
+}
+
+/// Records what would have been injected instead of touching the system.
+///
+/// [`fail_next`](Self::fail_next) makes the next keystrokes fail, to exercise
+/// retry profiles and error reporting.
+#[derive(Debug, Default)]
+pub struct RecordingInjector {
+    inner: Mutex<Inner>,
+}
+
+impl RecordingInjector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn fail_next(&self, count: u32) {
+        self.inner.lock().unwrap().failures = count;
+    }
+
+    pub fn events(&self) -> Vec<PasteEvent> {
+        self.inner.lock().unwrap().events.clone()
+    }
+}
+
+impl PasteInjector for RecordingInjector {
+    fn activate(&self, app_name: &str) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .events
+            .push(PasteEvent::Activate(app_name.to_string()));
+        Ok(())
+    }
+
+    fn send_paste(&self) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.failures > 0 {
+            inner.failures -= 1;
+            return Err(Error::Paste("scripted failure".into()));
+        }
+        inner.events.push(PasteEvent::Paste);
+        Ok(())
+    }
+}
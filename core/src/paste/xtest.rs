@@ -0,0 +1,152 @@
+use super::PasteInjector;
+use crate::error::{Error, Result};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask, Keycode, Window, KEY_PRESS_EVENT,
+    KEY_RELEASE_EVENT,
+};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+use x11rb::CURRENT_TIME;
+
+const XK_CONTROL_L: u32 = 0xffe3;
+const XK_V: u32 = 0x0076;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        WM_CLASS,
+    }
+}
+
+fn x11_error(e: impl std::fmt::Display) -> Error {
+    Error::Paste(e.to_string())
+}
+
+/// Sends Ctrl+V through the XTest extension and activates windows through
+/// the EWMH `_NET_ACTIVE_WINDOW` request.
+pub struct XTestInjector {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+}
+
+impl XTestInjector {
+    /// Connects to the display named by `$DISPLAY`.
+    pub fn new() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(x11_error)?;
+        let root = conn.setup().roots[screen_num].root;
+        conn.xtest_get_version(2, 2)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        let atoms = Atoms::new(&conn)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+
+        Ok(Self { conn, root, atoms })
+    }
+
+    fn keycode(&self, keysym: u32) -> Result<Keycode> {
+        let setup = self.conn.setup();
+        let (min, max) = (setup.min_keycode, setup.max_keycode);
+        let mapping = self
+            .conn
+            .get_keyboard_mapping(min, max - min + 1)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+
+        let per_keycode = usize::from(mapping.keysyms_per_keycode.max(1));
+        mapping
+            .keysyms
+            .chunks(per_keycode)
+            .position(|keysyms| keysyms.contains(&keysym))
+            .map(|index| min + index as u8)
+            .ok_or_else(|| Error::Paste(format!("no keycode for keysym {:#x}", keysym)))
+    }
+
+    /// Finds a top-level window whose `WM_CLASS` matches the application name.
+    fn find_window(&self, app_name: &str) -> Result<Option<Window>> {
+        let clients = self
+            .conn
+            .get_property(
+                false,
+                self.root,
+                self.atoms._NET_CLIENT_LIST,
+                AtomEnum::WINDOW,
+                0,
+                u32::MAX,
+            )
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        let Some(windows) = clients.value32() else {
+            return Ok(None);
+        };
+
+        for window in windows {
+            let class = self
+                .conn
+                .get_property(false, window, self.atoms.WM_CLASS, AtomEnum::STRING, 0, 256)
+                .map_err(x11_error)?
+                .reply()
+                .map_err(x11_error)?;
+            let matches = class
+                .value
+                .split(|&b| b == 0)
+                .any(|part| String::from_utf8_lossy(part).eq_ignore_ascii_case(app_name));
+            if matches {
+                return Ok(Some(window));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl PasteInjector for XTestInjector {
+    fn activate(&self, app_name: &str) -> Result<()> {
+        let window = self
+            .find_window(app_name)?
+            .ok_or_else(|| Error::Paste(format!("could not activate {}", app_name)))?;
+
+        // Source indication 2 means the request comes from a pager-like tool
+        let event = ClientMessageEvent::new(
+            32,
+            window,
+            self.atoms._NET_ACTIVE_WINDOW,
+            [2, CURRENT_TIME, 0, 0, 0],
+        );
+        self.conn
+            .send_event(
+                false,
+                self.root,
+                EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+                event,
+            )
+            .map_err(x11_error)?;
+        self.conn.flush().map_err(x11_error)?;
+        Ok(())
+    }
+
+    fn send_paste(&self) -> Result<()> {
+        let control = self.keycode(XK_CONTROL_L)?;
+        let v = self.keycode(XK_V)?;
+
+        for (type_, keycode) in [
+            (KEY_PRESS_EVENT, control),
+            (KEY_PRESS_EVENT, v),
+            (KEY_RELEASE_EVENT, v),
+            (KEY_RELEASE_EVENT, control),
+        ] {
+            self.conn
+                .xtest_fake_input(type_, keycode, CURRENT_TIME, self.root, 0, 0, 0)
+                .map_err(x11_error)?
+                .check()
+                .map_err(x11_error)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use crate::history::now_millis;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Why clipboard capture is paused and when it resumes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum Pause {
+    /// Until resumed by hand.
+    Indefinite,
+    /// Until `resume_at`, in milliseconds since the Unix epoch.
+    #[serde(rename_all = "camelCase")]
+    Until { resume_at: i64 },
+    /// Ignores the next clipboard change, then resumes.
+    NextCopy,
+}
+
+impl Pause {
+    pub fn for_duration(duration: Duration) -> Self {
+        Pause::Until {
+            resume_at: now_millis().saturating_add(duration.as_millis() as i64),
+        }
+    }
+}
+
+/// Pause state of clipboard capture.
+///
+/// Changes seen while paused are remembered by change count, so the content
+/// copied during a pause is not picked up after resuming.
+#[derive(Debug, Clone, Default)]
+pub struct CapturePause {
+    pause: Option<Pause>,
+    ignored_change: Option<i64>,
+}
+
+impl CapturePause {
+    pub fn new(pause: Option<Pause>) -> Self {
+        Self {
+            pause,
+            ignored_change: None,
+        }
+    }
+
+    pub fn current(&self) -> Option<Pause> {
+        self.pause
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_some()
+    }
+
+    pub fn pause(&mut self, pause: Pause) {
+        self.pause = Some(pause);
+    }
+
+    /// Returns `false` if capture was not paused.
+    pub fn resume(&mut self) -> bool {
+        self.pause.take().is_some()
+    }
+
+    /// Ends a timed pause whose time has come. Returns `true` if capture
+    /// resumed.
+    pub fn tick(&mut self) -> bool {
+        self.tick_at(now_millis())
+    }
+
+    pub fn tick_at(&mut self, now: i64) -> bool {
+        match self.pause {
+            Some(Pause::Until { resume_at }) if resume_at <= now => self.resume(),
+            _ => false,
+        }
+    }
+
+    /// Decides whether a clipboard change should be recorded, ending a
+    /// [`Pause::NextCopy`] pause.
+    pub fn on_change(&mut self, change_count: i64) -> bool {
+        match self.pause {
+            None => return true,
+            Some(Pause::NextCopy) => self.pause = None,
+            Some(_) => {}
+        }
+        self.ignored_change = Some(change_count);
+        false
+    }
+
+    /// Whether the current clipboard content may be recorded, without
+    /// counting as a change.
+    pub fn allows(&self, change_count: i64) -> bool {
+        self.pause.is_none() && self.ignored_change != Some(change_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_every_change_when_not_paused() {
+        let mut pause = CapturePause::default();
+        assert!(pause.on_change(1));
+        assert!(pause.allows(1));
+        assert!(!pause.resume());
+    }
+
+    #[test]
+    fn ignores_changes_until_resumed() {
+        let mut pause = CapturePause::new(Some(Pause::Indefinite));
+        assert!(!pause.on_change(1));
+        assert!(!pause.on_change(2));
+        assert!(pause.is_paused());
+
+        assert!(pause.resume());
+        // The content copied during the pause stays ignored
+        assert!(!pause.allows(2));
+        assert!(pause.on_change(3));
+        assert!(pause.allows(3));
+    }
+
+    #[test]
+    fn timed_pause_ends_when_its_time_comes() {
+        let mut pause = CapturePause::new(Some(Pause::Until { resume_at: 1_000 }));
+        assert!(!pause.tick_at(999));
+        assert!(!pause.on_change(1));
+        assert!(pause.tick_at(1_000));
+        assert!(!pause.is_paused());
+        assert!(!pause.tick_at(2_000));
+
+        // Other pauses never end on their own
+        pause.pause(Pause::Indefinite);
+        assert!(!pause.tick_at(i64::MAX));
+    }
+
+    #[test]
+    fn next_copy_pause_skips_a_single_change() {
+        let mut pause = CapturePause::new(Some(Pause::NextCopy));
+        assert!(!pause.on_change(1));
+        assert!(!pause.is_paused());
+        assert!(!pause.allows(1));
+        assert!(pause.on_change(2));
+    }
+
+    #[test]
+    fn serializes_with_a_mode_tag() {
+        let pause = Pause::Until { resume_at: 5 };
+        let json = serde_json::to_value(pause).unwrap();
+        assert_eq!(json, serde_json::json!({ "mode": "until", "resumeAt": 5 }));
+        assert_eq!(serde_json::from_value::<Pause>(json).unwrap(), pause);
+    }
+}
@@ -0,0 +1,127 @@
+//! Pasting without formatting.
+//!
+//! Only the plain-text representation goes back on the clipboard, optionally
+//! cleaned up by [`PlainTextOptions`] first.
+
+use serde::{Deserialize, Serialize};
+
+const SINGLE_QUOTES: &[char] = &['\u{2018}', '\u{2019}', '\u{201A}', '\u{201B}'];
+const DOUBLE_QUOTES: &[char] = &['\u{201C}', '\u{201D}', '\u{201E}', '\u{201F}'];
+
+/// Clean-up applied when pasting as plain text. Everything is off by
+/// default, so the text is pasted as copied. Trimming and collapsing work on
+/// lines, so they also turn `\r\n` and `\r` line endings into `\n`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PlainTextOptions {
+    /// Removes whitespace at the start and end of the text and at the end of
+    /// every line.
+    pub trim: bool,
+    /// Replaces runs of blank lines with a single one.
+    pub collapse_blank_lines: bool,
+    /// Replaces curly quotes and apostrophes with straight ones.
+    pub straighten_quotes: bool,
+}
+
+impl PlainTextOptions {
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = if self.straighten_quotes {
+            text.replace(SINGLE_QUOTES, "'")
+                .replace(DOUBLE_QUOTES, "\"")
+        } else {
+            text.to_string()
+        };
+
+        if self.trim || self.collapse_blank_lines {
+            text = text.replace("\r\n", "\n").replace('\r', "\n");
+        }
+
+        if self.trim {
+            text = text
+                .split('\n')
+                .map(str::trim_end)
+                .collect::<Vec<_>>()
+                .join("\n")
+                .trim()
+                .to_string();
+        }
+
+        if self.collapse_blank_lines {
+            let mut previous_blank = false;
+            text = text
+                .split('\n')
+                .filter(|line| {
+                    let blank = line.trim().is_empty();
+                    let keep = !(blank && previous_blank);
+                    previous_blank = blank;
+                    keep
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIM: PlainTextOptions = PlainTextOptions {
+        trim: true,
+        collapse_blank_lines: false,
+        straighten_quotes: false,
+    };
+    const COLLAPSE: PlainTextOptions = PlainTextOptions {
+        trim: false,
+        collapse_blank_lines: true,
+        straighten_quotes: false,
+    };
+    const STRAIGHTEN: PlainTextOptions = PlainTextOptions {
+        trim: false,
+        collapse_blank_lines: false,
+        straighten_quotes: true,
+    };
+
+    #[test]
+    fn leaves_the_text_alone_by_default() {
+        let text = "  \u{201C}a\u{201D}  \r\n\r\n\r\nb ";
+        assert_eq!(PlainTextOptions::default().apply(text), text);
+    }
+
+    #[test]
+    fn trims_the_text_and_every_line() {
+        assert_eq!(TRIM.apply("\n  first  \t\nsecond \n\n"), "first\nsecond");
+        assert_eq!(TRIM.apply("a \r\n  b\t\r\n"), "a\n  b");
+    }
+
+    #[test]
+    fn collapses_runs_of_blank_lines() {
+        assert_eq!(COLLAPSE.apply("a\n\n\n\nb\n \n\t\nc"), "a\n\nb\n \nc");
+        // Windows and classic Mac line endings leave no stray '\r'
+        assert_eq!(COLLAPSE.apply("a\r\n\r\n\r\nb\r\n"), "a\n\nb\n");
+        assert_eq!(COLLAPSE.apply("a\r\r\rb"), "a\n\nb");
+    }
+
+    #[test]
+    fn straightens_curly_quotes() {
+        assert_eq!(
+            STRAIGHTEN.apply("\u{201C}It\u{2019}s \u{2018}fine\u{2019}\u{201D} \u{201E}ok\u{201F}"),
+            "\"It's 'fine'\" \"ok\""
+        );
+        // Line endings are kept when no line is touched
+        assert_eq!(STRAIGHTEN.apply("a\r\nb"), "a\r\nb");
+    }
+
+    #[test]
+    fn combines_all_options() {
+        let options = PlainTextOptions {
+            trim: true,
+            collapse_blank_lines: true,
+            straighten_quotes: true,
+        };
+        let text = "\r\n  \u{201C}Hi\u{201D}  \r\n\r\n \r\n\r\n\u{2018}there\u{2019}\t\r\n\r\n";
+        assert_eq!(options.apply(text), "\"Hi\"\n\n'there'");
+    }
+}
@@ -0,0 +1,97 @@
+//! Routes for the `macopy://` URI scheme that serves image bytes to the
+//! popup, so the history event only has to carry content hashes.
+//!
+//! Paths have the form `image/<hash>` or `thumbnail/<hash>`, where the hash is
+//! that of the blob. A URL therefore always refers to the same bytes and can be
+//! cached for good. The frontend builds URLs with
+//! `convertFileSrc(path, "macopy")`, which percent-encodes the slash, so both
+//! encoded and plain paths are accepted.
+
+use crate::blob::is_valid_hash;
+
+pub const URI_SCHEME: &str = "macopy";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resource {
+    /// The original PNG of an image.
+    Image(String),
+    /// A downscaled PNG for the popup list.
+    Thumbnail(String),
+}
+
+impl Resource {
+    /// Parses the path of a request URI, with or without the leading slash.
+    pub fn parse(path: &str) -> Option<Self> {
+        let path = percent_decode(path.trim_start_matches('/'))?;
+        let (kind, hash) = path.split_once('/')?;
+        if !is_valid_hash(hash) {
+            return None;
+        }
+
+        match kind {
+            "image" => Some(Resource::Image(hash.to_string())),
+            "thumbnail" => Some(Resource::Thumbnail(hash.to_string())),
+            _ => None,
+        }
+    }
+
+    pub fn path(&self) -> String {
+        match self {
+            Resource::Image(hash) => format!("image/{}", hash),
+            Resource::Thumbnail(hash) => format!("thumbnail/{}", hash),
+        }
+    }
+
+    /// The content hash of the blob.
+    pub fn hash(&self) -> &str {
+        match self {
+            Resource::Image(hash) | Resource::Thumbnail(hash) => hash,
+        }
+    }
+}
+
+fn percent_decode(input: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut rest = input.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn parses_plain_and_encoded_paths() {
+        let thumbnail = Resource::Thumbnail(HASH.to_string());
+        assert_eq!(
+            Resource::parse(&format!("/thumbnail/{}", HASH)),
+            Some(thumbnail.clone())
+        );
+        assert_eq!(
+            Resource::parse(&format!("thumbnail%2F{}", HASH)),
+            Some(thumbnail.clone())
+        );
+        assert_eq!(Resource::parse(&thumbnail.path()), Some(thumbnail));
+    }
+
+    #[test]
+    fn rejects_anything_but_blob_hashes() {
+        assert_eq!(Resource::parse("image/42"), None);
+        assert_eq!(Resource::parse("image/../store.json"), None);
+        assert_eq!(Resource::parse(&format!("blob/{}", HASH)), None);
+    }
+}
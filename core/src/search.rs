@@ -0,0 +1,566 @@
+//! Searching the history.
+//!
+//! Text entries can be matched fuzzily, by regular expression or by exact
+//! phrase, and every entry can be filtered by type, source app and capture
+//! time. Results come back one page at a time.
+
+use crate::error::{Error, Result};
+use crate::history::Entry;
+use crate::item::HistoryItem;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Only the start of very long texts is searched fuzzily, which keeps
+/// searches over thousands of entries fast.
+const FUZZY_MAX_CHARS: usize = 4096;
+/// How many occurrences of the first pattern character are tried as the
+/// start of a fuzzy match.
+const FUZZY_MAX_STARTS: usize = 16;
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_CONSECUTIVE: i64 = 12;
+const BONUS_WORD_START: i64 = 8;
+const BONUS_TEXT_START: i64 = 8;
+const PENALTY_GAP: i64 = 1;
+const MAX_GAP_PENALTY: i64 = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Characters must appear in order but not necessarily next to each
+    /// other. Results are ranked by match quality.
+    #[default]
+    Fuzzy,
+    Regex,
+    /// Case-insensitive substring match.
+    Exact,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemType {
+    Text,
+    Image,
+    Files,
+}
+
+/// A search request as sent by the frontend.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchQuery {
+    /// Matches every entry when empty.
+    pub text: String,
+    pub mode: SearchMode,
+    pub item_type: Option<ItemType>,
+    pub source_app: Option<String>,
+    /// Inclusive bounds on the capture time, in milliseconds since the Unix
+    /// epoch.
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            mode: SearchMode::default(),
+            item_type: None,
+            source_app: None,
+            from: None,
+            to: None,
+            offset: 0,
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+/// A matching entry. `ranges` are the matched spans of the text as
+/// `[start, end)` offsets in UTF-16 code units, ready for highlighting in
+/// JavaScript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit<'a> {
+    pub entry: &'a Entry,
+    pub score: i64,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl SearchHit<'_> {
+    pub fn to_frontend_format(&self) -> serde_json::Value {
+        let mut value = self.entry.to_frontend_format();
+        value["score"] = self.score.into();
+        value["ranges"] = serde_json::json!(self.ranges);
+        value
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchPage<'a> {
+    pub hits: Vec<SearchHit<'a>>,
+    /// Number of matches across all pages.
+    pub total: usize,
+}
+
+impl SearchPage<'_> {
+    pub fn to_frontend_format(&self) -> serde_json::Value {
+        let items: Vec<serde_json::Value> = self
+            .hits
+            .iter()
+            .map(SearchHit::to_frontend_format)
+            .collect();
+        serde_json::json!({
+            "items": items,
+            "total": self.total,
+        })
+    }
+}
+
+enum Matcher {
+    All,
+    Fuzzy(Vec<char>),
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn new(query: &SearchQuery) -> Result<Self> {
+        if query.text.is_empty() {
+            return Ok(Matcher::All);
+        }
+
+        let regex = match query.mode {
+            SearchMode::Fuzzy => return Ok(Matcher::Fuzzy(query.text.chars().map(fold).collect())),
+            SearchMode::Regex => RegexBuilder::new(&query.text),
+            SearchMode::Exact => RegexBuilder::new(&regex::escape(&query.text)),
+        }
+        .case_insensitive(query.mode == SearchMode::Exact)
+        .build()
+        .map_err(|e| Error::InvalidQuery(e.to_string()))?;
+
+        Ok(Matcher::Regex(regex))
+    }
+
+    /// Returns the score and matched byte ranges, or `None` if `text` does
+    /// not match.
+    fn matches(&self, text: &str) -> Option<(i64, Vec<(usize, usize)>)> {
+        match self {
+            Matcher::All => Some((0, Vec::new())),
+            Matcher::Fuzzy(pattern) => fuzzy_match(pattern, text),
+            Matcher::Regex(regex) => {
+                let ranges: Vec<(usize, usize)> = regex
+                    .find_iter(text)
+                    .filter(|m| !m.is_empty())
+                    .map(|m| (m.start(), m.end()))
+                    .collect();
+                (!ranges.is_empty() || regex.is_match(text)).then_some((0, ranges))
+            }
+        }
+    }
+}
+
+/// Searches `entries`, which are expected in history order. Fuzzy matches
+/// are ranked by score; otherwise, and between equal scores, history order
+/// is kept.
+pub fn search<'a>(entries: &'a [Entry], query: &SearchQuery) -> Result<SearchPage<'a>> {
+    let matcher = Matcher::new(query)?;
+
+    let mut hits: Vec<SearchHit> = entries
+        .iter()
+        .filter(|entry| passes_filters(entry, query))
+        .filter_map(|entry| {
+            let (score, ranges) = match entry.item.text() {
+                // Masked text is not searchable
+                Some(text) if !entry.sensitive => {
+                    let (score, ranges) = matcher.matches(text)?;
+                    (score, to_utf16_ranges(text, &ranges))
+                }
+                _ if matches!(matcher, Matcher::All) => (0, Vec::new()),
+                _ => return None,
+            };
+            Some(SearchHit {
+                entry,
+                score,
+                ranges,
+            })
+        })
+        .collect();
+
+    // Stable, so history order breaks ties
+    hits.sort_by_key(|hit| std::cmp::Reverse(hit.score));
+
+    let total = hits.len();
+    let hits = hits
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit)
+        .collect();
+
+    Ok(SearchPage { hits, total })
+}
+
+fn passes_filters(entry: &Entry, query: &SearchQuery) -> bool {
+    let item_type = match entry.item {
+        HistoryItem::Image(_) => ItemType::Image,
+        HistoryItem::Files(_) => ItemType::Files,
+        HistoryItem::Text(_) | HistoryItem::Rich(_) => ItemType::Text,
+    };
+    if query.item_type.is_some_and(|wanted| wanted != item_type) {
+        return false;
+    }
+
+    if let Some(source_app) = &query.source_app {
+        let matches_app = entry
+            .source_app
+            .as_ref()
+            .is_some_and(|app| app.eq_ignore_ascii_case(source_app));
+        if !matches_app {
+            return false;
+        }
+    }
+
+    query.from.map_or(true, |from| entry.created_at >= from)
+        && query.to.map_or(true, |to| entry.created_at <= to)
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn is_word_start(prev: Option<char>, c: char) -> bool {
+    match prev {
+        None => true,
+        Some(prev) => {
+            (!prev.is_alphanumeric() && c.is_alphanumeric())
+                || (prev.is_lowercase() && c.is_uppercase())
+        }
+    }
+}
+
+/// Scores `text` against a case-folded pattern. The first few occurrences of
+/// the first pattern character are tried as starting points and the best
+/// greedy match wins.
+fn fuzzy_match(pattern: &[char], text: &str) -> Option<(i64, Vec<(usize, usize)>)> {
+    let chars: Vec<(usize, char)> = text.char_indices().take(FUZZY_MAX_CHARS).collect();
+    let first = *pattern.first()?;
+
+    let mut best: Option<(i64, Vec<usize>)> = None;
+    let starts = (0..chars.len())
+        .filter(|&i| fold(chars[i].1) == first)
+        .take(FUZZY_MAX_STARTS);
+    for start in starts {
+        let Some(positions) = greedy_positions(pattern, &chars, start) else {
+            // Later starts cannot match if this one ran out of text
+            break;
+        };
+        let score = score_positions(&chars, &positions);
+        if best
+            .as_ref()
+            .map_or(true, |(best_score, _)| score > *best_score)
+        {
+            best = Some((score, positions));
+        }
+    }
+
+    let (score, positions) = best?;
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for index in positions {
+        let (start, c) = chars[index];
+        let end = start + c.len_utf8();
+        match ranges.last_mut() {
+            Some(last) if last.1 == start => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+    Some((score, ranges))
+}
+
+/// Matches the pattern forwards from `start`, then backwards from where that
+/// match ended, which pulls the matched characters closer together.
+fn greedy_positions(pattern: &[char], chars: &[(usize, char)], start: usize) -> Option<Vec<usize>> {
+    let mut end = start;
+    for &wanted in pattern {
+        let offset = chars[end..].iter().position(|&(_, c)| fold(c) == wanted)?;
+        end += offset + 1;
+    }
+
+    let mut positions = Vec::with_capacity(pattern.len());
+    let mut next = end;
+    for &wanted in pattern.iter().rev() {
+        next = chars[start..next]
+            .iter()
+            .rposition(|&(_, c)| fold(c) == wanted)
+            .map(|offset| start + offset)?;
+        positions.push(next);
+    }
+    positions.reverse();
+    Some(positions)
+}
+
+fn score_positions(chars: &[(usize, char)], positions: &[usize]) -> i64 {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+
+    for &index in positions {
+        score += SCORE_MATCH;
+
+        let prev_char = index.checked_sub(1).map(|i| chars[i].1);
+        if is_word_start(prev_char, chars[index].1) {
+            score += BONUS_WORD_START;
+        }
+        if index == 0 {
+            score += BONUS_TEXT_START;
+        }
+
+        match previous {
+            Some(previous) if index == previous + 1 => score += BONUS_CONSECUTIVE,
+            Some(previous) => {
+                score -= ((index - previous - 1) as i64 * PENALTY_GAP).min(MAX_GAP_PENALTY)
+            }
+            None => {}
+        }
+        previous = Some(index);
+    }
+
+    score
+}
+
+/// Converts sorted, non-overlapping byte ranges into UTF-16 offsets.
+fn to_utf16_ranges(text: &str, ranges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut byte = 0;
+    let mut utf16 = 0;
+    let mut advance = |to: usize| {
+        utf16 += text[byte..to].encode_utf16().count();
+        byte = to;
+        utf16
+    };
+
+    ranges
+        .iter()
+        .map(|&(start, end)| (advance(start), advance(end)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::EntryId;
+    use crate::item::{ImageRef, RichContent};
+    use std::path::PathBuf;
+
+    /// Text entries, newest first, created one millisecond apart.
+    fn entries(texts: &[&str]) -> Vec<Entry> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| {
+                let id = (texts.len() - index) as EntryId;
+                Entry::new(id, id as i64, HistoryItem::Text(text.to_string()))
+            })
+            .collect()
+    }
+
+    fn query(text: &str, mode: SearchMode) -> SearchQuery {
+        SearchQuery {
+            text: text.to_string(),
+            mode,
+            ..SearchQuery::default()
+        }
+    }
+
+    fn texts<'a>(page: &SearchPage<'a>) -> Vec<&'a str> {
+        page.hits
+            .iter()
+            .map(|hit| hit.entry.item.text().unwrap_or_default())
+            .collect()
+    }
+
+    fn ranges(text: &str, query: &SearchQuery) -> Vec<(usize, usize)> {
+        let entries = entries(&[text]);
+        let page = search(&entries, query).unwrap();
+        page.hits[0].ranges.clone()
+    }
+
+    #[test]
+    fn fuzzy_ranks_by_match_quality() {
+        let entries = entries(&["xabxc", "cab", "a big cat", "abc"]);
+        let page = search(&entries, &query("abc", SearchMode::Fuzzy)).unwrap();
+
+        // Consecutive characters beat word starts, which beat gaps
+        assert_eq!(texts(&page), ["abc", "a big cat", "xabxc"]);
+        assert_eq!(page.total, 3);
+        assert!(page.hits[0].score > page.hits[1].score);
+        assert!(page.hits[1].score > page.hits[2].score);
+    }
+
+    #[test]
+    fn other_modes_keep_history_order() {
+        let entries = entries(&["cat b", "a cat", "dog"]);
+        let page = search(&entries, &query("cat", SearchMode::Exact)).unwrap();
+        assert_eq!(texts(&page), ["cat b", "a cat"]);
+
+        let page = search(&entries, &query("^a", SearchMode::Regex)).unwrap();
+        assert_eq!(texts(&page), ["a cat"]);
+    }
+
+    #[test]
+    fn fuzzy_and_exact_fold_case() {
+        let entries = entries(&["Hello World", "École"]);
+
+        let page = search(&entries, &query("HW", SearchMode::Fuzzy)).unwrap();
+        assert_eq!(texts(&page), ["Hello World"]);
+        let page = search(&entries, &query("éco", SearchMode::Fuzzy)).unwrap();
+        assert_eq!(texts(&page), ["École"]);
+        let page = search(&entries, &query("hello WORLD", SearchMode::Exact)).unwrap();
+        assert_eq!(texts(&page), ["Hello World"]);
+
+        // Regular expressions are case-sensitive unless they ask otherwise
+        let page = search(&entries, &query("world", SearchMode::Regex)).unwrap();
+        assert!(page.hits.is_empty());
+        let page = search(&entries, &query("(?i)world", SearchMode::Regex)).unwrap();
+        assert_eq!(texts(&page), ["Hello World"]);
+    }
+
+    #[test]
+    fn exact_matches_regex_characters_literally() {
+        let entries = entries(&["axb", "a.b"]);
+        let page = search(&entries, &query("a.b", SearchMode::Exact)).unwrap();
+        assert_eq!(texts(&page), ["a.b"]);
+    }
+
+    #[test]
+    fn invalid_regex_is_an_invalid_query() {
+        let entries = entries(&["a"]);
+        let result = search(&entries, &query("(unclosed", SearchMode::Regex));
+        assert!(matches!(result, Err(Error::InvalidQuery(_))));
+    }
+
+    #[test]
+    fn filters_by_item_type() {
+        let mut entries = entries(&["text"]);
+        entries.push(Entry::new(
+            10,
+            10,
+            HistoryItem::Rich(RichContent {
+                text: "rich".to_string(),
+                html: Some("<b>rich</b>".to_string()),
+                rtf: None,
+                image: None,
+            }),
+        ));
+        entries.push(Entry::new(
+            11,
+            11,
+            HistoryItem::Image(ImageRef {
+                hash: "hash".to_string(),
+                size: 1,
+            }),
+        ));
+        entries.push(Entry::new(
+            12,
+            12,
+            HistoryItem::Files(vec![PathBuf::from("/tmp/file")]),
+        ));
+
+        let ids = |item_type| {
+            let query = SearchQuery {
+                item_type: Some(item_type),
+                ..SearchQuery::default()
+            };
+            let page = search(&entries, &query).unwrap();
+            page.hits.iter().map(|hit| hit.entry.id).collect::<Vec<_>>()
+        };
+        // Formatted text counts as text
+        assert_eq!(ids(ItemType::Text), [1, 10]);
+        assert_eq!(ids(ItemType::Image), [11]);
+        assert_eq!(ids(ItemType::Files), [12]);
+    }
+
+    #[test]
+    fn filters_by_source_app_ignoring_case() {
+        let mut entries = entries(&["a", "b", "c"]);
+        entries[0].source_app = Some("Safari".to_string());
+        entries[1].source_app = Some("Terminal".to_string());
+
+        let query = SearchQuery {
+            source_app: Some("safari".to_string()),
+            ..SearchQuery::default()
+        };
+        assert_eq!(texts(&search(&entries, &query).unwrap()), ["a"]);
+    }
+
+    #[test]
+    fn filters_by_inclusive_time_range() {
+        let entries = entries(&["4", "3", "2", "1"]);
+        let query = SearchQuery {
+            from: Some(2),
+            to: Some(3),
+            ..SearchQuery::default()
+        };
+        assert_eq!(texts(&search(&entries, &query).unwrap()), ["3", "2"]);
+
+        let query = SearchQuery {
+            from: Some(4),
+            ..SearchQuery::default()
+        };
+        assert_eq!(texts(&search(&entries, &query).unwrap()), ["4"]);
+    }
+
+    #[test]
+    fn pages_report_the_total() {
+        let entries = entries(&["a1", "a2", "a3", "a4", "a5", "b"]);
+        let page_of = |offset, limit| {
+            let query = SearchQuery {
+                offset,
+                limit,
+                ..query("a", SearchMode::Exact)
+            };
+            search(&entries, &query).unwrap()
+        };
+
+        let page = page_of(2, 2);
+        assert_eq!(texts(&page), ["a3", "a4"]);
+        assert_eq!(page.total, 5);
+
+        let page = page_of(4, 2);
+        assert_eq!(texts(&page), ["a5"]);
+        assert_eq!(page.total, 5);
+
+        let page = page_of(10, 2);
+        assert!(page.hits.is_empty());
+        assert_eq!(page.total, 5);
+    }
+
+    #[test]
+    fn masked_text_only_shows_without_a_query() {
+        let mut entries = entries(&["secret", "other"]);
+        entries[0].sensitive = true;
+
+        let page = search(&entries, &query("secret", SearchMode::Exact)).unwrap();
+        assert!(page.hits.is_empty());
+        assert_eq!(search(&entries, &SearchQuery::default()).unwrap().total, 2);
+    }
+
+    #[test]
+    fn ranges_are_utf16_offsets() {
+        let text = "😀 café ok";
+
+        // The emoji takes two UTF-16 code units and "é" one
+        assert_eq!(ranges(text, &query("café", SearchMode::Exact)), [(3, 7)]);
+        assert_eq!(ranges(text, &query("é.", SearchMode::Regex)), [(6, 8)]);
+        assert_eq!(
+            ranges(text, &query("co", SearchMode::Fuzzy)),
+            [(3, 4), (8, 9)]
+        );
+        assert_eq!(ranges("😀😀 x", &query("😀 ", SearchMode::Exact)), [(2, 5)]);
+    }
+
+    #[test]
+    fn consecutive_fuzzy_matches_share_a_range() {
+        assert_eq!(
+            ranges("hello world", &query("hewo", SearchMode::Fuzzy)),
+            [(0, 2), (6, 8)]
+        );
+    }
+}
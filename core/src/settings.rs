@@ -0,0 +1,242 @@
+use crate::encryption::EncryptionConfig;
+use crate::exclusions::ExcludedApps;
+use crate::history::HistoryLimits;
+use crate::keymap::Keymap;
+use crate::paste::{PasteProfiles, PasteStrategy};
+use crate::pause::Pause;
+use crate::plain_text::PlainTextOptions;
+use crate::sensitive::SensitiveSettings;
+use crate::shortcut::Accelerator;
+use crate::store::{
+    KeyValueStore, CAPTURE_PAUSE_KEY, ENCRYPTION_KEY, EXCLUDED_APPS_KEY, HISTORY_LIMITS_KEY,
+    IGNORE_WHITESPACE_DUPLICATES_KEY, KEYMAP_KEY, OPEN_AT_LOGIN_KEY, PASTE_PROFILES_KEY,
+    PASTE_STRATEGY_KEY, PERSIST_PAUSE_KEY, PLAIN_TEXT_KEY, RESTORE_CLIPBOARD_KEY,
+    SENSITIVE_CONTENT_KEY, SHORTCUT_KEY, SHOW_TRAY_ICON_KEY,
+};
+
+pub const DEFAULT_SHORTCUT: &str = "Alt+V";
+
+/// User settings persisted next to the history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub show_tray_icon: bool,
+    pub shortcut: String,
+    /// Shortcuts for the other actions.
+    pub keymap: Keymap,
+    pub open_at_login: bool,
+    pub paste_strategy: PasteStrategy,
+    pub paste_profiles: PasteProfiles,
+    /// Clean-up when pasting as plain text.
+    pub plain_text: PlainTextOptions,
+    /// Put back what was on the clipboard after pasting a history item.
+    pub restore_clipboard: bool,
+    pub history_limits: HistoryLimits,
+    /// Treat texts that differ only in whitespace as the same item.
+    pub ignore_whitespace_duplicates: bool,
+    pub excluded_apps: ExcludedApps,
+    pub sensitive_content: SensitiveSettings,
+    /// Keep capture paused across restarts.
+    pub persist_pause: bool,
+    /// The pause saved when `persist_pause` is on.
+    pub capture_pause: Option<Pause>,
+    /// Set while the history is encrypted at rest.
+    pub encryption: Option<EncryptionConfig>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            show_tray_icon: true,
+            shortcut: DEFAULT_SHORTCUT.to_string(),
+            keymap: Keymap::default(),
+            open_at_login: false,
+            paste_strategy: PasteStrategy::default(),
+            paste_profiles: PasteProfiles::default(),
+            plain_text: PlainTextOptions::default(),
+            restore_clipboard: false,
+            history_limits: HistoryLimits::default(),
+            ignore_whitespace_duplicates: false,
+            excluded_apps: ExcludedApps::default(),
+            sensitive_content: SensitiveSettings::default(),
+            persist_pause: false,
+            capture_pause: None,
+            encryption: None,
+        }
+    }
+}
+
+impl Settings {
+    /// Loads settings from the store, keeping defaults for missing keys.
+    pub fn load(store: &dyn KeyValueStore) -> Self {
+        let mut settings = Self::default();
+
+        if let Some(show) = store.get(SHOW_TRAY_ICON_KEY).and_then(|v| v.as_bool()) {
+            settings.show_tray_icon = show;
+        }
+
+        if let Some(shortcut) = store
+            .get(SHORTCUT_KEY)
+            .and_then(|v| v.as_str().map(String::from))
+        {
+            settings.shortcut = shortcut;
+        }
+
+        // Saved shortcuts are checked here, so the app can register them as is
+        let shortcut = Accelerator::parse(&settings.shortcut).or_else(|e| {
+            log::warn!("Ignoring saved shortcut: {}", e);
+            Accelerator::parse(DEFAULT_SHORTCUT)
+        });
+        if let Ok(shortcut) = shortcut {
+            settings.shortcut = shortcut.to_string();
+
+            let bindings = store
+                .get(KEYMAP_KEY)
+                .and_then(|v| serde_json::from_value(v).ok())
+                .unwrap_or_else(|| settings.keymap.bindings().to_vec());
+            settings.keymap = Keymap::new_lossy(bindings, &shortcut);
+        }
+
+        if let Some(login) = store.get(OPEN_AT_LOGIN_KEY).and_then(|v| v.as_bool()) {
+            settings.open_at_login = login;
+        }
+
+        if let Some(strategy) = store
+            .get(PASTE_STRATEGY_KEY)
+            .and_then(|v| serde_json::from_value(v).ok())
+        {
+            settings.paste_strategy = strategy;
+        }
+
+        if let Some(profiles) = store
+            .get(PASTE_PROFILES_KEY)
+            .and_then(|v| serde_json::from_value(v).ok())
+        {
+            settings.paste_profiles = profiles;
+        }
+
+        if let Some(options) = store
+            .get(PLAIN_TEXT_KEY)
+            .and_then(|v| serde_json::from_value(v).ok())
+        {
+            settings.plain_text = options;
+        }
+
+        if let Some(restore) = store.get(RESTORE_CLIPBOARD_KEY).and_then(|v| v.as_bool()) {
+            settings.restore_clipboard = restore;
+        }
+
+        if let Some(limits) = store
+            .get(HISTORY_LIMITS_KEY)
+            .and_then(|v| serde_json::from_value(v).ok())
+        {
+            settings.history_limits = limits;
+        }
+
+        if let Some(ignore) = store
+            .get(IGNORE_WHITESPACE_DUPLICATES_KEY)
+            .and_then(|v| v.as_bool())
+        {
+            settings.ignore_whitespace_duplicates = ignore;
+        }
+
+        if let Some(apps) = store
+            .get(EXCLUDED_APPS_KEY)
+            .and_then(|v| serde_json::from_value(v).ok())
+        {
+            settings.excluded_apps = ExcludedApps::new(apps);
+        }
+
+        if let Some(sensitive) = store
+            .get(SENSITIVE_CONTENT_KEY)
+            .and_then(|v| serde_json::from_value(v).ok())
+        {
+            settings.sensitive_content = sensitive;
+        }
+
+        if let Some(persist) = store.get(PERSIST_PAUSE_KEY).and_then(|v| v.as_bool()) {
+            settings.persist_pause = persist;
+        }
+
+        if settings.persist_pause {
+            settings.capture_pause = store
+                .get(CAPTURE_PAUSE_KEY)
+                .and_then(|v| serde_json::from_value(v).ok());
+        }
+
+        settings.encryption = store
+            .get(ENCRYPTION_KEY)
+            .and_then(|v| serde_json::from_value(v).ok());
+
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::{Action, Binding};
+    use crate::store::MemoryStore;
+    use serde_json::json;
+
+    #[test]
+    fn keeps_defaults_for_missing_or_invalid_values() {
+        let store = MemoryStore::new();
+        assert_eq!(Settings::load(&store), Settings::default());
+
+        store.set(SHOW_TRAY_ICON_KEY, json!("no"));
+        store.set(HISTORY_LIMITS_KEY, json!(42));
+        assert_eq!(Settings::load(&store), Settings::default());
+    }
+
+    #[test]
+    fn loads_saved_values() {
+        let store = MemoryStore::new();
+        store.set(SHOW_TRAY_ICON_KEY, json!(false));
+        store.set(SHORTCUT_KEY, json!("ctrl+alt+v"));
+        store.set(RESTORE_CLIPBOARD_KEY, json!(true));
+        store.set(HISTORY_LIMITS_KEY, json!({ "maxItems": 10 }));
+        store.set(EXCLUDED_APPS_KEY, json!(["Terminal", "terminal"]));
+
+        let settings = Settings::load(&store);
+        assert!(!settings.show_tray_icon);
+        assert_eq!(settings.shortcut, "Control+Alt+V");
+        assert!(settings.restore_clipboard);
+        assert_eq!(settings.history_limits.max_items, 10);
+        assert_eq!(settings.excluded_apps.apps(), ["Terminal"]);
+    }
+
+    #[test]
+    fn checks_the_saved_shortcut_and_keymap() {
+        let store = MemoryStore::new();
+        store.set(SHORTCUT_KEY, json!("Shift+V"));
+        store.set(
+            KEYMAP_KEY,
+            json!([
+                Binding::new(Action::PastePrevious, "Alt+V"),
+                Binding::new(Action::PauseCapture, "Control+Alt+P"),
+            ]),
+        );
+
+        // The popup falls back to the default shortcut, which the keymap may
+        // not take
+        let settings = Settings::load(&store);
+        assert_eq!(settings.shortcut, DEFAULT_SHORTCUT);
+        assert_eq!(
+            settings.keymap.bindings(),
+            [Binding::new(Action::PauseCapture, "Control+Alt+P")]
+        );
+    }
+
+    #[test]
+    fn restores_the_pause_only_when_persisted() {
+        let store = MemoryStore::new();
+        store.set(CAPTURE_PAUSE_KEY, json!({ "mode": "indefinite" }));
+        assert_eq!(Settings::load(&store).capture_pause, None);
+
+        store.set(PERSIST_PAUSE_KEY, json!(true));
+        assert_eq!(
+            Settings::load(&store).capture_pause,
+            Some(Pause::Indefinite)
+        );
+    }
+}
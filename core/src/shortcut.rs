@@ -0,0 +1,407 @@
+//! Global shortcut accelerators such as `CommandOrControl+Shift+V`.
+//!
+//! [`Accelerator::parse`] accepts the usual spellings of modifiers and keys
+//! and rejects combinations that cannot work as a global shortcut. The
+//! `Display` form is canonical and can be parsed by the global shortcut
+//! plugin.
+
+use crate::error::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// Taken by the system or by every app, so registering them would either
+/// fail or break something the user relies on.
+#[cfg(target_os = "macos")]
+const RESERVED: &[&str] = &[
+    "Command+Q",
+    "Command+W",
+    "Command+H",
+    "Command+M",
+    "Command+A",
+    "Command+C",
+    "Command+V",
+    "Command+X",
+    "Command+Z",
+    "Command+Tab",
+    "Command+Space",
+    "Control+Space",
+    "Command+Shift+3",
+    "Command+Shift+4",
+    "Command+Shift+5",
+];
+#[cfg(not(target_os = "macos"))]
+const RESERVED: &[&str] = &[
+    "Control+A",
+    "Control+C",
+    "Control+V",
+    "Control+X",
+    "Control+Z",
+    "Alt+Tab",
+    "Alt+F4",
+    "Super+L",
+];
+
+/// In canonical order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Modifier {
+    /// ⌘ on macOS, the Windows or Super key elsewhere.
+    Super,
+    Control,
+    Alt,
+    Shift,
+}
+
+impl Modifier {
+    fn parse(token: &str) -> Option<Self> {
+        match token.to_ascii_uppercase().as_str() {
+            "SUPER" | "COMMAND" | "CMD" | "META" => Some(Modifier::Super),
+            "CONTROL" | "CTRL" => Some(Modifier::Control),
+            "ALT" | "OPTION" | "OPT" => Some(Modifier::Alt),
+            "SHIFT" => Some(Modifier::Shift),
+            "COMMANDORCONTROL" | "COMMANDORCTRL" | "CMDORCONTROL" | "CMDORCTRL" => {
+                Some(Self::command_or_control())
+            }
+            _ => None,
+        }
+    }
+
+    fn command_or_control() -> Self {
+        if cfg!(target_os = "macos") {
+            Modifier::Super
+        } else {
+            Modifier::Control
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Modifier::Super if cfg!(target_os = "macos") => "Command",
+            Modifier::Super => "Super",
+            Modifier::Control => "Control",
+            Modifier::Alt => "Alt",
+            Modifier::Shift => "Shift",
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Modifier::Super => "⌘",
+            Modifier::Control => "⌃",
+            Modifier::Alt => "⌥",
+            Modifier::Shift => "⇧",
+        }
+    }
+}
+
+/// A validated global shortcut: at least one modifier other than Shift and
+/// exactly one key. Function keys may be used alone.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Accelerator {
+    modifiers: Vec<Modifier>,
+    key: &'static str,
+}
+
+impl Accelerator {
+    /// Modifiers come first, then the key, separated by `+`. Case and
+    /// spaces around tokens do not matter.
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = |reason: String| Error::InvalidShortcut(format!("{:?}: {}", text, reason));
+
+        if text.trim().is_empty() {
+            return Err(invalid("the shortcut is empty".to_string()));
+        }
+
+        let mut modifiers = Vec::new();
+        let mut key = None;
+        for token in text.split('+').map(str::trim) {
+            if token.is_empty() {
+                return Err(invalid("empty key name".to_string()));
+            }
+            if key.is_some() {
+                return Err(invalid("the key must come last".to_string()));
+            }
+
+            if let Some(modifier) = Modifier::parse(token) {
+                if modifiers.contains(&modifier) {
+                    return Err(invalid(format!("{} is given twice", modifier.name())));
+                }
+                modifiers.push(modifier);
+            } else {
+                key = Some(
+                    parse_key(token).ok_or_else(|| invalid(format!("unknown key {}", token)))?,
+                );
+            }
+        }
+        let key = key.ok_or_else(|| invalid("no key besides the modifiers".to_string()))?;
+
+        // Plain or shifted keys would swallow ordinary typing
+        let is_function_key = key.len() > 1 && key.starts_with('F');
+        if !is_function_key
+            && modifiers
+                .iter()
+                .all(|&modifier| modifier == Modifier::Shift)
+        {
+            return Err(invalid(
+                "add Control, Alt or Command so it does not block typing".to_string(),
+            ));
+        }
+
+        modifiers.sort();
+        Ok(Self { modifiers, key })
+    }
+
+    pub fn modifiers(&self) -> &[Modifier] {
+        &self.modifiers
+    }
+
+    pub fn key(&self) -> &str {
+        self.key
+    }
+
+    /// Compact form for menus, such as `⌃⌥V`.
+    pub fn label(&self) -> String {
+        // macOS menus list modifiers in this order
+        let order = [
+            Modifier::Control,
+            Modifier::Alt,
+            Modifier::Shift,
+            Modifier::Super,
+        ];
+        let mut label: String = order
+            .iter()
+            .filter(|modifier| self.modifiers.contains(modifier))
+            .map(|modifier| modifier.symbol())
+            .collect();
+        label.push_str(self.key);
+        label
+    }
+
+    /// Fails if the accelerator is reserved by the system or equals one of
+    /// `in_use`, which pairs shortcuts with what they do.
+    pub fn check_conflicts(&self, in_use: &[(&Accelerator, &str)]) -> Result<()> {
+        if let Some((_, action)) = in_use.iter().find(|(other, _)| *other == self) {
+            return Err(Error::ShortcutConflict(format!(
+                "{} is already used to {}",
+                self, action
+            )));
+        }
+
+        let reserved = RESERVED
+            .iter()
+            .any(|reserved| Accelerator::parse(reserved).is_ok_and(|reserved| reserved == *self));
+        if reserved {
+            return Err(Error::ShortcutConflict(format!(
+                "{} is reserved by the system",
+                self
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Accelerator {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        Self::parse(text)
+    }
+}
+
+impl fmt::Display for Accelerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{}+", modifier.name())?;
+        }
+        f.write_str(self.key)
+    }
+}
+
+const LETTERS: [&str; 26] = [
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S",
+    "T", "U", "V", "W", "X", "Y", "Z",
+];
+const DIGITS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+const FUNCTION_KEYS: [&str; 12] = [
+    "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12",
+];
+
+/// Returns the canonical name of a key, accepting `KeyboardEvent.code`
+/// names such as `KeyV` and `Digit1` as well.
+fn parse_key(token: &str) -> Option<&'static str> {
+    let upper = token.to_ascii_uppercase();
+    let name = upper
+        .strip_prefix("KEY")
+        .or_else(|| upper.strip_prefix("DIGIT"))
+        .filter(|rest| rest.len() == 1)
+        .unwrap_or(&upper);
+
+    if let Some(key) = LETTERS
+        .iter()
+        .chain(&DIGITS)
+        .chain(&FUNCTION_KEYS)
+        .find(|key| **key == name)
+    {
+        return Some(key);
+    }
+
+    let key = match name {
+        "SPACE" => "Space",
+        "ENTER" | "RETURN" => "Enter",
+        "TAB" => "Tab",
+        "BACKSPACE" => "Backspace",
+        "DELETE" | "DEL" => "Delete",
+        "ESCAPE" | "ESC" => "Escape",
+        "INSERT" => "Insert",
+        "HOME" => "Home",
+        "END" => "End",
+        "PAGEUP" => "PageUp",
+        "PAGEDOWN" => "PageDown",
+        "UP" | "ARROWUP" => "Up",
+        "DOWN" | "ARROWDOWN" => "Down",
+        "LEFT" | "ARROWLEFT" => "Left",
+        "RIGHT" | "ARROWRIGHT" => "Right",
+        "BACKQUOTE" | "`" => "Backquote",
+        "MINUS" | "-" => "Minus",
+        "EQUAL" | "=" => "Equal",
+        "BRACKETLEFT" | "[" => "BracketLeft",
+        "BRACKETRIGHT" | "]" => "BracketRight",
+        "BACKSLASH" | "\\" => "Backslash",
+        "SEMICOLON" | ";" => "Semicolon",
+        "QUOTE" | "'" => "Quote",
+        "COMMA" | "," => "Comma",
+        "PERIOD" | "." => "Period",
+        "SLASH" | "/" => "Slash",
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Accelerator {
+        Accelerator::parse(text).unwrap()
+    }
+
+    fn rejection(text: &str) -> String {
+        match Accelerator::parse(text) {
+            Err(Error::InvalidShortcut(reason)) => reason,
+            other => panic!("{:?} was not rejected: {:?}", text, other),
+        }
+    }
+
+    #[test]
+    fn accepts_modifier_aliases() {
+        assert_eq!(parse("Cmd+V"), parse("Command+V"));
+        assert_eq!(parse("meta+v"), parse("Super+V"));
+        assert_eq!(parse("Option+V"), parse("Alt+V"));
+        assert_eq!(parse("Opt+V"), parse("Alt+V"));
+        assert_eq!(parse("Ctrl+V"), parse("Control+V"));
+
+        let command_or_control = parse("CommandOrControl+V");
+        assert_eq!(
+            command_or_control.modifiers(),
+            [Modifier::command_or_control()]
+        );
+        assert_eq!(parse("CmdOrCtrl+V"), command_or_control);
+    }
+
+    #[test]
+    fn accepts_key_codes_and_symbols() {
+        assert_eq!(parse("Alt+KeyV").key(), "V");
+        assert_eq!(parse("Alt+Digit1").key(), "1");
+        assert_eq!(parse("Alt+ArrowUp").key(), "Up");
+        assert_eq!(parse("Alt+Esc").key(), "Escape");
+        assert_eq!(parse("Alt+/").key(), "Slash");
+        // Only single letters and digits follow the prefixes
+        assert!(Accelerator::parse("Alt+KeyVV").is_err());
+        assert!(Accelerator::parse("Alt+Digit10").is_err());
+    }
+
+    #[test]
+    fn ignores_case_spaces_and_modifier_order() {
+        let accelerator = parse(" shift + alt + control + v ");
+        assert_eq!(
+            accelerator.modifiers(),
+            [Modifier::Control, Modifier::Alt, Modifier::Shift]
+        );
+        assert_eq!(accelerator, parse("Control+Alt+Shift+V"));
+    }
+
+    #[test]
+    fn rejects_malformed_shortcuts() {
+        assert!(rejection("").contains("empty"));
+        assert!(rejection("Alt++V").contains("empty key name"));
+        assert!(rejection("Alt+Alt+V").contains("given twice"));
+        // Aliases of one modifier count as the same
+        assert!(rejection("Cmd+Super+V").contains("given twice"));
+        assert!(rejection("V+Alt").contains("must come last"));
+        assert!(rejection("Alt+V+B").contains("must come last"));
+        assert!(rejection("Alt+Shift").contains("no key"));
+        assert!(rejection("Alt+Hyper").contains("unknown key"));
+    }
+
+    #[test]
+    fn rejects_shortcuts_that_block_typing() {
+        assert!(rejection("V").contains("does not block typing"));
+        assert!(rejection("Shift+V").contains("does not block typing"));
+        assert!(rejection("Shift+1").contains("does not block typing"));
+    }
+
+    #[test]
+    fn function_keys_may_stand_alone() {
+        assert!(parse("F9").modifiers().is_empty());
+        assert_eq!(parse("Shift+F12").key(), "F12");
+        assert!(Accelerator::parse("F13").is_err());
+    }
+
+    #[test]
+    fn displays_the_canonical_form() {
+        let accelerator = parse("shift+opt+ctrl+keyv");
+        assert_eq!(accelerator.to_string(), "Control+Alt+Shift+V");
+        assert_eq!(accelerator.label(), "⌃⌥⇧V");
+
+        for text in ["Cmd+Shift+V", "CommandOrControl+Digit1", "F5", "Alt+Space"] {
+            let accelerator = parse(text);
+            let canonical = accelerator.to_string();
+            assert_eq!(parse(&canonical), accelerator);
+            assert_eq!(parse(&canonical).to_string(), canonical);
+        }
+
+        let super_name = if cfg!(target_os = "macos") {
+            "Command"
+        } else {
+            "Super"
+        };
+        assert_eq!(parse("Cmd+V").to_string(), format!("{}+V", super_name));
+        assert_eq!("Alt+F4".parse::<Accelerator>().unwrap(), parse("Option+F4"));
+    }
+
+    #[test]
+    fn conflicts_with_shortcuts_in_use() {
+        let popup = parse("Control+Alt+V");
+        let other = parse("Control+Alt+B");
+        let in_use = [(&popup, "open the popup")];
+
+        let conflict = parse("alt+ctrl+v").check_conflicts(&in_use);
+        assert!(matches!(
+            conflict,
+            Err(Error::ShortcutConflict(reason)) if reason.contains("open the popup")
+        ));
+        assert!(other.check_conflicts(&in_use).is_ok());
+    }
+
+    #[test]
+    fn conflicts_with_reserved_shortcuts() {
+        // Paste is reserved on every platform
+        let paste = parse("CommandOrControl+V");
+        assert!(matches!(
+            paste.check_conflicts(&[]),
+            Err(Error::ShortcutConflict(reason)) if reason.contains("reserved")
+        ));
+        assert!(parse("CommandOrControl+Shift+V")
+            .check_conflicts(&[])
+            .is_ok());
+    }
+}
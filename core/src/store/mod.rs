@@ -0,0 +1,272 @@
+//! Persistence of settings and history.
+//!
+//! Settings live in a JSON [`KeyValueStore`] (`store.json`), while history
+//! entries go to a [`HistoryStore`] that is updated incrementally.
+
+mod sqlite;
+
+use crate::encryption::Protection;
+use crate::error::Result;
+use crate::history::{now_millis, Entry, EntryId, HistoryDiff};
+use crate::item::HistoryItem;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+pub use sqlite::SqliteHistoryStore;
+
+pub const STORE_FILE: &str = "store.json";
+pub const HISTORY_DATABASE_FILE: &str = "history.sqlite3";
+
+pub const HISTORY_KEY: &str = "history";
+pub const HISTORY_LIMITS_KEY: &str = "historyLimits";
+pub const EXCLUDED_APPS_KEY: &str = "excludedApps";
+pub const IGNORE_WHITESPACE_DUPLICATES_KEY: &str = "ignoreWhitespaceDuplicates";
+pub const SENSITIVE_CONTENT_KEY: &str = "sensitiveContent";
+pub const PERSIST_PAUSE_KEY: &str = "persistPause";
+pub const CAPTURE_PAUSE_KEY: &str = "capturePause";
+pub const ENCRYPTION_KEY: &str = "encryption";
+pub const SHOW_TRAY_ICON_KEY: &str = "showTrayIcon";
+pub const SHORTCUT_KEY: &str = "shortcut";
+pub const KEYMAP_KEY: &str = "keymap";
+pub const OPEN_AT_LOGIN_KEY: &str = "openAtLogin";
+pub const PASTE_STRATEGY_KEY: &str = "pasteStrategy";
+pub const PASTE_PROFILES_KEY: &str = "pasteProfiles";
+pub const PLAIN_TEXT_KEY: &str = "plainText";
+pub const RESTORE_CLIPBOARD_KEY: &str = "restoreClipboard";
+
+/// A JSON key/value store, such as `tauri_plugin_store`'s `store.json`.
+pub trait KeyValueStore {
+    fn get(&self, key: &str) -> Option<Value>;
+    fn set(&self, key: &str, value: Value);
+    fn delete(&self, key: &str);
+    fn save(&self) -> Result<()>;
+}
+
+/// Sets a single key and flushes the store.
+pub fn save_value(store: &dyn KeyValueStore, key: &str, value: Value) -> Result<()> {
+    store.set(key, value);
+    store.save()
+}
+
+/// Reads the history array that older versions kept in `store.json`.
+/// Entries with an unknown shape are skipped.
+pub fn load_history(store: &dyn KeyValueStore) -> Option<Vec<HistoryItem>> {
+    let items = serde_json::from_value::<Vec<Value>>(store.get(HISTORY_KEY)?).ok()?;
+
+    Some(
+        items
+            .iter()
+            .filter_map(|item| {
+                let type_str = item.get("type").and_then(|v| v.as_str())?;
+                let content = item.get("content").and_then(|v| v.as_str())?;
+                HistoryItem::from_parts(type_str, content)
+            })
+            .collect(),
+    )
+}
+
+/// Moves the history array of the legacy `store.json` format into `target`,
+/// then drops it from the key/value store so the import happens only once.
+/// Returns the number of imported entries.
+///
+/// The entries are inserted all at once, and the array is only dropped after
+/// that succeeded, so an interrupted import is retried on the next start.
+pub fn migrate_legacy_history(kv: &dyn KeyValueStore, target: &dyn HistoryStore) -> Result<usize> {
+    let Some(items) = load_history(kv) else {
+        return Ok(0);
+    };
+
+    // A non-empty store means a previous import went through, but the array
+    // could not be dropped afterwards
+    let mut imported = 0;
+    if target.load()?.is_empty() {
+        let now = now_millis();
+        let count = items.len();
+        let entries: Vec<Entry> = items
+            .into_iter()
+            .enumerate()
+            // The array is newest first; keep that order through IDs and times
+            .map(|(index, item)| Entry::new((count - index) as EntryId, now - index as i64, item))
+            .collect();
+        target.insert_all(&entries)?;
+        imported = entries.len();
+    }
+
+    kv.delete(HISTORY_KEY);
+    kv.save()?;
+    Ok(imported)
+}
+
+/// Persistent storage for history entries.
+pub trait HistoryStore: Send + Sync {
+    /// Loads every entry, most recently copied first.
+    fn load(&self) -> Result<Vec<Entry>>;
+    fn insert(&self, entry: &Entry) -> Result<()>;
+
+    /// Inserts `entries` so that either all of them or none are stored.
+    /// Stores that can fail halfway through must override this.
+    fn insert_all(&self, entries: &[Entry]) -> Result<()> {
+        for entry in entries {
+            self.insert(entry)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, ids: &[EntryId]) -> Result<()>;
+
+    /// Sets how entries are protected on disk, e.g. once the key has been
+    /// unlocked. Stored entries are left as they are.
+    fn set_protection(&self, _protection: Protection) {}
+
+    /// Rewrites every stored entry under `protection` and switches to it.
+    fn rekey(&self, protection: Protection) -> Result<()> {
+        self.set_protection(protection);
+        Ok(())
+    }
+
+    fn apply(&self, diff: &HistoryDiff) -> Result<()> {
+        for entry in diff.inserted.iter().chain(&diff.updated) {
+            self.insert(entry)?;
+        }
+        if !diff.removed.is_empty() {
+            let ids: Vec<EntryId> = diff.removed.iter().map(|entry| entry.id).collect();
+            self.remove(&ids)?;
+        }
+        Ok(())
+    }
+}
+
+/// [`HistoryStore`] that keeps entries in memory only.
+#[derive(Debug, Default)]
+pub struct MemoryHistoryStore {
+    entries: Mutex<BTreeMap<EntryId, Entry>>,
+}
+
+impl MemoryHistoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl HistoryStore for MemoryHistoryStore {
+    fn load(&self) -> Result<Vec<Entry>> {
+        let mut entries: Vec<Entry> = self.entries.lock().unwrap().values().cloned().collect();
+        entries.sort_by_key(|entry| Reverse((entry.last_copied_at, entry.id)));
+        Ok(entries)
+    }
+
+    fn insert(&self, entry: &Entry) -> Result<()> {
+        self.entries.lock().unwrap().insert(entry.id, entry.clone());
+        Ok(())
+    }
+
+    fn remove(&self, ids: &[EntryId]) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        for id in ids {
+            entries.remove(id);
+        }
+        Ok(())
+    }
+}
+
+/// In-memory [`KeyValueStore`] for tests and front-ends without persistence.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    values: Mutex<HashMap<String, Value>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl KeyValueStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<Value> {
+        self.values.lock().unwrap().get(key).cloned()
+    }
+
+    fn set(&self, key: &str, value: Value) {
+        self.values.lock().unwrap().insert(key.to_string(), value);
+    }
+
+    fn delete(&self, key: &str) {
+        self.values.lock().unwrap().remove(key);
+    }
+
+    fn save(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use serde_json::json;
+
+    /// Fails every insert after the first, like a disk filling up.
+    #[derive(Default)]
+    struct FailingStore {
+        inserted: Mutex<usize>,
+    }
+
+    impl HistoryStore for FailingStore {
+        fn load(&self) -> Result<Vec<Entry>> {
+            Ok(Vec::new())
+        }
+
+        fn insert(&self, _entry: &Entry) -> Result<()> {
+            let mut inserted = self.inserted.lock().unwrap();
+            if *inserted > 0 {
+                return Err(Error::Store("disk full".to_string()));
+            }
+            *inserted += 1;
+            Ok(())
+        }
+
+        fn remove(&self, _ids: &[EntryId]) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn legacy_store() -> MemoryStore {
+        let kv = MemoryStore::new();
+        kv.set(
+            HISTORY_KEY,
+            json!([
+                { "type": "text", "content": "newest" },
+                { "type": "text", "content": "older" },
+                { "type": "text", "content": "oldest" },
+            ]),
+        );
+        kv
+    }
+
+    #[test]
+    fn migrates_legacy_history_newest_first() {
+        let kv = legacy_store();
+        let target = SqliteHistoryStore::open_in_memory().unwrap();
+
+        assert_eq!(migrate_legacy_history(&kv, &target).unwrap(), 3);
+        assert!(kv.get(HISTORY_KEY).is_none());
+
+        let texts: Vec<_> = target
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.item.text().unwrap().to_string())
+            .collect();
+        assert_eq!(texts, ["newest", "older", "oldest"]);
+    }
+
+    #[test]
+    fn keeps_legacy_history_when_import_fails() {
+        let kv = legacy_store();
+
+        assert!(migrate_legacy_history(&kv, &FailingStore::default()).is_err());
+        assert!(kv.get(HISTORY_KEY).is_some());
+    }
+}
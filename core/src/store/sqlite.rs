@@ -0,0 +1,331 @@
+use super::HistoryStore;
+use crate::encryption::Protection;
+use crate::error::{Error, Result};
+use crate::history::{Entry, EntryId};
+use crate::item::HistoryItem;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Schema migrations, applied in order. `PRAGMA user_version` records how
+/// many have run.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS history (
+        id INTEGER PRIMARY KEY,
+        kind TEXT NOT NULL,
+        content TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS history_created_at ON history (created_at);",
+    // Images moved to the blob store; `content` now holds the hash
+    "ALTER TABLE history ADD COLUMN size INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE history ADD COLUMN source_app TEXT;
+    ALTER TABLE history ADD COLUMN last_used_at INTEGER;
+    ALTER TABLE history ADD COLUMN paste_count INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE history ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;",
+    // Re-copied items move to the top, so order by the latest copy
+    "ALTER TABLE history ADD COLUMN last_copied_at INTEGER NOT NULL DEFAULT 0;
+    UPDATE history SET last_copied_at = created_at;
+    CREATE INDEX history_last_copied_at ON history (last_copied_at);",
+    "ALTER TABLE history ADD COLUMN sensitive INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE history ADD COLUMN expires_at INTEGER;",
+    // `content` is sealed and base64-encoded when set
+    "ALTER TABLE history ADD COLUMN encrypted INTEGER NOT NULL DEFAULT 0;",
+];
+
+/// [`HistoryStore`] backed by an embedded SQLite database.
+pub struct SqliteHistoryStore {
+    conn: Mutex<Connection>,
+    protection: Mutex<Protection>,
+}
+
+impl SqliteHistoryStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            protection: Mutex::new(Protection::default()),
+        })
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Seals `content` if the store is encrypted. Only the content is
+/// encrypted; metadata such as the source app stays readable.
+fn encode_content(protection: &Protection, content: &str) -> Result<(String, bool)> {
+    match protection.cipher()? {
+        Some(cipher) => Ok((BASE64.encode(cipher.seal(content.as_bytes())?), true)),
+        None => Ok((content.to_string(), false)),
+    }
+}
+
+fn decode_content(protection: &Protection, content: String, encrypted: bool) -> Result<String> {
+    if !encrypted {
+        return Ok(content);
+    }
+    let cipher = protection.cipher()?.ok_or(Error::Locked)?;
+    let sealed = BASE64
+        .decode(content)
+        .map_err(|e| Error::Encryption(format!("invalid sealed content: {}", e)))?;
+    String::from_utf8(cipher.open(&sealed)?)
+        .map_err(|e| Error::Encryption(format!("invalid sealed content: {}", e)))
+}
+
+fn insert_entry(conn: &Connection, protection: &Protection, entry: &Entry) -> Result<()> {
+    let (kind, content) = entry.item.to_parts();
+    let (content, encrypted) = encode_content(protection, &content)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO history
+         (id, kind, content, created_at, size, source_app, last_used_at, paste_count, pinned,
+          last_copied_at, sensitive, expires_at, encrypted)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        params![
+            entry.id as i64,
+            kind,
+            content,
+            entry.created_at,
+            entry.size_bytes() as i64,
+            entry.source_app,
+            entry.last_used_at,
+            entry.paste_count,
+            entry.pinned,
+            entry.last_copied_at,
+            entry.sensitive,
+            entry.expires_at,
+            encrypted
+        ],
+    )?;
+    Ok(())
+}
+
+impl HistoryStore for SqliteHistoryStore {
+    fn load(&self) -> Result<Vec<Entry>> {
+        let protection = self.protection.lock().unwrap().clone();
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT id, kind, content, created_at, size, source_app, last_used_at, paste_count,
+                    pinned, last_copied_at, sensitive, expires_at, encrypted
+             FROM history
+             ORDER BY last_copied_at DESC, id DESC",
+        )?;
+        let mut rows = statement.query([])?;
+
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let kind: String = row.get(1)?;
+            let size: i64 = row.get(4)?;
+            let content = decode_content(&protection, row.get(2)?, row.get(12)?)?;
+
+            match HistoryItem::from_stored(&kind, content, size as u64) {
+                Some(item) => entries.push(Entry {
+                    id: id as EntryId,
+                    created_at: row.get(3)?,
+                    item,
+                    source_app: row.get(5)?,
+                    last_used_at: row.get(6)?,
+                    paste_count: row.get(7)?,
+                    pinned: row.get(8)?,
+                    last_copied_at: row.get(9)?,
+                    sensitive: row.get(10)?,
+                    expires_at: row.get(11)?,
+                }),
+                None => log::warn!("Skipping history entry {} of unknown kind {}", id, kind),
+            }
+        }
+        Ok(entries)
+    }
+
+    fn insert(&self, entry: &Entry) -> Result<()> {
+        let protection = self.protection.lock().unwrap().clone();
+        insert_entry(&self.conn.lock().unwrap(), &protection, entry)
+    }
+
+    fn insert_all(&self, entries: &[Entry]) -> Result<()> {
+        let protection = self.protection.lock().unwrap().clone();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for entry in entries {
+            insert_entry(&tx, &protection, entry)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn remove(&self, ids: &[EntryId]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut statement = tx.prepare("DELETE FROM history WHERE id = ?1")?;
+            for id in ids {
+                statement.execute([*id as i64])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn set_protection(&self, protection: Protection) {
+        *self.protection.lock().unwrap() = protection;
+    }
+
+    fn rekey(&self, protection: Protection) -> Result<()> {
+        let mut current = self.protection.lock().unwrap();
+        let mut conn = self.conn.lock().unwrap();
+
+        let tx = conn.transaction()?;
+        {
+            let mut select = tx.prepare("SELECT id, content, encrypted FROM history")?;
+            let mut update =
+                tx.prepare("UPDATE history SET content = ?2, encrypted = ?3 WHERE id = ?1")?;
+            let rows = select
+                .query_map([], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get(1)?, row.get(2)?))
+                })?
+                .collect::<rusqlite::Result<Vec<(i64, String, bool)>>>()?;
+
+            for (id, content, encrypted) in rows {
+                let content = decode_content(&current, content, encrypted)?;
+                let (content, encrypted) = encode_content(&protection, &content)?;
+                update.execute(params![id, content, encrypted])?;
+            }
+        }
+        tx.commit()?;
+        *current = protection;
+
+        // Drop the old contents from free pages and the write-ahead log
+        if let Err(e) = conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);") {
+            log::warn!("Failed to compact the history after rekeying: {}", e);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::Cipher;
+
+    fn encrypted() -> Protection {
+        Protection::Encrypted(Cipher::new(&Cipher::generate_key()))
+    }
+
+    fn entries() -> Vec<Entry> {
+        (1..=3)
+            .map(|id| Entry::new(id, id as i64, HistoryItem::Text(format!("item {}", id))))
+            .collect()
+    }
+
+    fn texts(store: &SqliteHistoryStore) -> Vec<String> {
+        store
+            .load()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.item.text().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    /// The stored `content` and `encrypted` columns, by ID.
+    fn raw(store: &SqliteHistoryStore) -> Vec<(String, bool)> {
+        let conn = store.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare("SELECT content, encrypted FROM history ORDER BY id")
+            .unwrap();
+        statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn rekey_encrypts_and_decrypts_the_history() {
+        let store = SqliteHistoryStore::open_in_memory().unwrap();
+        store.insert_all(&entries()).unwrap();
+        let expected = texts(&store);
+
+        let key = encrypted();
+        store.rekey(key.clone()).unwrap();
+        assert!(raw(&store)
+            .iter()
+            .all(|(content, encrypted)| *encrypted && !content.contains("item")));
+        assert_eq!(texts(&store), expected);
+
+        // New entries are sealed too
+        store
+            .insert(&Entry::new(4, 4, HistoryItem::Text("item 4".to_string())))
+            .unwrap();
+        assert!(raw(&store)[3].1);
+
+        let other = encrypted();
+        store.rekey(other.clone()).unwrap();
+        assert_eq!(texts(&store).len(), 4);
+        store.set_protection(key);
+        assert!(matches!(store.load(), Err(Error::WrongKey)));
+        store.set_protection(Protection::Locked);
+        assert!(matches!(store.load(), Err(Error::Locked)));
+
+        store.set_protection(other);
+        store.rekey(Protection::Plaintext).unwrap();
+        assert_eq!(raw(&store)[0], ("item 1".to_string(), false),);
+        assert_eq!(texts(&store)[1..], expected[..]);
+    }
+
+    #[test]
+    fn failed_rekey_keeps_the_current_key() {
+        let store = SqliteHistoryStore::open_in_memory().unwrap();
+        store.insert_all(&entries()).unwrap();
+        let key = encrypted();
+        store.rekey(key.clone()).unwrap();
+
+        // A store that was given the wrong key cannot move the history
+        store.set_protection(encrypted());
+        assert!(matches!(
+            store.rekey(Protection::Plaintext),
+            Err(Error::WrongKey)
+        ));
+        store.set_protection(key);
+        assert_eq!(texts(&store).len(), 3);
+    }
+
+    #[test]
+    fn insert_all_is_atomic() {
+        let store = SqliteHistoryStore::open_in_memory().unwrap();
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER fail_second BEFORE INSERT ON history WHEN NEW.id = 2
+                 BEGIN SELECT RAISE(ABORT, 'disk full'); END;",
+            )
+            .unwrap();
+
+        let entries: Vec<Entry> = (1..=3)
+            .map(|id| Entry::new(id, id as i64, HistoryItem::Text(format!("item {}", id))))
+            .collect();
+        assert!(store.insert_all(&entries).is_err());
+        assert!(store.load().unwrap().is_empty());
+    }
+}
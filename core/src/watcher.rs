@@ -0,0 +1,92 @@
+use crate::clipboard::{ClipboardBackend, ClipboardContent};
+use std::sync::Arc;
+
+/// Detects clipboard changes by polling the backend's change count. Changes
+/// made through the backend itself are skipped, so pasting or restoring
+/// content is not mistaken for a copy.
+///
+/// The caller decides how often to [`poll`](Self::poll), which keeps the
+/// watcher deterministic when driven by a [`MemoryClipboard`] in tests.
+///
+/// [`MemoryClipboard`]: crate::clipboard::MemoryClipboard
+pub struct ClipboardWatcher {
+    backend: Arc<dyn ClipboardBackend>,
+    last_count: i64,
+}
+
+impl ClipboardWatcher {
+    pub fn new(backend: Arc<dyn ClipboardBackend>) -> Self {
+        let last_count = backend.change_count();
+        Self {
+            backend,
+            last_count,
+        }
+    }
+
+    /// The change count seen by the last poll.
+    pub fn change_count(&self) -> i64 {
+        self.last_count
+    }
+
+    /// Returns the clipboard contents if they changed since the last poll.
+    pub fn poll(&mut self) -> Option<ClipboardContent> {
+        let current_count = self.backend.change_count();
+        if current_count == self.last_count {
+            return None;
+        }
+        self.last_count = current_count;
+        if self.backend.is_own_write(current_count) {
+            return None;
+        }
+
+        match self.backend.read() {
+            Ok(content) => Some(content),
+            Err(e) => {
+                log::error!("Failed to read clipboard: {}", e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::MemoryClipboard;
+
+    #[test]
+    fn reports_each_copy_once() {
+        let clipboard = Arc::new(MemoryClipboard::new());
+        clipboard.copy(ClipboardContent::text("before"));
+        let mut watcher = ClipboardWatcher::new(clipboard.clone());
+
+        // What was copied before the watcher started is not a change
+        assert_eq!(watcher.poll(), None);
+
+        clipboard.script([ClipboardContent::text("a"), ClipboardContent::text("b")]);
+        clipboard.advance();
+        assert_eq!(watcher.poll(), Some(ClipboardContent::text("a")));
+        assert_eq!(watcher.poll(), None);
+
+        // Only the latest of several copies between polls is seen
+        clipboard.advance();
+        clipboard.copy(ClipboardContent::text("c"));
+        assert_eq!(watcher.poll(), Some(ClipboardContent::text("c")));
+        assert_eq!(watcher.change_count(), clipboard.change_count());
+        assert!(!clipboard.advance());
+    }
+
+    #[test]
+    fn skips_the_backends_own_writes() {
+        let clipboard = Arc::new(MemoryClipboard::new());
+        let mut watcher = ClipboardWatcher::new(clipboard.clone());
+
+        clipboard.write(&ClipboardContent::text("pasted")).unwrap();
+        assert_eq!(watcher.poll(), None);
+        assert_eq!(watcher.change_count(), clipboard.change_count());
+
+        // A copy of the same content by another app is still recorded
+        clipboard.copy(ClipboardContent::text("pasted"));
+        assert_eq!(watcher.poll(), Some(ClipboardContent::text("pasted")));
+    }
+}
@@ -1,22 +1,67 @@
-import { useEffect, useState } from "react";
+import {
+  type FormEvent,
+  Fragment,
+  type MouseEvent,
+  useEffect,
+  useMemo,
+  useState,
+} from "react";
 import useMeasure from "react-use-measure";
-import type { HistoryItem } from "../shared/types";
+import type {
+  CapturePause,
+  CommandError,
+  HistoryItem,
+} from "../shared/types";
 import styles from "./App.module.css";
-import { copyText, tauriApi } from "./api/tauri";
+import { tauriApi, thumbnailUrl } from "./api/tauri";
 
 const api = tauriApi;
 
+/** Newest first, the order the backend keeps. */
+const byLastCopied = (a: HistoryItem, b: HistoryItem) =>
+  b.lastCopiedAt - a.lastCopiedAt || b.id - a.id;
+
+const upsert = (items: HistoryItem[], item: HistoryItem) =>
+  [...items.filter((existing) => existing.id !== item.id), item].sort(
+    byLastCopied
+  );
+
 const App = () => {
-  const [history, setHistory] = useState<HistoryItem[]>([]);
+  const [items, setItems] = useState<HistoryItem[]>([]);
   const [selectedIndex, setSelectedIndex] = useState<number>(0);
   const [trayVisible, setTrayVisible] = useState<boolean>(true);
+  const [capturePause, setCapturePause] = useState<CapturePause | null>(null);
+  const [locked, setLocked] = useState<boolean>(false);
+  const [passphrase, setPassphrase] = useState<string>("");
+  const [unlockError, setUnlockError] = useState<string | null>(null);
   const [ref, bounds] = useMeasure();
   const isJapanese = navigator.language.startsWith("ja");
 
+  // Pinned items get their own section at the top
+  const history = useMemo(
+    () => [
+      ...items.filter((item) => item.pinned),
+      ...items.filter((item) => !item.pinned),
+    ],
+    [items]
+  );
+
   useEffect(() => {
-    api.onHistory((data: HistoryItem[]) => {
-      setHistory(data);
-    });
+    api.onItemAdded((item) => setItems((prev) => upsert(prev, item)));
+    api.onItemUpdated((item) => setItems((prev) => upsert(prev, item)));
+    api.onItemRemoved((id) =>
+      setItems((prev) => prev.filter((item) => item.id !== id))
+    );
+    api.onHistoryReset((data) => setItems([...data].sort(byLastCopied)));
+    (async () => {
+      try {
+        const { total } = await api.getHistory(0, 0);
+        const page = await api.getHistory(0, total);
+        setItems(page.items);
+      } catch {
+        // Locked; the history arrives once unlocked
+      }
+    })();
   }, []);
 
   useEffect(() => {
@@ -26,8 +71,49 @@ const App = () => {
     })();
   }, []);
 
+  useEffect(() => {
+    (async () => {
+      const status = await api.getEncryptionStatus();
+      setLocked(status.locked);
+    })();
+  }, []);
+
+  useEffect(() => {
+    api.onCapturePause(setCapturePause);
+    (async () => {
+      setCapturePause(await api.getCapturePause());
+    })();
+  }, []);
+
+  const pasteItem = async (item: HistoryItem, plainText = false) => {
+    try {
+      await api.pasteItem(item.id, plainText);
+    } catch (error) {
+      if ((error as CommandError).kind === "locked") {
+        setLocked(true);
+      }
+    }
+  };
+
+  const togglePinned = async (item: HistoryItem) => {
+    try {
+      if (item.pinned) {
+        await api.unpinItem(item.id);
+      } else {
+        await api.pinItem(item.id);
+      }
+    } catch (error) {
+      if ((error as CommandError).kind === "locked") {
+        setLocked(true);
+      }
+    }
+  };
+
   useEffect(() => {
     const handler = async (e: KeyboardEvent) => {
+      if (e.target instanceof HTMLInputElement) {
+        return;
+      }
       e.preventDefault();
       if (e.key === "ArrowUp") {
         setSelectedIndex((prev) =>
@@ -40,26 +126,19 @@ const App = () => {
       } else if (e.key === "Enter") {
         const item = history[selectedIndex];
         if (item) {
-          if (item.type === "text") {
-            await copyText(item.content);
-          } else {
-            api.copyImage(item.content);
-          }
-          await api.hideWindow();
-          await api.pasteFromClipboard();
+          await pasteItem(item, e.shiftKey);
+        }
+      } else if (e.key === "p") {
+        const item = history[selectedIndex];
+        if (item) {
+          await togglePinned(item);
         }
       } else if (/^[0-9]$/.test(e.key)) {
         const pressed = Number(e.key);
         const index = pressed === 0 ? 9 : pressed - 1;
         if (index < history.length) {
           const item = history[index];
-          if (item.type === "text") {
-            await copyText(item.content);
-          } else {
-            api.copyImage(item.content);
-          }
-          await api.hideWindow();
-          await api.pasteFromClipboard();
+          await pasteItem(item);
         }
       }
     };
@@ -72,49 +151,119 @@ const App = () => {
     api.updateWindowHeight(bounds.height);
   }, [bounds.height]);
 
-  const handleItemClick = async (item: HistoryItem) => {
-    if (item.type === "text") {
-      await copyText(item.content);
-    } else {
-      api.copyImage(item.content);
+  const handleUnlock = async (e: FormEvent) => {
+    e.preventDefault();
+    try {
+      await api.unlockHistory(passphrase);
+      setLocked(false);
+      setPassphrase("");
+      setUnlockError(null);
+    } catch (error) {
+      setUnlockError(String(error));
     }
-    await api.hideWindow();
-    await api.pasteFromClipboard();
+  };
+
+  // Shift-click pastes without formatting
+  const handleItemClick = async (item: HistoryItem, e: MouseEvent) => {
+    await pasteItem(item, e.shiftKey);
   };
 
   return (
     <main className={styles.root} ref={ref}>
+      {locked ? (
+        <form className={styles.unlock} onSubmit={handleUnlock}>
+          <input
+            autoFocus
+            className={styles.passphrase}
+            onChange={(e) => setPassphrase(e.target.value)}
+            placeholder={isJapanese ? "パスフレーズ" : "Passphrase"}
+            type="password"
+            value={passphrase}
+          />
+          {unlockError ? (
+            <div className={styles.section}>{unlockError}</div>
+          ) : null}
+        </form>
+      ) : null}
+      {capturePause ? (
+        <div className={styles.section}>
+          {isJapanese ? "記録を一時停止中" : "Capture paused"}
+        </div>
+      ) : null}
       {history.map((item, index) => (
-        <button
-          className={`${styles.item} ${
-            index === selectedIndex && selectedIndex !== -1
-              ? styles.selected
-              : ""
-          }`}
-          key={`${item.type}-${item.content.slice(0, 20)}-${index}`}
-          onClick={() => handleItemClick(item)}
-          onMouseEnter={() => {
-            setSelectedIndex(index);
-          }}
-          type="button"
-        >
-          <div>
-            {item.type === "text" ? (
-              <div className={styles.text}>{item.content.slice(0, 100)}</div>
-            ) : (
-              <div className={styles.imageContainer}>
-                <img
-                  alt="Clipboard content"
-                  className={styles.image}
-                  src={item.content}
-                />
-              </div>
-            )}
-          </div>
-          <div className={styles.num}>{index < 9 ? index + 1 : 0}</div>
-        </button>
+        <Fragment key={item.id}>
+          {index === 0 && item.pinned ? (
+            <div className={styles.section}>
+              {isJapanese ? "ピン留め" : "Pinned"}
+            </div>
+          ) : null}
+          {index > 0 && history[index - 1].pinned && !item.pinned ? (
+            <hr className={styles.hr} />
+          ) : null}
+          <button
+            className={`${styles.item} ${
+              index === selectedIndex && selectedIndex !== -1
+                ? styles.selected
+                : ""
+            }`}
+            onClick={(e) => handleItemClick(item, e)}
+            onMouseEnter={() => {
+              setSelectedIndex(index);
+            }}
+            type="button"
+          >
+            <div>
+              {item.imageHash && (item.type === "image" || !item.content) ? (
+                <div className={styles.imageContainer}>
+                  <img
+                    alt="Clipboard content"
+                    className={styles.image}
+                    src={thumbnailUrl(item.imageHash)}
+                  />
+                </div>
+              ) : item.type === "files" && item.files ? (
+                <div className={styles.text}>
+                  {item.files.map((file) => (
+                    <div
+                      className={file.exists ? undefined : styles.missing}
+                      key={file.path}
+                      title={file.path}
+                    >
+                      {file.name}
+                    </div>
+                  ))}
+                </div>
+              ) : (
+                <div className={styles.text}>{item.content.slice(0, 100)}</div>
+              )}
+            </div>
+            <div className={styles.num}>{index < 9 ? index + 1 : 0}</div>
+          </button>
+        </Fragment>
       ))}
       {history.length > 0 ? <hr className={styles.hr} /> : null}
+      <button
+        className={`${styles.item} ${styles.toggle}`}
+        onClick={() => {
+          if (capturePause) {
+            api.resumeCapture();
+          } else {
+            api.pauseCapture({ mode: "indefinite" });
+          }
+        }}
+        onMouseEnter={() => {
+          setSelectedIndex(-1);
+        }}
+        type="button"
+      >
+        {isJapanese
+          ? capturePause
+            ? "記録を再開"
+            : "記録を一時停止"
+          : capturePause
+            ? "Resume Capture"
+            : "Pause Capture"}
+      </button>
       <button
         className={`${styles.item} ${styles.toggle}`}
         onClick={() => {
@@ -1,21 +1,100 @@
-import { invoke } from "@tauri-apps/api/core";
+import { convertFileSrc, invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
-import type { HistoryItem } from "../../shared/types";
+import type {
+  Binding,
+  CapturePause,
+  EncryptionStatus,
+  HistoryItem,
+  HistoryLimits,
+  HistoryPage,
+  KeySource,
+  PlainTextOptions,
+  SearchPage,
+  SearchQuery,
+  SensitiveSettings,
+} from "../../shared/types";
 
 interface MacopyAPI {
-  onHistory: (callback: (data: HistoryItem[]) => void) => void;
+  onItemAdded: (callback: (item: HistoryItem) => void) => void;
+  onItemUpdated: (callback: (item: HistoryItem) => void) => void;
+  onItemRemoved: (callback: (id: number) => void) => void;
+  onHistoryReset: (callback: (items: HistoryItem[]) => void) => void;
+  onCapturePause: (callback: (pause: CapturePause | null) => void) => void;
   hideWindow: () => Promise<void>;
   pasteFromClipboard: () => Promise<void>;
   updateWindowHeight: (height: number) => void;
-  copyImage: (dataUrl: string) => void;
+  copyItem: (id: number) => Promise<void>;
+  /** With `plainText`, pastes only the text, cleaned up as configured. */
+  pasteItem: (id: number, plainText?: boolean) => Promise<void>;
+  getHistory: (offset?: number, limit?: number) => Promise<HistoryPage>;
+  getItem: (id: number) => Promise<HistoryItem>;
+  deleteItem: (id: number) => Promise<void>;
+  clearHistory: (includePinned?: boolean) => Promise<void>;
+  moveItem: (id: number, index: number) => Promise<void>;
   toggleTrayIcon: () => void;
   getTrayIconState: () => Promise<boolean>;
+  getShortcut: () => Promise<string>;
+  /** Resolves to the canonical form of the shortcut; rejects with the reason it cannot be used. */
+  setShortcut: (shortcut: string) => Promise<string>;
+  getKeymap: () => Promise<Binding[]>;
+  /** Resolves to the bindings with canonical shortcuts. */
+  setKeymap: (bindings: Binding[]) => Promise<Binding[]>;
+  getPlainTextOptions: () => Promise<PlainTextOptions>;
+  setPlainTextOptions: (options: PlainTextOptions) => Promise<void>;
+  /** Whether the previous clipboard is put back after pasting a history item. */
+  getRestoreClipboard: () => Promise<boolean>;
+  setRestoreClipboard: (enabled: boolean) => Promise<void>;
+  getHistoryLimits: () => Promise<HistoryLimits>;
+  setHistoryLimits: (limits: HistoryLimits) => Promise<void>;
+  getIgnoreWhitespaceDuplicates: () => Promise<boolean>;
+  setIgnoreWhitespaceDuplicates: (enabled: boolean) => Promise<void>;
+  /** Rejects with a `CommandError`, e.g. `invalidQuery` for a bad pattern. */
+  searchHistory: (query: SearchQuery) => Promise<SearchPage>;
+  getExcludedApps: () => Promise<string[]>;
+  addExcludedApp: (name: string) => Promise<string[]>;
+  removeExcludedApp: (name: string) => Promise<string[]>;
+  getSensitiveSettings: () => Promise<SensitiveSettings>;
+  setSensitiveSettings: (settings: SensitiveSettings) => Promise<void>;
+  getCapturePause: () => Promise<CapturePause | null>;
+  pauseCapture: (pause: CapturePause) => Promise<void>;
+  resumeCapture: () => Promise<void>;
+  getEncryptionStatus: () => Promise<EncryptionStatus>;
+  unlockHistory: (passphrase: string) => Promise<void>;
+  enableEncryption: (keySource: KeySource, passphrase?: string) => Promise<void>;
+  disableEncryption: () => Promise<void>;
+  /** Rejects with a `CommandError` if the item is gone or the history is locked. */
+  pinItem: (id: number) => Promise<void>;
+  unpinItem: (id: number) => Promise<void>;
   quitApp: () => void;
 }
 
 export const tauriApi: MacopyAPI = {
-  onHistory: (callback: (data: HistoryItem[]) => void) => {
-    listen<HistoryItem[]>("clipboard-history", (event) => {
+  onItemAdded: (callback: (item: HistoryItem) => void) => {
+    listen<HistoryItem>("item-added", (event) => {
+      callback(event.payload);
+    });
+  },
+
+  onItemUpdated: (callback: (item: HistoryItem) => void) => {
+    listen<HistoryItem>("item-updated", (event) => {
+      callback(event.payload);
+    });
+  },
+
+  onItemRemoved: (callback: (id: number) => void) => {
+    listen<number>("item-removed", (event) => {
+      callback(event.payload);
+    });
+  },
+
+  onHistoryReset: (callback: (items: HistoryItem[]) => void) => {
+    listen<HistoryItem[]>("history-reset", (event) => {
+      callback(event.payload);
+    });
+  },
+
+  onCapturePause: (callback: (pause: CapturePause | null) => void) => {
+    listen<CapturePause | null>("capture-pause", (event) => {
       callback(event.payload);
     });
   },
@@ -32,8 +111,32 @@ export const tauriApi: MacopyAPI = {
     invoke("update_window_height", { height });
   },
 
-  copyImage: (dataUrl: string) => {
-    invoke("copy_image", { dataUrl });
+  copyItem: async (id: number) => {
+    await invoke("copy_item", { id });
+  },
+
+  pasteItem: async (id: number, plainText?: boolean) => {
+    await invoke("paste_item", { id, plainText });
+  },
+
+  getHistory: async (offset?: number, limit?: number): Promise<HistoryPage> => {
+    return invoke<HistoryPage>("get_history", { offset, limit });
+  },
+
+  getItem: async (id: number): Promise<HistoryItem> => {
+    return invoke<HistoryItem>("get_item", { id });
+  },
+
+  deleteItem: async (id: number) => {
+    await invoke("delete_item", { id });
+  },
+
+  clearHistory: async (includePinned?: boolean) => {
+    await invoke("clear_history", { includePinned });
+  },
+
+  moveItem: async (id: number, index: number) => {
+    await invoke("move_item", { id, index });
   },
 
   toggleTrayIcon: () => {
@@ -44,11 +147,122 @@ export const tauriApi: MacopyAPI = {
     return invoke<boolean>("get_tray_icon_state");
   },
 
+  getShortcut: async (): Promise<string> => {
+    return invoke<string>("get_shortcut");
+  },
+
+  setShortcut: async (shortcut: string): Promise<string> => {
+    return invoke<string>("set_shortcut", { shortcut });
+  },
+
+  getKeymap: async (): Promise<Binding[]> => {
+    return invoke<Binding[]>("get_keymap");
+  },
+
+  setKeymap: async (bindings: Binding[]): Promise<Binding[]> => {
+    return invoke<Binding[]>("set_keymap", { bindings });
+  },
+
+  getPlainTextOptions: async (): Promise<PlainTextOptions> => {
+    return invoke<PlainTextOptions>("get_plain_text_options");
+  },
+
+  setPlainTextOptions: async (options: PlainTextOptions) => {
+    await invoke("set_plain_text_options", { options });
+  },
+
+  getRestoreClipboard: async (): Promise<boolean> => {
+    return invoke<boolean>("get_restore_clipboard");
+  },
+
+  setRestoreClipboard: async (enabled: boolean) => {
+    await invoke("set_restore_clipboard", { enabled });
+  },
+
+  getHistoryLimits: async (): Promise<HistoryLimits> => {
+    return invoke<HistoryLimits>("get_history_limits");
+  },
+
+  setHistoryLimits: async (limits: HistoryLimits) => {
+    await invoke("set_history_limits", { limits });
+  },
+
+  getIgnoreWhitespaceDuplicates: async (): Promise<boolean> => {
+    return invoke<boolean>("get_ignore_whitespace_duplicates");
+  },
+
+  setIgnoreWhitespaceDuplicates: async (enabled: boolean) => {
+    await invoke("set_ignore_whitespace_duplicates", { enabled });
+  },
+
+  searchHistory: async (query: SearchQuery): Promise<SearchPage> => {
+    return invoke<SearchPage>("search_history", { query });
+  },
+
+  getExcludedApps: async (): Promise<string[]> => {
+    return invoke<string[]>("get_excluded_apps");
+  },
+
+  addExcludedApp: async (name: string): Promise<string[]> => {
+    return invoke<string[]>("add_excluded_app", { name });
+  },
+
+  removeExcludedApp: async (name: string): Promise<string[]> => {
+    return invoke<string[]>("remove_excluded_app", { name });
+  },
+
+  getSensitiveSettings: async (): Promise<SensitiveSettings> => {
+    return invoke<SensitiveSettings>("get_sensitive_settings");
+  },
+
+  setSensitiveSettings: async (settings: SensitiveSettings) => {
+    await invoke("set_sensitive_settings", { settings });
+  },
+
+  getCapturePause: async (): Promise<CapturePause | null> => {
+    return invoke<CapturePause | null>("get_capture_pause");
+  },
+
+  pauseCapture: async (pause: CapturePause) => {
+    await invoke("pause_capture", { pause });
+  },
+
+  resumeCapture: async () => {
+    await invoke("resume_capture");
+  },
+
+  getEncryptionStatus: async (): Promise<EncryptionStatus> => {
+    return invoke<EncryptionStatus>("get_encryption_status");
+  },
+
+  unlockHistory: async (passphrase: string) => {
+    await invoke("unlock_history", { passphrase });
+  },
+
+  enableEncryption: async (keySource: KeySource, passphrase?: string) => {
+    await invoke("enable_encryption", { keySource, passphrase });
+  },
+
+  disableEncryption: async () => {
+    await invoke("disable_encryption");
+  },
+
+  pinItem: async (id: number) => {
+    await invoke("pin_item", { id });
+  },
+
+  unpinItem: async (id: number) => {
+    await invoke("unpin_item", { id });
+  },
+
   quitApp: () => {
     invoke("quit_app");
   },
 };
 
+export const thumbnailUrl = (imageHash: string): string =>
+  convertFileSrc(`thumbnail/${imageHash}`, "macopy");
+
 export const copyText = async (text: string): Promise<void> => {
   await invoke("copy_text", { text });
 };
@@ -1,4 +1,143 @@
+interface CopiedFile {
+  path: string;
+  name: string;
+  /** False once the file was moved or deleted. */
+  exists: boolean;
+}
+
 export interface HistoryItem {
-  type: "text" | "image";
+  id: number;
+  /** "rich" items keep HTML, RTF or an image alongside the plain text. */
+  type: "text" | "image" | "rich" | "files";
+  /**
+   * The text itself, the content hash of an image, or the name of the first
+   * file. Masked when sensitive.
+   */
   content: string;
+  /**
+   * Content hash of the image of an "image" or "rich" item, which its
+   * thumbnail URL is built from.
+   */
+  imageHash?: string | null;
+  /** Representations of a rich item besides plain text. */
+  formats?: ("html" | "rtf" | "image")[];
+  /** The copied files of a "files" item. */
+  files?: CopiedFile[];
+  /** Milliseconds since the Unix epoch. */
+  createdAt: number;
+  /** Copying an item again moves it to the top and updates this. */
+  lastCopiedAt: number;
+  sourceApp: string | null;
+  /** In bytes; decoded PNG size for images. */
+  size: number;
+  lastUsedAt: number | null;
+  pasteCount: number;
+  /** Pinned items are never evicted by the history limits. */
+  pinned: boolean;
+  /** Flagged as a secret; the text stays in the backend. */
+  sensitive: boolean;
+  /** When the item is deleted automatically, if ever. */
+  expiresAt: number | null;
+}
+
+export interface HistoryLimits {
+  maxItems: number;
+  maxImages: number | null;
+  maxTotalBytes: number | null;
+  maxItemBytes: number | null;
+}
+
+export interface SensitiveSettings {
+  enabled: boolean;
+  /** What happens to detected secrets. "expire" masks and deletes after `ttlSecs`. */
+  action: "skip" | "mask" | "expire";
+  ttlSecs: number;
+  /** Card numbers, PEM blocks, AWS keys, JWTs and random-looking tokens. */
+  builtinDetectors: boolean;
+  /** User-defined regular expressions. */
+  patterns: string[];
+}
+
+/** Why clipboard capture is paused; `null` while capturing. */
+export type CapturePause =
+  | { mode: "indefinite" }
+  /** Milliseconds since the Unix epoch. */
+  | { mode: "until"; resumeAt: number }
+  /** Resumes after ignoring the next copy. */
+  | { mode: "nextCopy" };
+
+export type KeySource = "passphrase" | "keychain" | "keyFile";
+
+export interface EncryptionStatus {
+  enabled: boolean;
+  keySource: KeySource | null;
+  /** Encrypted with a passphrase that has not been entered yet. */
+  locked: boolean;
+}
+
+export interface SearchQuery {
+  /** Matches every item when empty. */
+  text?: string;
+  /** Defaults to "fuzzy". "exact" is a case-insensitive phrase match. */
+  mode?: "fuzzy" | "regex" | "exact";
+  itemType?: "text" | "image" | "files" | null;
+  sourceApp?: string | null;
+  /** Inclusive capture time bounds, in milliseconds since the Unix epoch. */
+  from?: number | null;
+  to?: number | null;
+  offset?: number;
+  /** Defaults to 50. */
+  limit?: number;
+}
+
+export interface SearchResult extends HistoryItem {
+  score: number;
+  /** Matched `[start, end)` spans of `content`, in UTF-16 code units. */
+  ranges: [number, number][];
+}
+
+export interface SearchPage {
+  items: SearchResult[];
+  /** Number of matches across all pages. */
+  total: number;
+}
+
+export interface HistoryPage {
+  items: HistoryItem[];
+  total: number;
+}
+
+/** Rejection value of the history commands. */
+export interface CommandError {
+  kind:
+    | "notFound"
+    | "invalidIndex"
+    | "noText"
+    | "invalidQuery"
+    | "locked"
+    | "clipboard"
+    | "paste"
+    | "storage";
+  message: string;
+}
+
+/** Clean-up when pasting as plain text; all off by default. */
+export interface PlainTextOptions {
+  /** Also drops trailing whitespace on every line. */
+  trim: boolean;
+  collapseBlankLines: boolean;
+  straightenQuotes: boolean;
 }
+
+/** An action bindable to a global shortcut, besides opening the popup. */
+export type ShortcutAction =
+  | { action: "pastePrevious" }
+  /** Puts older items on the clipboard one by one with repeated presses. */
+  | { action: "cycleHistory" }
+  | { action: "pastePlainText" }
+  /** 1-based number of the item in the popup, up to 9. */
+  | { action: "pasteItem"; index: number }
+  | { action: "pauseCapture" }
+  | { action: "clearHistory" };
+
+export type Binding = ShortcutAction & { shortcut: string };
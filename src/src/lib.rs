@@ -1,70 +1,226 @@
-use serde::{Deserialize, Serialize};
+#[cfg(target_os = "linux")]
+use macopy_core::active_app::X11ActiveApp;
+use macopy_core::blob::{self, BlobStore};
+use macopy_core::clipboard::ClipboardRestore;
+use macopy_core::encryption::{self, Cipher, EncryptionConfig, KeySource, Protection};
+use macopy_core::paste::{self, Paster};
+use macopy_core::protocol::{self, Resource};
+use macopy_core::search::{self, SearchQuery};
+use macopy_core::store::{self, HistoryStore, KeyValueStore};
+use macopy_core::{
+    Accelerator, Action, AppInfo, Binding, CaptureContext, CapturePause, ClipboardBackend,
+    ClipboardContent, ClipboardWatcher, CommandError, Entry, EntryId, ExcludedApps, History,
+    HistoryCycle, HistoryDiff, HistoryEvent, HistoryLimits, Keymap, PasteProfiles, PasteStrategy,
+    Pause, PlainTextOptions, SensitiveDetector, SensitiveSettings, Settings,
+};
+use std::path::PathBuf;
 use std::process::Command;
 use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(target_os = "linux")]
+use std::sync::OnceLock;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 use tauri::{
+    http::{header, Response, StatusCode},
     image::Image,
     menu::{Menu, MenuBuilder, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent},
     AppHandle, Emitter, Manager, State, WebviewWindow,
 };
+#[cfg(not(target_os = "macos"))]
 use tauri_plugin_clipboard_manager::ClipboardExt;
-use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Modifiers, Shortcut, ShortcutState};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
 use tauri_plugin_store::StoreExt;
 
 #[cfg(target_os = "macos")]
 use cocoa::base::{id, nil};
 #[cfg(target_os = "macos")]
-use cocoa::foundation::{NSAutoreleasePool, NSData};
+use cocoa::foundation::NSAutoreleasePool;
 #[cfg(target_os = "macos")]
 use objc::{msg_send, sel, sel_impl};
 
-const MAX_HISTORY_ITEMS: usize = 10;
+pub use macopy_core::HistoryItem;
+
 const POPUP_WIDTH: f64 = 250.0;
+const TRAY_HISTORY_ITEMS: usize = 10;
 const CLIPBOARD_POLL_INTERVAL_MS: u64 = 500;
+const PAUSE_FOR_MINUTES: u64 = 5;
+
+/// `store.json` managed by `tauri_plugin_store`, exposed to the core crate.
+struct TauriStore(Arc<tauri_plugin_store::Store<tauri::Wry>>);
+
+impl KeyValueStore for TauriStore {
+    fn get(&self, key: &str) -> Option<serde_json::Value> {
+        self.0.get(key)
+    }
+
+    fn set(&self, key: &str, value: serde_json::Value) {
+        self.0.set(key, value);
+    }
+
+    fn delete(&self, key: &str) {
+        self.0.delete(key);
+    }
+
+    fn save(&self) -> macopy_core::Result<()> {
+        self.0
+            .save()
+            .map_err(|e| macopy_core::Error::Store(e.to_string()))
+    }
+}
+
+fn open_store(app: &AppHandle) -> Option<TauriStore> {
+    app.store(store::STORE_FILE).ok().map(TauriStore)
+}
+
+fn app_data_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+fn open_history_store(app: &AppHandle) -> Arc<dyn HistoryStore> {
+    let opened = app_data_dir(app).and_then(|dir| {
+        store::SqliteHistoryStore::open(dir.join(store::HISTORY_DATABASE_FILE))
+            .map_err(|e| e.to_string())
+    });
+
+    match opened {
+        Ok(history_store) => Arc::new(history_store),
+        Err(e) => {
+            log::error!(
+                "Failed to open history database, history will not persist: {}",
+                e
+            );
+            Arc::new(store::MemoryHistoryStore::new())
+        }
+    }
+}
+
+fn open_blob_store(app: &AppHandle) -> BlobStore {
+    let opened = app_data_dir(app)
+        .and_then(|dir| BlobStore::open(dir.join(blob::BLOB_DIR)).map_err(|e| e.to_string()));
+
+    match opened {
+        Ok(blobs) => blobs,
+        Err(e) => {
+            // Keep images for this session at least
+            log::error!("Failed to open blob store, images will not persist: {}", e);
+            let dir = std::env::temp_dir().join("macopy").join(blob::BLOB_DIR);
+            BlobStore::open(dir).unwrap_or_else(|e| {
+                log::error!(
+                    "Failed to open temporary blob store, images will not be recorded: {}",
+                    e
+                );
+                BlobStore::disabled()
+            })
+        }
+    }
+}
+
+/// Text-only clipboard through `tauri_plugin_clipboard_manager`, used when no
+/// native backend is available. It cannot detect changes.
+#[cfg(not(target_os = "macos"))]
+struct PluginClipboard(AppHandle);
+
+#[cfg(not(target_os = "macos"))]
+impl ClipboardBackend for PluginClipboard {
+    fn change_count(&self) -> i64 {
+        0
+    }
+
+    fn read(&self) -> macopy_core::Result<ClipboardContent> {
+        Ok(ClipboardContent {
+            text: self.0.clipboard().read_text().ok(),
+            ..ClipboardContent::default()
+        })
+    }
+
+    fn write(&self, content: &ClipboardContent) -> macopy_core::Result<i64> {
+        if let Some(text) = &content.text {
+            self.0
+                .clipboard()
+                .write_text(text.clone())
+                .map_err(|e| macopy_core::Error::Clipboard(e.to_string()))?;
+        }
+        Ok(0)
+    }
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
-#[serde(tag = "type", content = "content")]
-pub enum HistoryItem {
-    #[serde(rename = "text")]
-    Text(String),
-    #[serde(rename = "image")]
-    Image(String),
+    fn is_own_write(&self, _change_count: i64) -> bool {
+        false
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn create_clipboard_backend(_app: &AppHandle) -> Arc<dyn ClipboardBackend> {
+    Arc::new(macopy_core::clipboard::MacClipboard::new())
 }
 
-impl HistoryItem {
-    fn to_frontend_format(&self) -> serde_json::Value {
-        match self {
-            HistoryItem::Text(content) => serde_json::json!({
-                "type": "text",
-                "content": content
-            }),
-            HistoryItem::Image(content) => serde_json::json!({
-                "type": "image",
-                "content": content
-            }),
+#[cfg(not(target_os = "macos"))]
+fn create_clipboard_backend(app: &AppHandle) -> Arc<dyn ClipboardBackend> {
+    #[cfg(target_os = "linux")]
+    {
+        match macopy_core::clipboard::X11Clipboard::new() {
+            Ok(clipboard) => return Arc::new(clipboard),
+            Err(e) => log::warn!("X11 clipboard unavailable, using text only: {}", e),
         }
     }
+
+    Arc::new(PluginClipboard(app.clone()))
 }
 
 pub struct AppState {
-    history: Mutex<Vec<HistoryItem>>,
+    clipboard: Arc<dyn ClipboardBackend>,
+    restore: ClipboardRestore,
+    history_store: Arc<dyn HistoryStore>,
+    blobs: BlobStore,
+    history: Mutex<History>,
+    /// `None` if no way to paste is available on this system.
+    paster: Mutex<Option<Paster>>,
+    excluded_apps: Mutex<ExcludedApps>,
+    sensitive: Mutex<SensitiveDetector>,
+    pause: Mutex<CapturePause>,
+    persist_pause: Mutex<bool>,
+    encryption: Mutex<Option<EncryptionConfig>>,
+    protection: Mutex<Protection>,
     last_active_app: Mutex<String>,
     show_tray_icon: Mutex<bool>,
     current_shortcut: Mutex<String>,
+    keymap: Mutex<Keymap>,
+    cycle: Mutex<HistoryCycle>,
+    plain_text: Mutex<PlainTextOptions>,
+    restore_clipboard: Mutex<bool>,
     open_at_login: Mutex<bool>,
     tray_icon: Mutex<Option<TrayIcon>>,
 }
 
-impl Default for AppState {
-    fn default() -> Self {
+impl AppState {
+    fn new(
+        clipboard: Arc<dyn ClipboardBackend>,
+        history_store: Arc<dyn HistoryStore>,
+        blobs: BlobStore,
+    ) -> Self {
         Self {
-            history: Mutex::new(Vec::new()),
+            restore: ClipboardRestore::new(clipboard.clone()),
+            clipboard,
+            history_store,
+            blobs,
+            history: Mutex::new(History::default()),
+            paster: Mutex::new(create_paster(Default::default(), Default::default())),
+            excluded_apps: Mutex::new(ExcludedApps::default()),
+            sensitive: Mutex::new(SensitiveDetector::default()),
+            pause: Mutex::new(CapturePause::default()),
+            persist_pause: Mutex::new(false),
+            encryption: Mutex::new(None),
+            protection: Mutex::new(Protection::default()),
             last_active_app: Mutex::new(String::new()),
             show_tray_icon: Mutex::new(true),
-            current_shortcut: Mutex::new("Alt+V".to_string()),
+            current_shortcut: Mutex::new(macopy_core::settings::DEFAULT_SHORTCUT.to_string()),
+            keymap: Mutex::new(Keymap::default()),
+            cycle: Mutex::new(HistoryCycle::default()),
+            plain_text: Mutex::new(PlainTextOptions::default()),
+            restore_clipboard: Mutex::new(false),
             open_at_login: Mutex::new(false),
             tray_icon: Mutex::new(None),
         }
@@ -72,197 +228,229 @@ impl Default for AppState {
 }
 
 fn get_frontmost_app() -> String {
-    let script = r#"tell application "System Events" to get name of first application process whose frontmost is true"#;
-    let output = Command::new("osascript")
-        .arg("-e")
-        .arg(script)
-        .output();
-
-    match output {
-        Ok(out) => String::from_utf8_lossy(&out.stdout).trim().to_string(),
-        Err(_) => String::new(),
-    }
+    get_frontmost_app_info().name
 }
 
-fn execute_paste(app_name: &str) {
-    // If no app name, just send paste command to frontmost app
-    let script = if app_name.is_empty() {
-        r#"
-delay 0.1
-tell application "System Events"
-  keystroke "v" using {command down}
-end tell
-"#.to_string()
-    } else {
-        let escaped_name = app_name.replace('\\', "\\\\").replace('"', "\\\"");
-        format!(
-            r#"
-tell application "{}"
-  activate
-end tell
-delay 0.1
-tell application "System Events"
-  keystroke "v" using {{command down}}
-end tell
-"#,
-            escaped_name
-        )
+#[cfg(target_os = "macos")]
+fn get_frontmost_app_info() -> AppInfo {
+    let script = r#"tell application "System Events" to get {name, bundle identifier} of first application process whose frontmost is true"#;
+    let output = Command::new("osascript").arg("-e").arg(script).output();
+
+    let Ok(out) = output else {
+        return AppInfo::default();
     };
+    let output = String::from_utf8_lossy(&out.stdout).trim().to_string();
+
+    // "Name, com.example.App", or "Name, missing value" without a bundle ID
+    match output.rsplit_once(", ") {
+        Some((name, bundle_id)) => AppInfo::new(
+            name,
+            Some(bundle_id.to_string()).filter(|id| id != "missing value"),
+        ),
+        None => AppInfo::new(output, None),
+    }
+}
 
-    // Use thread to avoid blocking, but still capture errors
-    std::thread::spawn(move || {
-        let result = Command::new("osascript")
-            .arg("-e")
-            .arg(&script)
-            .output();
-
-        if let Err(e) = result {
-            log::error!("Failed to execute paste script: {}", e);
-        } else if let Ok(output) = result {
-            if !output.status.success() {
-                log::error!(
-                    "Paste script failed: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                );
-            }
+/// The application of the active X11 window. The display connection is
+/// opened on first use and kept.
+#[cfg(target_os = "linux")]
+fn get_frontmost_app_info() -> AppInfo {
+    static ACTIVE_APP: OnceLock<Option<X11ActiveApp>> = OnceLock::new();
+    let active_app = ACTIVE_APP.get_or_init(|| match X11ActiveApp::new() {
+        Ok(active_app) => Some(active_app),
+        Err(e) => {
+            log::warn!("Cannot look up the active window: {}", e);
+            None
         }
     });
+
+    match active_app.as_ref().map(X11ActiveApp::current) {
+        Some(Ok(app)) => app,
+        Some(Err(e)) => {
+            log::warn!("Failed to look up the active window: {}", e);
+            AppInfo::default()
+        }
+        None => AppInfo::default(),
+    }
 }
 
-#[cfg(target_os = "macos")]
-fn get_clipboard_change_count() -> i64 {
-    unsafe {
-        let pool = NSAutoreleasePool::new(nil);
-        let pasteboard: id = msg_send![objc::class!(NSPasteboard), generalPasteboard];
-        let count: i64 = msg_send![pasteboard, changeCount];
-        let _: () = msg_send![pool, drain];
-        count
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn get_frontmost_app_info() -> AppInfo {
+    AppInfo::default()
+}
+
+/// The clipboard before a history item replaces it for a paste, if it is to
+/// be restored afterwards.
+fn clipboard_snapshot(state: &AppState) -> Option<ClipboardContent> {
+    if !*state.restore_clipboard.lock().unwrap() {
+        return None;
+    }
+    match state.restore.snapshot() {
+        Ok(content) => Some(content),
+        Err(e) => {
+            log::error!("Failed to read clipboard: {}", e);
+            None
+        }
     }
 }
 
-#[cfg(target_os = "macos")]
-fn read_image_from_clipboard() -> Option<String> {
-    unsafe {
-        let pool = NSAutoreleasePool::new(nil);
-        let pasteboard: id = msg_send![objc::class!(NSPasteboard), generalPasteboard];
-
-        let png_type: id = msg_send![objc::class!(NSString), stringWithUTF8String:b"public.png\0".as_ptr()];
-        let tiff_type: id = msg_send![objc::class!(NSString), stringWithUTF8String:b"public.tiff\0".as_ptr()];
-
-        // Try PNG first
-        let png_data: id = msg_send![pasteboard, dataForType: png_type];
-        if png_data != nil {
-            let length: usize = msg_send![png_data, length];
-            let bytes: *const u8 = msg_send![png_data, bytes];
-            let slice = std::slice::from_raw_parts(bytes, length);
-            let base64 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, slice);
-            let _: () = msg_send![pool, drain];
-            return Some(format!("data:image/png;base64,{}", base64));
-        }
-
-        // Try TIFF
-        let tiff_data: id = msg_send![pasteboard, dataForType: tiff_type];
-        if tiff_data != nil {
-            // Convert TIFF to PNG using NSImage
-            let image: id = msg_send![objc::class!(NSImage), alloc];
-            let image: id = msg_send![image, initWithData: tiff_data];
-            if image != nil {
-                let tiff_rep: id = msg_send![image, TIFFRepresentation];
-                if tiff_rep != nil {
-                    let bitmap_rep: id = msg_send![objc::class!(NSBitmapImageRep), imageRepWithData: tiff_rep];
-                    if bitmap_rep != nil {
-                        let props: id = msg_send![objc::class!(NSDictionary), dictionary];
-                        let png_data: id = msg_send![bitmap_rep, representationUsingType: 4u64 properties: props];
-                        if png_data != nil {
-                            let length: usize = msg_send![png_data, length];
-                            let bytes: *const u8 = msg_send![png_data, bytes];
-                            let slice = std::slice::from_raw_parts(bytes, length);
-                            let base64 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, slice);
-                            let _: () = msg_send![pool, drain];
-                            return Some(format!("data:image/png;base64,{}", base64));
-                        }
-                    }
-                }
-            }
+fn create_paster(strategy: PasteStrategy, profiles: PasteProfiles) -> Option<Paster> {
+    match paste::create_injector(strategy) {
+        Ok(injector) => Some(Paster::new(injector, profiles)),
+        Err(e) => {
+            log::error!("Pasting is unavailable: {}", e);
+            None
         }
+    }
+}
 
-        let _: () = msg_send![pool, drain];
-        None
+fn current_paster(state: &AppState) -> macopy_core::Result<Paster> {
+    state
+        .paster
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| macopy_core::Error::Paste("no way to paste is available".to_string()))
+}
+
+/// Pastes into `app_name`, then puts `snapshot` back on the clipboard if
+/// given and nothing was copied since the write with change count `pasted`.
+/// Blocks until done.
+fn paste_and_restore(
+    paster: &Paster,
+    restore: &ClipboardRestore,
+    app_name: &str,
+    snapshot: Option<ClipboardContent>,
+    pasted: i64,
+) -> macopy_core::Result<()> {
+    match snapshot {
+        Some(snapshot) => {
+            restore.paste_and_restore(snapshot, pasted, paster.restore_delay(app_name), || {
+                paster.paste(app_name)
+            })
+        }
+        None => paster.paste(app_name),
     }
 }
 
-#[cfg(not(target_os = "macos"))]
-fn get_clipboard_change_count() -> i64 {
-    0
+/// Pastes into `app_name` on a background thread, logging failures. Used
+/// where no caller is waiting for the result.
+fn execute_paste(
+    state: &AppState,
+    app_name: &str,
+    snapshot: Option<ClipboardContent>,
+    pasted: i64,
+) {
+    let paster = match current_paster(state) {
+        Ok(paster) => paster,
+        Err(e) => {
+            log::error!("Failed to paste: {}", e);
+            return;
+        }
+    };
+    let restore = state.restore.clone();
+    let app_name = app_name.to_string();
+
+    thread::spawn(move || {
+        if let Err(e) = paste_and_restore(&paster, &restore, &app_name, snapshot, pasted) {
+            log::error!("Failed to paste: {}", e);
+        }
+    });
 }
 
-#[cfg(not(target_os = "macos"))]
-fn read_image_from_clipboard() -> Option<String> {
-    None
+/// Runs `run` with the current capture settings. They are copied, so no
+/// settings lock is held while the source app is looked up.
+fn with_capture_context<R>(state: &AppState, run: impl FnOnce(&CaptureContext) -> R) -> R {
+    let excluded_apps = state.excluded_apps.lock().unwrap().clone();
+    let sensitive = state.sensitive.lock().unwrap().clone();
+    let locked = state.protection.lock().unwrap().is_locked();
+
+    run(&CaptureContext {
+        history: &state.history,
+        blobs: &state.blobs,
+        pause: &state.pause,
+        excluded_apps: &excluded_apps,
+        sensitive: &sensitive,
+        locked,
+        source_app: &get_frontmost_app_info,
+    })
 }
 
 fn update_clipboard(app: &AppHandle, state: &AppState) -> bool {
-    let clipboard = app.clipboard();
-    let mut changed = false;
+    let diff = with_capture_context(state, |context| {
+        context.record_current(state.clipboard.as_ref())
+    });
+    if diff.is_empty() {
+        return false;
+    }
+    history_changed(app, state, &diff);
+    true
+}
 
-    // Try to read image first (higher priority)
-    if let Some(image_data) = read_image_from_clipboard() {
-        let mut history = state.history.lock().unwrap();
-        let new_item = HistoryItem::Image(image_data.clone());
+fn persist_history(state: &AppState, diff: &HistoryDiff) {
+    if let Err(e) = state.history_store.apply(diff) {
+        log::error!("Failed to save history: {}", e);
+    }
+    if let Err(e) = state.blobs.apply(diff) {
+        log::error!("Failed to update images: {}", e);
+    }
+}
 
-        if history.first() != Some(&new_item) {
-            history.insert(0, new_item);
-            if history.len() > MAX_HISTORY_ITEMS {
-                history.pop();
-            }
-            changed = true;
-
-            // Save to store
-            if let Ok(store) = app.store("store.json") {
-                let history_json: Vec<serde_json::Value> = history
-                    .iter()
-                    .map(|item| item.to_frontend_format())
-                    .collect();
-                let _ = store.set("history", serde_json::json!(history_json));
-                let _ = store.save();
-            }
-        }
-    } else if let Ok(text) = clipboard.read_text() {
-        // Fall back to text
-        if !text.is_empty() {
-            let mut history = state.history.lock().unwrap();
-            let new_item = HistoryItem::Text(text.clone());
+/// Saves a change to the history and sends it to the popup.
+fn history_changed(app: &AppHandle, state: &AppState, diff: &HistoryDiff) {
+    persist_history(state, diff);
+    for event in HistoryEvent::from_diff(diff) {
+        let _ = app.emit(event.name(), event);
+    }
+}
 
-            if history.first() != Some(&new_item) {
-                history.insert(0, new_item);
-                if history.len() > MAX_HISTORY_ITEMS {
-                    history.pop();
-                }
-                changed = true;
-
-                // Save to store
-                if let Ok(store) = app.store("store.json") {
-                    let history_json: Vec<serde_json::Value> = history
-                        .iter()
-                        .map(|item| item.to_frontend_format())
-                        .collect();
-                    let _ = store.set("history", serde_json::json!(history_json));
-                    let _ = store.save();
-                }
-            }
-        }
+/// Sends the whole history, for when it was replaced rather than changed.
+fn send_history_to_frontend(app: &AppHandle, state: &AppState) {
+    let event = HistoryEvent::reset(state.history.lock().unwrap().entries());
+    let _ = app.emit(event.name(), event);
+}
+
+/// Serves image bytes for `macopy://` requests from the popup.
+fn serve_resource(app: &AppHandle, path: &str) -> Response<Vec<u8>> {
+    let Some(resource) = Resource::parse(path) else {
+        return status_response(StatusCode::BAD_REQUEST);
+    };
+    let Some(state) = app.try_state::<AppState>() else {
+        return status_response(StatusCode::SERVICE_UNAVAILABLE);
+    };
+
+    // Only images still in the history are served
+    let referenced = state.history.lock().unwrap().entries().iter().any(|entry| {
+        entry
+            .item
+            .image()
+            .is_some_and(|image| image.hash == resource.hash())
+    });
+    if !referenced {
+        return status_response(StatusCode::NOT_FOUND);
     }
 
-    changed
+    let bytes = match &resource {
+        Resource::Image(hash) => state.blobs.get(hash),
+        Resource::Thumbnail(hash) => state.blobs.thumbnail(hash),
+    };
+
+    match bytes {
+        Ok(bytes) => Response::builder()
+            .header(header::CONTENT_TYPE, "image/png")
+            // The path holds the content hash, so it always refers to the same image
+            .header(header::CACHE_CONTROL, "max-age=31536000, immutable")
+            .body(bytes)
+            .unwrap(),
+        Err(e) => {
+            log::error!("Failed to load image for {}: {}", resource.path(), e);
+            status_response(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
 }
 
-fn send_history_to_frontend(app: &AppHandle, state: &AppState) {
-    let history = state.history.lock().unwrap();
-    let history_json: Vec<serde_json::Value> = history
-        .iter()
-        .map(|item| item.to_frontend_format())
-        .collect();
-    let _ = app.emit("clipboard-history", history_json);
+fn status_response(status: StatusCode) -> Response<Vec<u8>> {
+    Response::builder().status(status).body(Vec::new()).unwrap()
 }
 
 #[cfg(target_os = "macos")]
@@ -328,7 +516,8 @@ fn get_screen_bounds_at_cursor(cursor_x: f64, cursor_y: f64) -> (f64, f64, f64,
                 // Use visible frame for bounds (excludes menu bar and dock)
                 // Convert to top-left origin coordinates using main screen height as reference
                 let screen_left = visible_frame.origin.x;
-                let screen_top = main_screen_height - (visible_frame.origin.y + visible_frame.size.height);
+                let screen_top =
+                    main_screen_height - (visible_frame.origin.y + visible_frame.size.height);
                 let screen_right = visible_frame.origin.x + visible_frame.size.width;
                 let screen_bottom = main_screen_height - visible_frame.origin.y;
 
@@ -388,7 +577,8 @@ fn show_popup_at_cursor(window: &WebviewWindow) {
 
         #[cfg(not(target_os = "macos"))]
         let (cursor_x, cursor_y) = {
-            window.cursor_position()
+            window
+                .cursor_position()
                 .map(|p| (p.x, p.y))
                 .unwrap_or((0.0, 0.0))
         };
@@ -464,22 +654,44 @@ fn build_tray_menu(app: &AppHandle, state: &AppState) -> tauri::Result<Menu<taur
     let history = state.history.lock().unwrap();
     let current_shortcut = state.current_shortcut.lock().unwrap().clone();
     let open_at_login = *state.open_at_login.lock().unwrap();
+    let excluded_apps = state.excluded_apps.lock().unwrap().clone();
+    let capture_pause = state.pause.lock().unwrap().current();
+    let persist_pause = *state.persist_pause.lock().unwrap();
+    let pause_shortcut = state
+        .keymap
+        .lock()
+        .unwrap()
+        .shortcut_for(Action::PauseCapture)
+        .map(String::from);
+    let item_shortcuts = state.keymap.lock().unwrap().has_item_bindings();
+    let plain_text = *state.plain_text.lock().unwrap();
+    let restore_clipboard = *state.restore_clipboard.lock().unwrap();
 
     let mut builder = MenuBuilder::new(app);
 
+    // Add pinned items
+    if history.pinned().next().is_some() {
+        let pinned_label = if is_ja { "ピン留め" } else { "Pinned" };
+        let header = MenuItem::with_id(app, "pinned_header", pinned_label, false, None::<&str>)?;
+        builder = builder.item(&header);
+
+        for entry in history.pinned() {
+            let menu_item = MenuItem::with_id(
+                app,
+                format!("history_{}", entry.id),
+                entry.tray_label(),
+                true,
+                None::<&str>,
+            )?;
+            builder = builder.item(&menu_item);
+        }
+        builder = builder.separator();
+    }
+
     // Add history items
-    for (i, item) in history.iter().take(MAX_HISTORY_ITEMS).enumerate() {
-        let label = match item {
-            HistoryItem::Text(content) => {
-                let display: String = content.chars().take(30).collect();
-                if content.len() > 30 {
-                    format!("{}...", display)
-                } else {
-                    display
-                }
-            }
-            HistoryItem::Image(_) => "[Image]".to_string(),
-        };
+    let mut has_recent = false;
+    for (i, entry) in history.recent().take(TRAY_HISTORY_ITEMS).enumerate() {
+        let label = entry.tray_label();
         let accelerator = if i < 9 {
             format!("{}", i + 1)
         } else {
@@ -487,64 +699,330 @@ fn build_tray_menu(app: &AppHandle, state: &AppState) -> tauri::Result<Menu<taur
         };
         let menu_item = MenuItem::with_id(
             app,
-            format!("history_{}", i),
+            format!("history_{}", entry.id),
             &label,
             true,
             Some(&accelerator),
         )?;
         builder = builder.item(&menu_item);
+        has_recent = true;
     }
 
     // Separator
-    if !history.is_empty() {
+    if has_recent {
         builder = builder.separator();
     }
 
+    // Paste as plain text submenu
+    let plain_label = if is_ja {
+        "プレーンテキストでペースト"
+    } else {
+        "Paste as Plain Text"
+    };
+    let mut plain_submenu = tauri::menu::SubmenuBuilder::new(app, plain_label);
+    let mut has_plain_text = false;
+    for entry in history
+        .entries()
+        .iter()
+        .filter(|entry| entry.item.plain_text().is_some())
+        .take(TRAY_HISTORY_ITEMS)
+    {
+        let menu_item = MenuItem::with_id(
+            app,
+            format!("plain_history_{}", entry.id),
+            entry.tray_label(),
+            true,
+            None::<&str>,
+        )?;
+        plain_submenu = plain_submenu.item(&menu_item);
+        has_plain_text = true;
+    }
+    if has_plain_text {
+        plain_submenu = plain_submenu.separator();
+    }
+    let options = [
+        (
+            "plain_option_trim",
+            plain_text.trim,
+            "前後の空白を削除",
+            "Trim Whitespace",
+        ),
+        (
+            "plain_option_collapse",
+            plain_text.collapse_blank_lines,
+            "連続する空行をまとめる",
+            "Collapse Blank Lines",
+        ),
+        (
+            "plain_option_quotes",
+            plain_text.straighten_quotes,
+            "スマート引用符を変換",
+            "Straighten Smart Quotes",
+        ),
+    ];
+    for (id, enabled, ja_label, en_label) in options {
+        let label = if is_ja { ja_label } else { en_label };
+        let label = if enabled {
+            format!("✓ {}", label)
+        } else {
+            label.to_string()
+        };
+        let item = MenuItem::with_id(app, id, label, true, None::<&str>)?;
+        plain_submenu = plain_submenu.item(&item);
+    }
+
+    builder = builder.item(&plain_submenu.build()?);
+
+    // Pause submenu
+    let pause_label = if is_ja {
+        "記録の一時停止"
+    } else {
+        "Pause Capture"
+    };
+    let mut pause_submenu = tauri::menu::SubmenuBuilder::new(app, pause_label);
+    if let Some(pause) = capture_pause {
+        let status_label = match (pause, is_ja) {
+            (Pause::NextCopy, true) => "次のコピーまで一時停止中",
+            (Pause::NextCopy, false) => "Paused Until Next Copy",
+            (Pause::Until { .. }, true) => "一時停止中（時間指定）",
+            (Pause::Until { .. }, false) => "Paused for a While",
+            (Pause::Indefinite, true) => "一時停止中",
+            (Pause::Indefinite, false) => "Paused",
+        };
+        let status = MenuItem::with_id(app, "pause_status", status_label, false, None::<&str>)?;
+        let resume_label = if is_ja { "再開" } else { "Resume" };
+        let resume = MenuItem::with_id(
+            app,
+            "pause_resume",
+            resume_label,
+            true,
+            pause_shortcut.as_deref(),
+        )?;
+        pause_submenu = pause_submenu.item(&status).item(&resume);
+    } else {
+        let indefinite_label = if is_ja { "一時停止" } else { "Pause" };
+        let indefinite = MenuItem::with_id(
+            app,
+            "pause_indefinite",
+            indefinite_label,
+            true,
+            pause_shortcut.as_deref(),
+        )?;
+        let timed_label = if is_ja {
+            format!("{}分間一時停止", PAUSE_FOR_MINUTES)
+        } else {
+            format!("Pause for {} Minutes", PAUSE_FOR_MINUTES)
+        };
+        let timed = MenuItem::with_id(app, "pause_timed", timed_label, true, None::<&str>)?;
+        let next_copy_label = if is_ja {
+            "次のコピーまで一時停止"
+        } else {
+            "Pause Until Next Copy"
+        };
+        let next_copy =
+            MenuItem::with_id(app, "pause_next_copy", next_copy_label, true, None::<&str>)?;
+        pause_submenu = pause_submenu
+            .item(&indefinite)
+            .item(&timed)
+            .item(&next_copy);
+    }
+    let persist_label = if is_ja {
+        if persist_pause {
+            "✓ 再起動後も一時停止を維持"
+        } else {
+            "再起動後も一時停止を維持"
+        }
+    } else {
+        if persist_pause {
+            "✓ Stay Paused After Restart"
+        } else {
+            "Stay Paused After Restart"
+        }
+    };
+    let persist_item = MenuItem::with_id(app, "pause_persist", persist_label, true, None::<&str>)?;
+    pause_submenu = pause_submenu.separator().item(&persist_item);
+
+    builder = builder.item(&pause_submenu.build()?);
+
     // Shortcut settings submenu
-    let shortcut_label = if is_ja { "ショートカット設定" } else { "Shortcut Settings" };
+    let shortcut_label = if is_ja {
+        "ショートカット設定"
+    } else {
+        "Shortcut Settings"
+    };
 
     let opt_v = MenuItem::with_id(
         app,
         "shortcut_alt_v",
-        if current_shortcut == "Alt+V" { "✓ ⌥ Option + V" } else { "  ⌥ Option + V" },
+        if is_current_shortcut(&current_shortcut, "Alt+V") {
+            "✓ ⌥ Option + V"
+        } else {
+            "  ⌥ Option + V"
+        },
         true,
         None::<&str>,
     )?;
     let cmd_shift_v = MenuItem::with_id(
         app,
         "shortcut_cmd_shift_v",
-        if current_shortcut == "CommandOrControl+Shift+V" { "✓ ⌘ Shift + V" } else { "  ⌘ Shift + V" },
+        if is_current_shortcut(&current_shortcut, "CommandOrControl+Shift+V") {
+            "✓ ⌘ Shift + V"
+        } else {
+            "  ⌘ Shift + V"
+        },
         true,
         None::<&str>,
     )?;
     let ctrl_alt_v = MenuItem::with_id(
         app,
         "shortcut_ctrl_alt_v",
-        if current_shortcut == "Control+Alt+V" { "✓ ⌃ Ctrl + Option + V" } else { "  ⌃ Ctrl + Option + V" },
+        if is_current_shortcut(&current_shortcut, "Control+Alt+V") {
+            "✓ ⌃ Ctrl + Option + V"
+        } else {
+            "  ⌃ Ctrl + Option + V"
+        },
         true,
         None::<&str>,
     )?;
 
-    let shortcut_submenu = tauri::menu::SubmenuBuilder::new(app, shortcut_label)
+    let mut shortcut_submenu = tauri::menu::SubmenuBuilder::new(app, shortcut_label)
         .item(&opt_v)
         .item(&cmd_shift_v)
-        .item(&ctrl_alt_v)
-        .build()?;
+        .item(&ctrl_alt_v);
+
+    // A shortcut set from the settings UI
+    let is_preset = ["Alt+V", "CommandOrControl+Shift+V", "Control+Alt+V"]
+        .iter()
+        .any(|preset| is_current_shortcut(&current_shortcut, preset));
+    if !is_preset {
+        let label = Accelerator::parse(&current_shortcut)
+            .map(|accelerator| accelerator.label())
+            .unwrap_or(current_shortcut.clone());
+        let custom = MenuItem::with_id(
+            app,
+            "shortcut_custom",
+            format!("✓ {}", label),
+            false,
+            None::<&str>,
+        )?;
+        shortcut_submenu = shortcut_submenu.item(&custom);
+    }
+
+    builder = builder.item(&shortcut_submenu.build()?);
+
+    // Excluded apps submenu
+    let excluded_label = if is_ja {
+        "除外するアプリ"
+    } else {
+        "Excluded Apps"
+    };
+    let mut excluded_submenu = tauri::menu::SubmenuBuilder::new(app, excluded_label);
+    for (i, excluded_app) in excluded_apps.apps().iter().enumerate() {
+        let item = MenuItem::with_id(
+            app,
+            format!("excluded_app_{}", i),
+            format!("✓ {}", excluded_app),
+            true,
+            None::<&str>,
+        )?;
+        excluded_submenu = excluded_submenu.item(&item);
+    }
+
+    // Offer to exclude the app the latest item came from
+    let latest_app = history
+        .entries()
+        .first()
+        .and_then(|entry| entry.source_app.clone())
+        .filter(|name| !excluded_apps.contains(name));
+    if let Some(latest_app) = latest_app {
+        if !excluded_apps.apps().is_empty() {
+            excluded_submenu = excluded_submenu.separator();
+        }
+        let exclude_label = if is_ja {
+            format!("「{}」を除外", latest_app)
+        } else {
+            format!("Exclude \"{}\"", latest_app)
+        };
+        let item = MenuItem::with_id(app, "exclude_latest_app", exclude_label, true, None::<&str>)?;
+        excluded_submenu = excluded_submenu.item(&item);
+    } else if excluded_apps.apps().is_empty() {
+        let none_label = if is_ja { "なし" } else { "None" };
+        let item = MenuItem::with_id(app, "excluded_apps_none", none_label, false, None::<&str>)?;
+        excluded_submenu = excluded_submenu.item(&item);
+    }
+
+    builder = builder.item(&excluded_submenu.build()?);
+
+    // Restore the clipboard after pasting
+    let restore_label = if is_ja {
+        if restore_clipboard {
+            "✓ ペースト後にクリップボードを復元"
+        } else {
+            "ペースト後にクリップボードを復元"
+        }
+    } else {
+        if restore_clipboard {
+            "✓ Restore Clipboard After Paste"
+        } else {
+            "Restore Clipboard After Paste"
+        }
+    };
+    let restore_item = MenuItem::with_id(
+        app,
+        "toggle_restore_clipboard",
+        restore_label,
+        true,
+        None::<&str>,
+    )?;
+    builder = builder.item(&restore_item);
 
-    builder = builder.item(&shortcut_submenu);
+    // Alt+1…9 for the first nine items, off by default
+    let item_shortcuts_label = if is_ja {
+        if item_shortcuts {
+            "✓ ⌥1〜9 で項目をペースト"
+        } else {
+            "⌥1〜9 で項目をペースト"
+        }
+    } else {
+        if item_shortcuts {
+            "✓ Paste Items with ⌥1–9"
+        } else {
+            "Paste Items with ⌥1–9"
+        }
+    };
+    let item_shortcuts_item = MenuItem::with_id(
+        app,
+        "toggle_item_shortcuts",
+        item_shortcuts_label,
+        true,
+        None::<&str>,
+    )?;
+    builder = builder.item(&item_shortcuts_item);
 
     // Launch at login
     let login_label = if is_ja {
-        if open_at_login { "✓ ログイン時に自動起動" } else { "ログイン時に自動起動" }
+        if open_at_login {
+            "✓ ログイン時に自動起動"
+        } else {
+            "ログイン時に自動起動"
+        }
     } else {
-        if open_at_login { "✓ Launch at Login" } else { "Launch at Login" }
+        if open_at_login {
+            "✓ Launch at Login"
+        } else {
+            "Launch at Login"
+        }
     };
     let login_item = MenuItem::with_id(app, "toggle_login", login_label, true, None::<&str>)?;
     builder = builder.item(&login_item);
 
     // Separator and quit
     builder = builder.separator();
-    let quit_label = if is_ja { "Macopy を終了" } else { "Quit Macopy" };
+    let quit_label = if is_ja {
+        "Macopy を終了"
+    } else {
+        "Quit Macopy"
+    };
     let quit = MenuItem::with_id(app, "quit", quit_label, true, Some("CmdOrCtrl+Q"))?;
     builder = builder.item(&quit);
 
@@ -559,70 +1037,392 @@ fn update_tray_menu(app: &AppHandle, state: &AppState) {
     }
 }
 
-fn unregister_all_shortcuts(app: &AppHandle) {
-    let shortcuts = [
-        Shortcut::new(Some(Modifiers::ALT), Code::KeyV),
-        Shortcut::new(Some(Modifiers::SUPER | Modifiers::SHIFT), Code::KeyV),
-        Shortcut::new(Some(Modifiers::CONTROL | Modifiers::ALT), Code::KeyV),
-    ];
-
-    for shortcut in &shortcuts {
-        let _ = app.global_shortcut().unregister(*shortcut);
+/// The tray icon, dimmed while capture is paused.
+fn tray_icon_image(paused: bool) -> Image<'static> {
+    let png_data = include_bytes!("../icons/trayTemplate.png");
+    let img = image::load_from_memory(png_data).expect("Failed to decode tray icon");
+    let mut rgba = img.to_rgba8();
+    if paused {
+        for pixel in rgba.pixels_mut() {
+            pixel[3] /= 3;
+        }
     }
+    let (width, height) = rgba.dimensions();
+    Image::new_owned(rgba.into_raw(), width, height)
 }
 
-fn register_shortcut(app: &AppHandle, shortcut_str: &str) {
-    let shortcut = match shortcut_str {
-        "Alt+V" => Shortcut::new(Some(Modifiers::ALT), Code::KeyV),
-        "CommandOrControl+Shift+V" => Shortcut::new(Some(Modifiers::SUPER | Modifiers::SHIFT), Code::KeyV),
-        "Control+Alt+V" => Shortcut::new(Some(Modifiers::CONTROL | Modifiers::ALT), Code::KeyV),
-        _ => Shortcut::new(Some(Modifiers::ALT), Code::KeyV),
-    };
-
-    let app_handle = app.clone();
-    let _ = app.global_shortcut().on_shortcut(shortcut, move |_app, _shortcut, event| {
-        if event.state != ShortcutState::Pressed {
-            return;
-        }
+fn tray_tooltip(paused: bool) -> &'static str {
+    match (paused, is_japanese()) {
+        (true, true) => "Macopy（一時停止中）",
+        (true, false) => "Macopy (Paused)",
+        (false, _) => "Macopy",
+    }
+}
 
-        let frontmost = get_frontmost_app();
-        if let Some(state) = _app.try_state::<AppState>() {
-            *state.last_active_app.lock().unwrap() = frontmost;
-            update_clipboard(&app_handle, &state);
-            send_history_to_frontend(&app_handle, &state);
-            update_tray_menu(&app_handle, &state);
+/// Pauses capture, or resumes it when `pause` is `None`.
+fn set_capture_pause(app: &AppHandle, state: &AppState, pause: Option<Pause>) {
+    {
+        let mut capture_pause = state.pause.lock().unwrap();
+        match pause {
+            Some(pause) => capture_pause.pause(pause),
+            None => {
+                capture_pause.resume();
+            }
         }
+    }
+    capture_pause_changed(app, state);
+}
 
-        if let Some(window) = _app.get_webview_window("main") {
-            show_popup_at_cursor(&window);
-        }
-    });
+fn toggle_capture_pause(app: &AppHandle, state: &AppState) {
+    let paused = state.pause.lock().unwrap().is_paused();
+    set_capture_pause(
+        app,
+        state,
+        if paused {
+            None
+        } else {
+            Some(Pause::Indefinite)
+        },
+    );
 }
 
-fn change_shortcut(app: &AppHandle, state: &AppState, new_shortcut: &str) {
-    unregister_all_shortcuts(app);
-    *state.current_shortcut.lock().unwrap() = new_shortcut.to_string();
-    register_shortcut(app, new_shortcut);
+/// Saves the pause if it should survive a restart and updates the tray icon,
+/// the menu and the popup.
+fn capture_pause_changed(app: &AppHandle, state: &AppState) {
+    let pause = state.pause.lock().unwrap().current();
 
     // Save to store
-    if let Ok(store) = app.store("store.json") {
-        let _ = store.set("shortcut", serde_json::json!(new_shortcut));
-        let _ = store.save();
+    if *state.persist_pause.lock().unwrap() {
+        if let Some(store) = open_store(app) {
+            let _ = store::save_value(&store, store::CAPTURE_PAUSE_KEY, serde_json::json!(pause));
+        }
     }
 
+    if let Some(tray) = state.tray_icon.lock().unwrap().as_ref() {
+        let _ = tray.set_icon(Some(tray_icon_image(pause.is_some())));
+        let _ = tray.set_icon_as_template(true);
+        let _ = tray.set_tooltip(Some(tray_tooltip(pause.is_some())));
+    }
     update_tray_menu(app, state);
+    let _ = app.emit("capture-pause", pause);
 }
 
-fn toggle_login_item(app: &AppHandle, state: &AppState) {
-    let mut open_at_login = state.open_at_login.lock().unwrap();
-    *open_at_login = !*open_at_login;
-    let new_value = *open_at_login;
-    drop(open_at_login);
+fn toggle_persist_pause(app: &AppHandle, state: &AppState) {
+    let mut persist_pause = state.persist_pause.lock().unwrap();
+    *persist_pause = !*persist_pause;
+    let new_value = *persist_pause;
+    drop(persist_pause);
 
     // Save to store
-    if let Ok(store) = app.store("store.json") {
-        let _ = store.set("openAtLogin", serde_json::json!(new_value));
-        let _ = store.save();
+    if let Some(store) = open_store(app) {
+        let pause = if new_value {
+            state.pause.lock().unwrap().current()
+        } else {
+            None
+        };
+        let _ = store::save_value(&store, store::CAPTURE_PAUSE_KEY, serde_json::json!(pause));
+        let _ = store::save_value(
+            &store,
+            store::PERSIST_PAUSE_KEY,
+            serde_json::json!(new_value),
+        );
+    }
+
+    update_tray_menu(app, state);
+}
+
+fn change_plain_text_options(app: &AppHandle, state: &AppState, options: PlainTextOptions) {
+    *state.plain_text.lock().unwrap() = options;
+
+    // Save to store
+    if let Some(store) = open_store(app) {
+        let _ = store::save_value(&store, store::PLAIN_TEXT_KEY, serde_json::json!(options));
+    }
+
+    update_tray_menu(app, state);
+}
+
+fn toggle_plain_text_option(app: &AppHandle, state: &AppState, option_id: &str) {
+    let mut options = *state.plain_text.lock().unwrap();
+    match option_id {
+        "plain_option_trim" => options.trim = !options.trim,
+        "plain_option_collapse" => options.collapse_blank_lines = !options.collapse_blank_lines,
+        "plain_option_quotes" => options.straighten_quotes = !options.straighten_quotes,
+        _ => return,
+    }
+    change_plain_text_options(app, state, options);
+}
+
+fn change_restore_clipboard(app: &AppHandle, state: &AppState, enabled: bool) {
+    *state.restore_clipboard.lock().unwrap() = enabled;
+
+    // Save to store
+    if let Some(store) = open_store(app) {
+        let _ = store::save_value(
+            &store,
+            store::RESTORE_CLIPBOARD_KEY,
+            serde_json::json!(enabled),
+        );
+    }
+
+    update_tray_menu(app, state);
+}
+
+fn change_keymap(app: &AppHandle, state: &AppState, keymap: Keymap) -> Result<(), String> {
+    let old_keymap = state.keymap.lock().unwrap().clone();
+    unregister_keymap(app, &old_keymap);
+    if let Err(e) = register_keymap(app, &keymap) {
+        if let Err(e) = register_keymap(app, &old_keymap) {
+            log::error!("Failed to restore shortcuts: {}", e);
+        }
+        return Err(e);
+    }
+    *state.keymap.lock().unwrap() = keymap.clone();
+
+    // Save to store
+    if let Some(store) = open_store(app) {
+        let _ = store::save_value(&store, store::KEYMAP_KEY, serde_json::json!(keymap));
+    }
+
+    update_tray_menu(app, state);
+    Ok(())
+}
+
+/// Binds or unbinds Alt+1…9 for pasting the first nine items.
+fn toggle_item_shortcuts(app: &AppHandle, state: &AppState) -> Result<(), String> {
+    let popup_shortcut =
+        Accelerator::parse(&state.current_shortcut.lock().unwrap()).map_err(|e| e.to_string())?;
+    let keymap = state.keymap.lock().unwrap().clone();
+    let keymap = keymap
+        .with_item_bindings(!keymap.has_item_bindings(), &popup_shortcut)
+        .map_err(|e| e.to_string())?;
+    change_keymap(app, state, keymap)
+}
+
+fn is_current_shortcut(current: &str, preset: &str) -> bool {
+    Accelerator::parse(preset).is_ok_and(|preset| preset.to_string() == current)
+}
+
+fn register_binding(app: &AppHandle, binding: &Binding) -> Result<(), String> {
+    let shortcut = binding
+        .shortcut
+        .parse::<Shortcut>()
+        .map_err(|e| e.to_string())?;
+    let action = binding.action;
+
+    app.global_shortcut()
+        .on_shortcut(shortcut, move |app, _shortcut, event| {
+            if event.state != ShortcutState::Pressed {
+                return;
+            }
+            if let Some(state) = app.try_state::<AppState>() {
+                run_action(app, &state, action);
+            }
+        })
+        .map_err(|e| format!("Cannot register {}: {}", binding.shortcut, e))
+}
+
+/// Registers every binding, undoing the registrations if one fails.
+fn register_keymap(app: &AppHandle, keymap: &Keymap) -> Result<(), String> {
+    for (index, binding) in keymap.bindings().iter().enumerate() {
+        if let Err(e) = register_binding(app, binding) {
+            for registered in &keymap.bindings()[..index] {
+                unregister_shortcut(app, &registered.shortcut);
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+fn unregister_keymap(app: &AppHandle, keymap: &Keymap) {
+    for binding in keymap.bindings() {
+        unregister_shortcut(app, &binding.shortcut);
+    }
+}
+
+fn run_action(app: &AppHandle, state: &AppState, action: Action) {
+    match action {
+        Action::PastePrevious => {
+            let id = state
+                .history
+                .lock()
+                .unwrap()
+                .entries()
+                .get(1)
+                .map(|entry| entry.id);
+            if let Some(id) = id {
+                paste_entry(app, state, id);
+            }
+        }
+        Action::CycleHistory => {
+            let id = {
+                let history = state.history.lock().unwrap();
+                state.cycle.lock().unwrap().step(history.entries())
+            };
+            if let Some(id) = id {
+                if let Err(e) = copy_entry(app, state, id) {
+                    log::error!("Failed to copy history item: {}", e);
+                }
+            }
+        }
+        Action::PastePlainText => paste_plain_text(state),
+        Action::PasteItem { index } => {
+            if let Some(id) = popup_entry(state, index) {
+                paste_entry(app, state, id);
+            }
+        }
+        Action::PauseCapture => toggle_capture_pause(app, state),
+        Action::ClearHistory => {
+            let diff = state.history.lock().unwrap().clear(true);
+            apply_history_change(app, state, &diff);
+        }
+    }
+}
+
+/// The entry with this 1-based number in the popup, which lists pinned
+/// entries first.
+fn popup_entry(state: &AppState, number: usize) -> Option<EntryId> {
+    let history = state.history.lock().unwrap();
+    let (pinned, unpinned): (Vec<&Entry>, Vec<&Entry>) =
+        history.entries().iter().partition(|entry| entry.pinned);
+    pinned
+        .into_iter()
+        .chain(unpinned)
+        .nth(number.checked_sub(1)?)
+        .map(|entry| entry.id)
+}
+
+/// Pastes an entry into the frontmost app without opening the popup.
+fn paste_entry(app: &AppHandle, state: &AppState, id: EntryId) {
+    let frontmost = get_frontmost_app();
+    let snapshot = clipboard_snapshot(state);
+    match copy_entry(app, state, id) {
+        Ok(pasted) => execute_paste(state, &frontmost, snapshot, pasted),
+        Err(e) => log::error!("Failed to copy history item: {}", e),
+    }
+}
+
+/// Pastes the clipboard without formatting, if it holds text or files.
+fn paste_plain_text(state: &AppState) {
+    let frontmost = get_frontmost_app();
+    let content = match state.clipboard.read() {
+        Ok(content) => content,
+        Err(e) => {
+            log::error!("Failed to read clipboard: {}", e);
+            return;
+        }
+    };
+    let Some(text) = content.plain_text() else {
+        return;
+    };
+
+    let text = state.plain_text.lock().unwrap().apply(&text);
+    let pasted = match state.clipboard.write(&ClipboardContent::text(text)) {
+        Ok(pasted) => pasted,
+        Err(e) => {
+            log::error!("Failed to write clipboard: {}", e);
+            return;
+        }
+    };
+    // The formatted original goes back after the paste
+    let snapshot = (*state.restore_clipboard.lock().unwrap()).then_some(content);
+    execute_paste(state, &frontmost, snapshot, pasted);
+}
+
+fn unregister_shortcut(app: &AppHandle, shortcut_str: &str) {
+    if let Ok(shortcut) = shortcut_str.parse::<Shortcut>() {
+        let _ = app.global_shortcut().unregister(shortcut);
+    }
+}
+
+/// Registers the popup shortcut, given in the canonical form of
+/// [`Accelerator`].
+fn register_shortcut(app: &AppHandle, shortcut_str: &str) -> Result<(), String> {
+    let shortcut = shortcut_str
+        .parse::<Shortcut>()
+        .map_err(|e| e.to_string())?;
+
+    let app_handle = app.clone();
+    app.global_shortcut()
+        .on_shortcut(shortcut, move |_app, _shortcut, event| {
+            if event.state != ShortcutState::Pressed {
+                return;
+            }
+
+            let frontmost = get_frontmost_app();
+            if let Some(state) = _app.try_state::<AppState>() {
+                *state.last_active_app.lock().unwrap() = frontmost;
+                update_clipboard(&app_handle, &state);
+                update_tray_menu(&app_handle, &state);
+            }
+
+            if let Some(window) = _app.get_webview_window("main") {
+                show_popup_at_cursor(&window);
+            }
+        })
+        .map_err(|e| format!("Cannot register {}: {}", shortcut_str, e))
+}
+
+/// Switches the popup shortcut, keeping the old one if the new one is invalid,
+/// taken or cannot be registered. Returns the new shortcut in canonical form.
+fn change_shortcut(
+    app: &AppHandle,
+    state: &AppState,
+    new_shortcut: &str,
+) -> Result<String, String> {
+    let accelerator = Accelerator::parse(new_shortcut).map_err(|e| e.to_string())?;
+    let new_shortcut = accelerator.to_string();
+    let current = state.current_shortcut.lock().unwrap().clone();
+    if new_shortcut == current {
+        return Ok(new_shortcut);
+    }
+
+    let bound = state
+        .keymap
+        .lock()
+        .unwrap()
+        .accelerators()
+        .map_err(|e| e.to_string())?;
+    let in_use: Vec<(&Accelerator, &str)> = bound
+        .iter()
+        .map(|(accelerator, action)| (accelerator, action.as_str()))
+        .collect();
+    accelerator
+        .check_conflicts(&in_use)
+        .map_err(|e| e.to_string())?;
+
+    unregister_shortcut(app, &current);
+    if let Err(e) = register_shortcut(app, &new_shortcut) {
+        if let Err(e) = register_shortcut(app, &current) {
+            log::error!("Failed to restore shortcut: {}", e);
+        }
+        return Err(e);
+    }
+    *state.current_shortcut.lock().unwrap() = new_shortcut.clone();
+
+    // Save to store
+    if let Some(store) = open_store(app) {
+        let _ = store::save_value(&store, store::SHORTCUT_KEY, serde_json::json!(new_shortcut));
+    }
+
+    update_tray_menu(app, state);
+    Ok(new_shortcut)
+}
+
+fn toggle_login_item(app: &AppHandle, state: &AppState) {
+    let mut open_at_login = state.open_at_login.lock().unwrap();
+    *open_at_login = !*open_at_login;
+    let new_value = *open_at_login;
+    drop(open_at_login);
+
+    // Save to store
+    if let Some(store) = open_store(app) {
+        let _ = store::save_value(
+            &store,
+            store::OPEN_AT_LOGIN_KEY,
+            serde_json::json!(new_value),
+        );
     }
 
     // Set login item using AppleScript (works without sandbox)
@@ -640,126 +1440,276 @@ end tell
 "#
     };
 
-    let _ = Command::new("osascript")
-        .arg("-e")
-        .arg(script)
-        .spawn();
+    let _ = Command::new("osascript").arg("-e").arg(script).spawn();
 
     update_tray_menu(app, state);
 }
 
-fn handle_history_click(app: &AppHandle, state: &AppState, index: usize) {
-    let history = state.history.lock().unwrap();
-    if let Some(item) = history.get(index) {
-        let item = item.clone();
-        drop(history);
+/// Puts a history entry back on the clipboard and counts it as used, which
+/// moves it to the top. Returns the change count of the write.
+fn copy_entry(app: &AppHandle, state: &AppState, id: EntryId) -> Result<i64, CommandError> {
+    ensure_unlocked(state)?;
+    let entry = state
+        .history
+        .lock()
+        .unwrap()
+        .find(id)
+        .cloned()
+        .ok_or(CommandError::NotFound(id))?;
+
+    let content = entry.item.to_clipboard(&state.blobs)?;
+    let pasted = state.clipboard.write(&content)?;
+
+    let diff = state.history.lock().unwrap().mark_used(id);
+    history_changed(app, state, &diff);
+    update_tray_menu(app, state);
+    Ok(pasted)
+}
+
+/// Puts only the plain text of a history entry on the clipboard, cleaned up
+/// as configured, and counts the entry as used. Returns the change count of
+/// the write.
+fn copy_entry_plain(app: &AppHandle, state: &AppState, id: EntryId) -> Result<i64, CommandError> {
+    ensure_unlocked(state)?;
+    let entry = state
+        .history
+        .lock()
+        .unwrap()
+        .find(id)
+        .cloned()
+        .ok_or(CommandError::NotFound(id))?;
+
+    let text = entry.item.plain_text().ok_or(CommandError::NoText(id))?;
+    let text = state.plain_text.lock().unwrap().apply(&text);
+    let pasted = state.clipboard.write(&ClipboardContent::text(text))?;
+
+    let diff = state.history.lock().unwrap().mark_used(id);
+    history_changed(app, state, &diff);
+    update_tray_menu(app, state);
+    Ok(pasted)
+}
 
-        let last_app = state.last_active_app.lock().unwrap().clone();
+fn ensure_unlocked(state: &AppState) -> Result<(), CommandError> {
+    if state.protection.lock().unwrap().is_locked() {
+        return Err(CommandError::Locked);
+    }
+    Ok(())
+}
 
-        match &item {
-            HistoryItem::Text(content) => {
-                let clipboard = app.clipboard();
-                let _ = clipboard.write_text(content);
-            }
-            HistoryItem::Image(data_url) => {
-                // Write image to clipboard using native API
-                write_image_to_clipboard(data_url);
+fn handle_history_click(app: &AppHandle, state: &AppState, id: EntryId, plain_text: bool) {
+    let last_app = state.last_active_app.lock().unwrap().clone();
+    let snapshot = clipboard_snapshot(state);
+    let copied = if plain_text {
+        copy_entry_plain(app, state, id)
+    } else {
+        copy_entry(app, state, id)
+    };
+    match copied {
+        Ok(pasted) => execute_paste(state, &last_app, snapshot, pasted),
+        Err(e) => log::error!("Failed to copy history item: {}", e),
+    }
+}
+
+fn load_state_from_store(app: &AppHandle, state: &AppState) {
+    if let Some(store) = open_store(app) {
+        // Load settings
+        let settings = Settings::load(&store);
+        *state.show_tray_icon.lock().unwrap() = settings.show_tray_icon;
+        *state.current_shortcut.lock().unwrap() = settings.shortcut;
+        *state.keymap.lock().unwrap() = settings.keymap;
+        *state.plain_text.lock().unwrap() = settings.plain_text;
+        *state.restore_clipboard.lock().unwrap() = settings.restore_clipboard;
+        *state.open_at_login.lock().unwrap() = settings.open_at_login;
+        *state.paster.lock().unwrap() =
+            create_paster(settings.paste_strategy, settings.paste_profiles);
+        *state.excluded_apps.lock().unwrap() = settings.excluded_apps;
+        *state.sensitive.lock().unwrap() = SensitiveDetector::new_lossy(settings.sensitive_content);
+        *state.persist_pause.lock().unwrap() = settings.persist_pause;
+        *state.pause.lock().unwrap() = CapturePause::new(settings.capture_pause);
+
+        // Encrypted history needs its key before anything is read or written
+        if let Some(config) = &settings.encryption {
+            let protection = match stored_key(app, config) {
+                Ok(Some(cipher)) => Protection::Encrypted(cipher),
+                Ok(None) => Protection::Locked,
+                Err(e) => {
+                    log::error!("Failed to unlock history: {}", e);
+                    Protection::Locked
+                }
+            };
+            set_protection(state, protection);
+        }
+        *state.encryption.lock().unwrap() = settings.encryption;
+
+        // Older versions kept the history in store.json
+        match store::migrate_legacy_history(&store, state.history_store.as_ref()) {
+            Ok(0) => {}
+            Ok(count) => {
+                log::info!(
+                    "Migrated {} history items from {}",
+                    count,
+                    store::STORE_FILE
+                )
             }
+            Err(e) => log::error!("Failed to migrate history: {}", e),
         }
 
-        execute_paste(&last_app);
+        let mut history = state.history.lock().unwrap();
+        history.set_limits(settings.history_limits);
+        history.set_ignore_whitespace(settings.ignore_whitespace_duplicates);
     }
-}
 
-#[cfg(target_os = "macos")]
-fn write_image_to_clipboard(data_url: &str) {
-    // Extract base64 data from data URL
-    if let Some(base64_data) = data_url.strip_prefix("data:image/png;base64,") {
-        if let Ok(bytes) = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, base64_data) {
-            unsafe {
-                let pool = NSAutoreleasePool::new(nil);
-                let pasteboard: id = msg_send![objc::class!(NSPasteboard), generalPasteboard];
-                let _: () = msg_send![pasteboard, clearContents];
-
-                let data: id = NSData::dataWithBytes_length_(
-                    nil,
-                    bytes.as_ptr() as *const std::ffi::c_void,
-                    bytes.len() as u64,
-                );
+    // A passphrase-protected history loads once unlocked
+    if !state.protection.lock().unwrap().is_locked() {
+        load_history(state);
+        send_history_to_frontend(app, state);
+    }
+}
 
-                let png_type: id = msg_send![objc::class!(NSString), stringWithUTF8String:b"public.png\0".as_ptr()];
-                let _: bool = msg_send![pasteboard, setData:data forType:png_type];
+/// Loads the history, applying the configured limits.
+fn load_history(state: &AppState) {
+    match state.history_store.load() {
+        Ok(mut entries) => {
+            // Older versions stored images inline in the database
+            match blob::import_inline_images(
+                &state.blobs,
+                state.history_store.as_ref(),
+                &mut entries,
+            ) {
+                Ok(0) => {}
+                Ok(count) => log::info!("Moved {} images to the blob store", count),
+                Err(e) => log::error!("Failed to move images to the blob store: {}", e),
+            }
 
-                let _: () = msg_send![pool, drain];
+            match state.blobs.sync_refs(&entries) {
+                Ok(0) => {}
+                Ok(count) => log::info!("Removed {} unreferenced images", count),
+                Err(e) => log::error!("Failed to clean up images: {}", e),
             }
+
+            let mut history = state.history.lock().unwrap();
+            let mut diff = history.replace(entries);
+            // Sensitive items that expired while the app was not running
+            diff.removed.extend(history.expire().removed);
+            drop(history);
+            persist_history(state, &diff);
         }
+        Err(e) => log::error!("Failed to load history: {}", e),
     }
 }
 
-#[cfg(not(target_os = "macos"))]
-fn write_image_to_clipboard(_data_url: &str) {
-    // Not implemented for other platforms
+/// Reads a key that does not need the user, verifying it against `config`.
+/// Returns `None` for passphrase keys.
+fn stored_key(app: &AppHandle, config: &EncryptionConfig) -> Result<Option<Cipher>, String> {
+    let cipher = match config.key_source {
+        KeySource::Passphrase => return Ok(None),
+        KeySource::Keychain => encryption::keychain_key(false),
+        KeySource::KeyFile => encryption::key_file_key(&key_file_path(app)?, false),
+    }
+    .map_err(|e| e.to_string())?;
+
+    config.verify(&cipher).map_err(|e| e.to_string())?;
+    Ok(Some(cipher))
 }
 
-fn load_state_from_store(app: &AppHandle, state: &AppState) {
-    if let Ok(store) = app.store("store.json") {
-        // Load history
-        if let Some(history_value) = store.get("history") {
-            if let Ok(items) = serde_json::from_value::<Vec<serde_json::Value>>(history_value.clone()) {
-                let mut history = state.history.lock().unwrap();
-                history.clear();
-                for item in items {
-                    if let (Some(type_str), Some(content)) = (
-                        item.get("type").and_then(|v| v.as_str()),
-                        item.get("content").and_then(|v| v.as_str()),
-                    ) {
-                        match type_str {
-                            "text" => history.push(HistoryItem::Text(content.to_string())),
-                            "image" => history.push(HistoryItem::Image(content.to_string())),
-                            _ => {}
-                        }
-                    }
-                }
-            }
-        }
+fn key_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_data_dir(app)?.join(encryption::KEY_FILE))
+}
 
-        // Load settings
-        if let Some(show_tray) = store.get("showTrayIcon") {
-            if let Some(show) = show_tray.as_bool() {
-                *state.show_tray_icon.lock().unwrap() = show;
-            }
-        }
+fn set_protection(state: &AppState, protection: Protection) {
+    state.history_store.set_protection(protection.clone());
+    state.blobs.set_protection(protection.clone());
+    *state.protection.lock().unwrap() = protection;
+}
 
-        if let Some(shortcut) = store.get("shortcut") {
-            if let Some(s) = shortcut.as_str() {
-                *state.current_shortcut.lock().unwrap() = s.to_string();
-            }
+fn save_encryption_config(
+    app: &AppHandle,
+    config: Option<&EncryptionConfig>,
+) -> macopy_core::Result<()> {
+    let store = open_store(app).ok_or_else(|| {
+        macopy_core::Error::Store(format!("{} is unavailable", store::STORE_FILE))
+    })?;
+    match config {
+        Some(config) => store::save_value(&store, store::ENCRYPTION_KEY, serde_json::json!(config)),
+        None => {
+            store.delete(store::ENCRYPTION_KEY);
+            store.save()
         }
+    }
+}
 
-        if let Some(login) = store.get("openAtLogin") {
-            if let Some(l) = login.as_bool() {
-                *state.open_at_login.lock().unwrap() = l;
-            }
+/// Puts the images and the history back under `previous` after a failed
+/// rekey. Each store knows which key its data is under at that point.
+fn restore_protection(state: &AppState, previous: Protection) {
+    if let Err(e) = state.history_store.rekey(previous.clone()) {
+        log::error!("Failed to restore history: {}", e);
+    }
+    if let Err(e) = state.blobs.rekey(previous) {
+        log::error!("Failed to restore images: {}", e);
+    }
+}
+
+/// Re-encrypts the images and the history under `protection` and saves
+/// `config`. If any step fails, everything goes back under the previous key
+/// and settings, so the data always matches the saved key.
+fn rekey_history(
+    app: &AppHandle,
+    state: &AppState,
+    protection: Protection,
+    config: Option<EncryptionConfig>,
+) -> Result<(), String> {
+    if state.protection.lock().unwrap().is_locked() {
+        return Err(macopy_core::Error::Locked.to_string());
+    }
+    let previous = state.protection.lock().unwrap().clone();
+    let previous_config = state.encryption.lock().unwrap().clone();
+
+    let result = state
+        .blobs
+        .rekey(protection.clone())
+        .and_then(|()| state.history_store.rekey(protection.clone()))
+        .and_then(|()| save_encryption_config(app, config.as_ref()));
+    if let Err(e) = result {
+        log::error!("Failed to change the history encryption: {}", e);
+        restore_protection(state, previous);
+        if let Err(e) = save_encryption_config(app, previous_config.as_ref()) {
+            log::error!("Failed to restore encryption settings: {}", e);
         }
+        return Err(e.to_string());
     }
+
+    *state.protection.lock().unwrap() = protection;
+    *state.encryption.lock().unwrap() = config;
+    Ok(())
 }
 
 fn start_clipboard_watcher(app: AppHandle, running: Arc<AtomicBool>) {
     thread::spawn(move || {
-        let mut last_count = get_clipboard_change_count();
+        let Some(state) = app.try_state::<AppState>() else {
+            return;
+        };
+        let mut watcher = ClipboardWatcher::new(state.clipboard.clone());
 
         while running.load(Ordering::SeqCst) {
             thread::sleep(Duration::from_millis(CLIPBOARD_POLL_INTERVAL_MS));
 
-            let current_count = get_clipboard_change_count();
-            if current_count != last_count {
-                last_count = current_count;
+            if state.pause.lock().unwrap().tick() {
+                capture_pause_changed(&app, &state);
+            }
 
-                if let Some(state) = app.try_state::<AppState>() {
-                    if update_clipboard(&app, &state) {
-                        update_tray_menu(&app, &state);
-                    }
-                }
+            let polled = with_capture_context(&state, |context| context.poll(&mut watcher));
+            if !polled.diff.is_empty() {
+                history_changed(&app, &state, &polled.diff);
+                update_tray_menu(&app, &state);
+            } else if polled.resumed {
+                // A pause until the next copy just ended
+                capture_pause_changed(&app, &state);
+            }
+
+            let expired = state.history.lock().unwrap().expire();
+            if !expired.is_empty() {
+                history_changed(&app, &state, &expired);
+                update_tray_menu(&app, &state);
             }
         }
     });
@@ -771,9 +1721,14 @@ fn hide_window(window: WebviewWindow) {
 }
 
 #[tauri::command]
-fn paste_from_clipboard(state: State<AppState>) {
+async fn paste_from_clipboard(state: State<'_, AppState>) -> Result<(), String> {
     let app_name = state.last_active_app.lock().unwrap().clone();
-    execute_paste(&app_name);
+    let paster = current_paster(&state).map_err(|e| e.to_string())?;
+
+    tauri::async_runtime::spawn_blocking(move || paster.paste(&app_name))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -788,14 +1743,141 @@ fn update_window_height(window: WebviewWindow, height: f64) {
 }
 
 #[tauri::command]
-fn copy_text(app: AppHandle, text: String) {
-    let clipboard = app.clipboard();
-    let _ = clipboard.write_text(text);
+fn copy_text(state: State<AppState>, text: String) {
+    if let Err(e) = state.clipboard.write(&ClipboardContent::text(text)) {
+        log::error!("Failed to write clipboard: {}", e);
+    }
 }
 
 #[tauri::command]
-fn copy_image(_app: AppHandle, data_url: String) {
-    write_image_to_clipboard(&data_url);
+fn copy_item(app: AppHandle, state: State<AppState>, id: EntryId) -> Result<(), CommandError> {
+    copy_entry(&app, &state, id).map(|_| ())
+}
+
+/// Copies the item and pastes it into the app that was active before the
+/// popup opened. With `plain_text`, only its text is pasted.
+#[tauri::command]
+async fn paste_item(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    id: EntryId,
+    plain_text: Option<bool>,
+) -> Result<(), CommandError> {
+    let snapshot = clipboard_snapshot(&state);
+    let pasted = if plain_text.unwrap_or(false) {
+        copy_entry_plain(&app, &state, id)?
+    } else {
+        copy_entry(&app, &state, id)?
+    };
+
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.hide();
+    }
+
+    let app_name = state.last_active_app.lock().unwrap().clone();
+    let paster = current_paster(&state)?;
+    let restore = state.restore.clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        paste_and_restore(&paster, &restore, &app_name, snapshot, pasted)
+    })
+    .await
+    .map_err(|e| CommandError::Paste(e.to_string()))??;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_history(
+    state: State<AppState>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<serde_json::Value, CommandError> {
+    ensure_unlocked(&state)?;
+    let history = state.history.lock().unwrap();
+    let entries = history.entries();
+    let items: Vec<serde_json::Value> = entries
+        .iter()
+        .skip(offset.unwrap_or(0))
+        .take(limit.unwrap_or(search::DEFAULT_PAGE_SIZE))
+        .map(|entry| entry.to_frontend_format())
+        .collect();
+
+    Ok(serde_json::json!({
+        "items": items,
+        "total": entries.len(),
+    }))
+}
+
+#[tauri::command]
+fn get_item(state: State<AppState>, id: EntryId) -> Result<serde_json::Value, CommandError> {
+    ensure_unlocked(&state)?;
+    state
+        .history
+        .lock()
+        .unwrap()
+        .find(id)
+        .map(|entry| entry.to_frontend_format())
+        .ok_or(CommandError::NotFound(id))
+}
+
+/// Persists a change made through a command and refreshes the tray and the
+/// popup.
+fn apply_history_change(app: &AppHandle, state: &AppState, diff: &HistoryDiff) {
+    if diff.is_empty() {
+        return;
+    }
+    history_changed(app, state, diff);
+    update_tray_menu(app, state);
+}
+
+#[tauri::command]
+fn delete_item(app: AppHandle, state: State<AppState>, id: EntryId) -> Result<(), CommandError> {
+    ensure_unlocked(&state)?;
+    let diff = state.history.lock().unwrap().remove(id);
+    if diff.is_empty() {
+        return Err(CommandError::NotFound(id));
+    }
+
+    apply_history_change(&app, &state, &diff);
+    Ok(())
+}
+
+/// Pinned items are kept unless `include_pinned` is set.
+#[tauri::command]
+fn clear_history(
+    app: AppHandle,
+    state: State<AppState>,
+    include_pinned: Option<bool>,
+) -> Result<(), CommandError> {
+    ensure_unlocked(&state)?;
+    let keep_pinned = !include_pinned.unwrap_or(false);
+    let diff = state.history.lock().unwrap().clear(keep_pinned);
+
+    apply_history_change(&app, &state, &diff);
+    Ok(())
+}
+
+#[tauri::command]
+fn move_item(
+    app: AppHandle,
+    state: State<AppState>,
+    id: EntryId,
+    index: usize,
+) -> Result<(), CommandError> {
+    ensure_unlocked(&state)?;
+    let diff = {
+        let mut history = state.history.lock().unwrap();
+        if history.find(id).is_none() {
+            return Err(CommandError::NotFound(id));
+        }
+        if index >= history.entries().len() {
+            return Err(CommandError::InvalidIndex(index));
+        }
+        history.move_to(id, index)
+    };
+
+    apply_history_change(&app, &state, &diff);
+    Ok(())
 }
 
 #[tauri::command]
@@ -806,9 +1888,12 @@ fn toggle_tray_icon(app: AppHandle, state: State<AppState>) -> bool {
     drop(show);
 
     // Save to store
-    if let Ok(store) = app.store("store.json") {
-        let _ = store.set("showTrayIcon", serde_json::json!(new_value));
-        let _ = store.save();
+    if let Some(store) = open_store(&app) {
+        let _ = store::save_value(
+            &store,
+            store::SHOW_TRAY_ICON_KEY,
+            serde_json::json!(new_value),
+        );
     }
 
     // Actually show/hide the tray icon
@@ -824,6 +1909,308 @@ fn get_tray_icon_state(state: State<AppState>) -> bool {
     *state.show_tray_icon.lock().unwrap()
 }
 
+fn set_pinned(
+    app: &AppHandle,
+    state: &AppState,
+    id: EntryId,
+    pinned: bool,
+) -> Result<(), CommandError> {
+    ensure_unlocked(state)?;
+    let diff = {
+        let mut history = state.history.lock().unwrap();
+        if history.find(id).is_none() {
+            return Err(CommandError::NotFound(id));
+        }
+        if pinned {
+            history.pin(id)
+        } else {
+            history.unpin(id)
+        }
+    };
+
+    apply_history_change(app, state, &diff);
+    Ok(())
+}
+
+#[tauri::command]
+fn pin_item(app: AppHandle, state: State<AppState>, id: EntryId) -> Result<(), CommandError> {
+    set_pinned(&app, &state, id, true)
+}
+
+#[tauri::command]
+fn unpin_item(app: AppHandle, state: State<AppState>, id: EntryId) -> Result<(), CommandError> {
+    set_pinned(&app, &state, id, false)
+}
+
+#[tauri::command]
+fn search_history(
+    state: State<AppState>,
+    query: SearchQuery,
+) -> Result<serde_json::Value, CommandError> {
+    ensure_unlocked(&state)?;
+    let history = state.history.lock().unwrap();
+    let page = search::search(history.entries(), &query)?;
+    Ok(page.to_frontend_format())
+}
+
+#[tauri::command]
+fn get_shortcut(state: State<AppState>) -> String {
+    state.current_shortcut.lock().unwrap().clone()
+}
+
+/// Validates, registers and saves a shortcut recorded in the settings UI.
+#[tauri::command]
+fn set_shortcut(
+    app: AppHandle,
+    state: State<AppState>,
+    shortcut: String,
+) -> Result<String, String> {
+    change_shortcut(&app, &state, &shortcut)
+}
+
+#[tauri::command]
+fn get_keymap(state: State<AppState>) -> Keymap {
+    state.keymap.lock().unwrap().clone()
+}
+
+/// Replaces every binding, keeping the old keymap if a shortcut is invalid,
+/// taken or cannot be registered.
+#[tauri::command]
+fn set_keymap(
+    app: AppHandle,
+    state: State<AppState>,
+    bindings: Vec<Binding>,
+) -> Result<Keymap, String> {
+    let popup_shortcut =
+        Accelerator::parse(&state.current_shortcut.lock().unwrap()).map_err(|e| e.to_string())?;
+    let keymap = Keymap::new(bindings, &popup_shortcut).map_err(|e| e.to_string())?;
+    change_keymap(&app, &state, keymap.clone())?;
+    Ok(keymap)
+}
+
+#[tauri::command]
+fn get_plain_text_options(state: State<AppState>) -> PlainTextOptions {
+    *state.plain_text.lock().unwrap()
+}
+
+#[tauri::command]
+fn set_plain_text_options(app: AppHandle, state: State<AppState>, options: PlainTextOptions) {
+    change_plain_text_options(&app, &state, options);
+}
+
+#[tauri::command]
+fn get_restore_clipboard(state: State<AppState>) -> bool {
+    *state.restore_clipboard.lock().unwrap()
+}
+
+#[tauri::command]
+fn set_restore_clipboard(app: AppHandle, state: State<AppState>, enabled: bool) {
+    change_restore_clipboard(&app, &state, enabled);
+}
+
+#[tauri::command]
+fn get_history_limits(state: State<AppState>) -> HistoryLimits {
+    state.history.lock().unwrap().limits()
+}
+
+#[tauri::command]
+fn set_history_limits(app: AppHandle, state: State<AppState>, limits: HistoryLimits) {
+    let diff = state.history.lock().unwrap().set_limits(limits);
+
+    // Save to store
+    if let Some(store) = open_store(&app) {
+        let _ = store::save_value(&store, store::HISTORY_LIMITS_KEY, serde_json::json!(limits));
+    }
+
+    if !diff.is_empty() {
+        history_changed(&app, &state, &diff);
+        update_tray_menu(&app, &state);
+    }
+}
+
+#[tauri::command]
+fn get_ignore_whitespace_duplicates(state: State<AppState>) -> bool {
+    state.history.lock().unwrap().ignores_whitespace()
+}
+
+#[tauri::command]
+fn set_ignore_whitespace_duplicates(app: AppHandle, state: State<AppState>, enabled: bool) {
+    let diff = state.history.lock().unwrap().set_ignore_whitespace(enabled);
+
+    // Save to store
+    if let Some(store) = open_store(&app) {
+        let _ = store::save_value(
+            &store,
+            store::IGNORE_WHITESPACE_DUPLICATES_KEY,
+            serde_json::json!(enabled),
+        );
+    }
+
+    if !diff.is_empty() {
+        history_changed(&app, &state, &diff);
+        update_tray_menu(&app, &state);
+    }
+}
+
+/// Applies `change` and, if it reports a change, saves the list.
+fn update_excluded_apps(
+    app: &AppHandle,
+    state: &AppState,
+    change: impl FnOnce(&mut ExcludedApps) -> bool,
+) -> Vec<String> {
+    let mut excluded_apps = state.excluded_apps.lock().unwrap();
+    if !change(&mut excluded_apps) {
+        return excluded_apps.apps().to_vec();
+    }
+    let apps = excluded_apps.apps().to_vec();
+    drop(excluded_apps);
+
+    // Save to store
+    if let Some(store) = open_store(app) {
+        let _ = store::save_value(&store, store::EXCLUDED_APPS_KEY, serde_json::json!(apps));
+    }
+
+    update_tray_menu(app, state);
+    apps
+}
+
+#[tauri::command]
+fn get_excluded_apps(state: State<AppState>) -> Vec<String> {
+    state.excluded_apps.lock().unwrap().apps().to_vec()
+}
+
+#[tauri::command]
+fn add_excluded_app(app: AppHandle, state: State<AppState>, name: String) -> Vec<String> {
+    update_excluded_apps(&app, &state, |apps| apps.add(&name))
+}
+
+#[tauri::command]
+fn remove_excluded_app(app: AppHandle, state: State<AppState>, name: String) -> Vec<String> {
+    update_excluded_apps(&app, &state, |apps| apps.remove(&name))
+}
+
+#[tauri::command]
+fn get_sensitive_settings(state: State<AppState>) -> SensitiveSettings {
+    state.sensitive.lock().unwrap().settings().clone()
+}
+
+#[tauri::command]
+fn set_sensitive_settings(
+    app: AppHandle,
+    state: State<AppState>,
+    settings: SensitiveSettings,
+) -> Result<(), String> {
+    let detector = SensitiveDetector::new(settings.clone()).map_err(|e| e.to_string())?;
+    *state.sensitive.lock().unwrap() = detector;
+
+    // Save to store
+    if let Some(store) = open_store(&app) {
+        let _ = store::save_value(
+            &store,
+            store::SENSITIVE_CONTENT_KEY,
+            serde_json::json!(settings),
+        );
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn get_capture_pause(state: State<AppState>) -> Option<Pause> {
+    state.pause.lock().unwrap().current()
+}
+
+#[tauri::command]
+fn pause_capture(app: AppHandle, state: State<AppState>, pause: Pause) {
+    set_capture_pause(&app, &state, Some(pause));
+}
+
+#[tauri::command]
+fn resume_capture(app: AppHandle, state: State<AppState>) {
+    set_capture_pause(&app, &state, None);
+}
+
+#[tauri::command]
+fn get_encryption_status(state: State<AppState>) -> serde_json::Value {
+    let key_source = state
+        .encryption
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|config| config.key_source);
+    serde_json::json!({
+        "enabled": key_source.is_some(),
+        "keySource": key_source,
+        "locked": state.protection.lock().unwrap().is_locked(),
+    })
+}
+
+#[tauri::command]
+fn unlock_history(
+    app: AppHandle,
+    state: State<AppState>,
+    passphrase: String,
+) -> Result<(), String> {
+    if !state.protection.lock().unwrap().is_locked() {
+        return Ok(());
+    }
+    let config = state
+        .encryption
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| "The history is not encrypted".to_string())?;
+
+    let cipher = match config.key_source {
+        KeySource::Passphrase => config
+            .unlock_with_passphrase(&passphrase)
+            .map_err(|e| e.to_string())?,
+        _ => stored_key(&app, &config)?.ok_or_else(|| "No key available".to_string())?,
+    };
+    set_protection(&state, Protection::Encrypted(cipher));
+
+    load_history(&state);
+    send_history_to_frontend(&app, &state);
+    update_tray_menu(&app, &state);
+    Ok(())
+}
+
+/// Encrypts the history with a new key, or moves it to a new key if it is
+/// already encrypted.
+#[tauri::command]
+fn enable_encryption(
+    app: AppHandle,
+    state: State<AppState>,
+    key_source: KeySource,
+    passphrase: Option<String>,
+) -> Result<(), String> {
+    let (cipher, config) = match key_source {
+        KeySource::Passphrase => {
+            let passphrase = passphrase
+                .filter(|passphrase| !passphrase.is_empty())
+                .ok_or_else(|| "A passphrase is required".to_string())?;
+            encryption::create_passphrase_key(&passphrase)
+        }
+        KeySource::Keychain => encryption::keychain_key(true).and_then(|cipher| {
+            let config = EncryptionConfig::new(key_source, &cipher, None)?;
+            Ok((cipher, config))
+        }),
+        KeySource::KeyFile => {
+            encryption::key_file_key(&key_file_path(&app)?, true).and_then(|cipher| {
+                let config = EncryptionConfig::new(key_source, &cipher, None)?;
+                Ok((cipher, config))
+            })
+        }
+    }
+    .map_err(|e| e.to_string())?;
+
+    rekey_history(&app, &state, Protection::Encrypted(cipher), Some(config))
+}
+
+#[tauri::command]
+fn disable_encryption(app: AppHandle, state: State<AppState>) -> Result<(), String> {
+    rekey_history(&app, &state, Protection::Plaintext, None)
+}
+
 #[tauri::command]
 fn quit_app(app: AppHandle) {
     app.exit(0);
@@ -831,7 +2218,6 @@ fn quit_app(app: AppHandle) {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let state = AppState::default();
     let watcher_running = Arc::new(AtomicBool::new(true));
     let watcher_running_clone = watcher_running.clone();
 
@@ -847,18 +2233,64 @@ pub fn run() {
                 let _ = window.set_focus();
             }
         }))
-        .manage(state)
+        .register_asynchronous_uri_scheme_protocol(
+            protocol::URI_SCHEME,
+            |ctx, request, responder| {
+                let app = ctx.app_handle().clone();
+                let path = request.uri().path().to_string();
+                // Decoding and resizing large screenshots should not block the webview
+                thread::spawn(move || responder.respond(serve_resource(&app, &path)));
+            },
+        )
         .invoke_handler(tauri::generate_handler![
             hide_window,
             paste_from_clipboard,
             update_window_height,
             copy_text,
-            copy_image,
+            copy_item,
+            paste_item,
+            get_history,
+            get_item,
+            delete_item,
+            clear_history,
+            move_item,
             toggle_tray_icon,
             get_tray_icon_state,
+            get_shortcut,
+            set_shortcut,
+            get_keymap,
+            set_keymap,
+            get_plain_text_options,
+            set_plain_text_options,
+            get_restore_clipboard,
+            set_restore_clipboard,
+            get_history_limits,
+            set_history_limits,
+            pin_item,
+            unpin_item,
+            search_history,
+            get_excluded_apps,
+            add_excluded_app,
+            remove_excluded_app,
+            get_ignore_whitespace_duplicates,
+            set_ignore_whitespace_duplicates,
+            get_sensitive_settings,
+            set_sensitive_settings,
+            get_capture_pause,
+            pause_capture,
+            resume_capture,
+            get_encryption_status,
+            unlock_history,
+            enable_encryption,
+            disable_encryption,
             quit_app,
         ])
         .setup(move |app| {
+            let clipboard = create_clipboard_backend(app.handle());
+            let history_store = open_history_store(app.handle());
+            let blobs = open_blob_store(app.handle());
+            app.manage(AppState::new(clipboard, history_store, blobs));
+
             // Load state from store
             if let Some(state) = app.try_state::<AppState>() {
                 load_state_from_store(app.handle(), &state);
@@ -879,54 +2311,136 @@ pub fn run() {
             };
 
             // Load tray icon from embedded PNG
-            let png_data = include_bytes!("../icons/trayTemplate.png");
-            let img = image::load_from_memory(png_data).expect("Failed to decode tray icon");
-            let rgba = img.to_rgba8();
-            let (width, height) = rgba.dimensions();
-            let tray_icon_image = Image::new_owned(rgba.into_raw(), width, height);
+            let paused = app
+                .try_state::<AppState>()
+                .is_some_and(|state| state.pause.lock().unwrap().is_paused());
 
             let tray = TrayIconBuilder::new()
                 .menu(&menu)
-                .icon(tray_icon_image)
+                .icon(tray_icon_image(paused))
                 .icon_as_template(true)
+                .tooltip(tray_tooltip(paused))
                 .on_menu_event(move |app, event| {
                     let event_id = event.id.as_ref();
 
                     if event_id == "quit" {
                         app.exit(0);
                     } else if event_id.starts_with("history_") {
-                        if let Ok(index) = event_id.replace("history_", "").parse::<usize>() {
+                        if let Ok(id) = event_id.replace("history_", "").parse::<EntryId>() {
                             if let Some(state) = app.try_state::<AppState>() {
-                                handle_history_click(app, &state, index);
+                                handle_history_click(app, &state, id, false);
                             }
                         }
+                    } else if event_id.starts_with("plain_history_") {
+                        if let Ok(id) = event_id.replace("plain_history_", "").parse::<EntryId>() {
+                            if let Some(state) = app.try_state::<AppState>() {
+                                handle_history_click(app, &state, id, true);
+                            }
+                        }
+                    } else if event_id.starts_with("plain_option_") {
+                        if let Some(state) = app.try_state::<AppState>() {
+                            toggle_plain_text_option(app, &state, event_id);
+                        }
                     } else if event_id == "shortcut_alt_v" {
                         if let Some(state) = app.try_state::<AppState>() {
-                            change_shortcut(app, &state, "Alt+V");
+                            if let Err(e) = change_shortcut(app, &state, "Alt+V") {
+                                log::error!("Failed to change shortcut: {}", e);
+                            }
                         }
                     } else if event_id == "shortcut_cmd_shift_v" {
                         if let Some(state) = app.try_state::<AppState>() {
-                            change_shortcut(app, &state, "CommandOrControl+Shift+V");
+                            if let Err(e) = change_shortcut(app, &state, "CommandOrControl+Shift+V")
+                            {
+                                log::error!("Failed to change shortcut: {}", e);
+                            }
                         }
                     } else if event_id == "shortcut_ctrl_alt_v" {
                         if let Some(state) = app.try_state::<AppState>() {
-                            change_shortcut(app, &state, "Control+Alt+V");
+                            if let Err(e) = change_shortcut(app, &state, "Control+Alt+V") {
+                                log::error!("Failed to change shortcut: {}", e);
+                            }
+                        }
+                    } else if event_id == "toggle_restore_clipboard" {
+                        if let Some(state) = app.try_state::<AppState>() {
+                            let enabled = !*state.restore_clipboard.lock().unwrap();
+                            change_restore_clipboard(app, &state, enabled);
+                        }
+                    } else if event_id == "toggle_item_shortcuts" {
+                        if let Some(state) = app.try_state::<AppState>() {
+                            if let Err(e) = toggle_item_shortcuts(app, &state) {
+                                log::error!("Failed to change item shortcuts: {}", e);
+                            }
                         }
                     } else if event_id == "toggle_login" {
                         if let Some(state) = app.try_state::<AppState>() {
                             toggle_login_item(app, &state);
                         }
+                    } else if event_id == "pause_indefinite" {
+                        if let Some(state) = app.try_state::<AppState>() {
+                            set_capture_pause(app, &state, Some(Pause::Indefinite));
+                        }
+                    } else if event_id == "pause_timed" {
+                        if let Some(state) = app.try_state::<AppState>() {
+                            let duration = Duration::from_secs(PAUSE_FOR_MINUTES * 60);
+                            set_capture_pause(app, &state, Some(Pause::for_duration(duration)));
+                        }
+                    } else if event_id == "pause_next_copy" {
+                        if let Some(state) = app.try_state::<AppState>() {
+                            set_capture_pause(app, &state, Some(Pause::NextCopy));
+                        }
+                    } else if event_id == "pause_resume" {
+                        if let Some(state) = app.try_state::<AppState>() {
+                            set_capture_pause(app, &state, None);
+                        }
+                    } else if event_id == "pause_persist" {
+                        if let Some(state) = app.try_state::<AppState>() {
+                            toggle_persist_pause(app, &state);
+                        }
+                    } else if event_id == "exclude_latest_app" {
+                        if let Some(state) = app.try_state::<AppState>() {
+                            let latest_app = state
+                                .history
+                                .lock()
+                                .unwrap()
+                                .entries()
+                                .first()
+                                .and_then(|entry| entry.source_app.clone());
+                            if let Some(latest_app) = latest_app {
+                                update_excluded_apps(app, &state, |apps| apps.add(&latest_app));
+                            }
+                        }
+                    } else if event_id.starts_with("excluded_app_") {
+                        if let Ok(index) = event_id.replace("excluded_app_", "").parse::<usize>() {
+                            if let Some(state) = app.try_state::<AppState>() {
+                                let excluded_app = state
+                                    .excluded_apps
+                                    .lock()
+                                    .unwrap()
+                                    .apps()
+                                    .get(index)
+                                    .cloned();
+                                if let Some(excluded_app) = excluded_app {
+                                    update_excluded_apps(app, &state, |apps| {
+                                        apps.remove(&excluded_app)
+                                    });
+                                }
+                            }
+                        }
                     }
                 })
                 .on_tray_icon_event(|tray, event| {
-                    if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+                    if let TrayIconEvent::Click {
+                        button: MouseButton::Left,
+                        button_state: MouseButtonState::Up,
+                        ..
+                    } = event
+                    {
                         let app = tray.app_handle();
                         if let Some(window) = app.get_webview_window("main") {
                             if let Some(state) = app.try_state::<AppState>() {
                                 let frontmost = get_frontmost_app();
                                 *state.last_active_app.lock().unwrap() = frontmost;
                                 update_clipboard(app, &state);
-                                send_history_to_frontend(app, &state);
                                 update_tray_menu(app, &state);
                             }
                             show_popup_at_cursor(&window);
@@ -954,7 +2468,16 @@ pub fn run() {
                     "Alt+V".to_string()
                 }
             };
-            register_shortcut(app.handle(), &shortcut);
+            if let Err(e) = register_shortcut(app.handle(), &shortcut) {
+                log::error!("Failed to register shortcut: {}", e);
+            }
+            if let Some(state) = app.try_state::<AppState>() {
+                for binding in state.keymap.lock().unwrap().bindings() {
+                    if let Err(e) = register_binding(app.handle(), binding) {
+                        log::error!("Failed to register shortcut: {}", e);
+                    }
+                }
+            }
 
             // Setup window blur handler
             if let Some(window) = app.get_webview_window("main") {